@@ -1,16 +1,34 @@
 use moka::future::Cache;
-use reqwest::{Client, Error, StatusCode};
+use moka::Expiry;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use reqwest::header::CACHE_CONTROL;
+use reqwest::{Client, Proxy, StatusCode};
 use rocket::http::uri::Absolute;
 use rocket::http::Status;
 use rocket::request::{FromRequest, Outcome, Request};
 use rocket::serde::{Deserialize, Serialize};
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::convert::Infallible;
+use std::error::Error;
+use std::fmt;
 use std::hash::Hash;
+use std::net::{IpAddr, SocketAddr};
 
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use crate::admin::Admin;
+use crate::dns::CachingResolver;
+use crate::events::{Event, Events};
+use crate::registry::ModelRegistry;
+use crate::referer;
+use crate::shared::SharedAccess;
+use crate::token;
+use crate::tenant::Tenant;
 use crate::Config;
 use crate::Model;
 
@@ -18,27 +36,147 @@ use crate::Model;
 /// TODO: write docs
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub struct AccessConfig {
+    pub mode: AuthMode, // `disabled` grants access to everyone, development only
     pub server: Absolute<'static>,
     pub cache_ttl: u64, // cache entry Time To Live
     pub cache_tti: u64, // cache entry Time To Idle (from last request)
     pub cookie_name: Cow<'static, str>,
+    pub stat_scope: Option<String>, // auth server scope to read statistics, admin only if not set
+    pub redis: Option<String>, // redis url to share decisions between replicas, e.g. `redis://127.0.0.1/`
+    pub refresh_ahead: u64, // re-check decisions requested this many seconds before TTL, 0 disables
+    pub soft_fail: SoftFail, // decision when the auth server is unreachable
+    pub budget: u64, // answer by `budget_policy` if the auth check takes longer, ms, 0 disables
+    pub budget_policy: SoftFail, // decision while the slow check completes in background
+    pub preauth: bool, // on root tileset grant, check object access and pre-authorize its models
+    pub client: ClientConfig, // auth server HTTP client
+    pub vary: Vec<String>, // `Vary` headers of access checked responses, session sources
 }
 
+/// Auth server HTTP client settings
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct ClientConfig {
+    pub pool_max_idle: usize,        // idle connections kept per host
+    pub pool_idle_timeout: u64,      // seconds to keep idle connections
+    pub tcp_keepalive: Option<u64>,  // TCP keep-alive interval, seconds
+    pub http2: bool,                 // use HTTP/2 without negotiation
+    pub proxy: Option<String>,       // proxy url, `HTTP(S)_PROXY` variables are used if not set
+    pub no_proxy: bool,              // ignore proxy environment variables
+    pub resolve: HashMap<String, Vec<IpAddr>>, // pinned addresses by host name
+    pub dns_cache: u64,              // seconds to cache resolved addresses, 0 disables
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        ClientConfig {
+            pool_max_idle: 32,
+            pool_idle_timeout: 90,   // reqwest default
+            tcp_keepalive: Some(60), // 1 minute
+            http2: false,
+            proxy: None,
+            no_proxy: false,
+            resolve: HashMap::new(),
+            dns_cache: 60,           // 1 minute
+        }
+    }
+}
+
+impl ClientConfig {
+    /// Build client with the request timeout
+    pub fn build(&self, timeout: Duration) -> reqwest::Result<Client> {
+        let mut builder = Client::builder()
+            .timeout(timeout)
+            .pool_max_idle_per_host(self.pool_max_idle)
+            .pool_idle_timeout(Duration::from_secs(self.pool_idle_timeout))
+            .tcp_keepalive(self.tcp_keepalive.map(Duration::from_secs));
+        if self.http2 {
+            builder = builder.http2_prior_knowledge();
+        }
+        if self.no_proxy {
+            builder = builder.no_proxy();
+        }
+        if let Some(ref url) = self.proxy {
+            builder = builder.proxy(Proxy::all(url)?);
+        }
+        if self.dns_cache > 0 {
+            let ttl = Duration::from_secs(self.dns_cache);
+            builder = builder.dns_resolver(Arc::new(CachingResolver::new(ttl)));
+        }
+        for (host, ips) in &self.resolve {
+            // the port is taken from the url
+            let addrs: Vec<SocketAddr> = ips.iter().map(|ip| SocketAddr::new(*ip, 0)).collect();
+            builder = builder.resolve_to_addrs(host, &addrs);
+        }
+        builder.build()
+    }
+}
+
+/// Access policy for auth server transport errors
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SoftFail {
+    #[default]
+    Off,   // deny access
+    Grant, // grant access to everyone
+    Known, // grant access to sessions granted before
+}
+
+/// Model access checking mode
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthMode {
+    #[default]
+    Server,   // check sessions with the auth server
+    Disabled, // grant access to everyone without the auth server
+}
+
+// soft-fail decisions are cached for a short time
+const SOFT_FAIL_TTL: Duration = Duration::from_secs(30);
+
+// granted sessions are remembered for soft-fail
+const KNOWN_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
 impl Default for AccessConfig {
     fn default() -> Self {
         AccessConfig {
+            mode: AuthMode::Server,
             server: uri!("http://127.0.0.1:8888"),
             cache_ttl: 30 * 60, // 30 minutes
             cache_tti: 5 * 60,  // 5 minutes
             cookie_name: Cow::from("PHPSESSID"),
+            stat_scope: None,
+            redis: None,
+            refresh_ahead: 0,
+            soft_fail: SoftFail::Off,
+            budget: 0,
+            budget_policy: SoftFail::Known,
+            preauth: false,
+            client: ClientConfig::default(),
+            vary: vec!["Cookie".to_owned(), "Authorization".to_owned()],
         }
     }
 }
 
 /// User session identifier
-#[derive(Debug, Hash, PartialEq, Eq, Clone)]
+#[derive(Hash, PartialEq, Eq, Clone)]
 pub struct SessionId(Option<String>);
 
+/// Short hash of a secret to correlate log records without the secret
+pub fn secret_tag(secret: &str) -> String {
+    let hash = Sha256::digest(secret);
+    hash[..4].iter().map(|x| format!("{:02x}", x)).collect()
+}
+
+/// Session secrets are not logged, only a short hash to correlate records
+impl fmt::Debug for SessionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(ref id) => write!(f, "SessionId({})", secret_tag(id)),
+            None => f.write_str("SessionId(None)"),
+        }
+    }
+}
+
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for SessionId {
     type Error = Infallible;
@@ -47,11 +185,18 @@ impl<'r> FromRequest<'r> for SessionId {
         // get typed config from rocket managed state
         let config = req.rocket().state::<Config<'_>>().unwrap();
 
-        // get session id cookie from request
+        // get session id cookie from request, fallback to `access_token`
+        // query param (used by Cesium ion clients), bearer tokens are not
+        // sessions, the admin token is never sent to the auth server
         let id_option = req
             .cookies()
             .get(&config.access.cookie_name)
-            .map(|x| String::from(x.value()));
+            .map(|x| String::from(x.value()))
+            .or_else(|| {
+                req.query_value::<String>("access_token")
+                    .and_then(|x| x.ok())
+            })
+            .filter(|x| config.admin.token.as_ref() != Some(x));
 
         Outcome::Success(SessionId(id_option))
     }
@@ -64,6 +209,13 @@ impl From<&str> for SessionId {
     }
 }
 
+impl SessionId {
+    /// Session identifier value
+    pub fn value(&self) -> Option<&str> {
+        self.0.as_deref()
+    }
+}
+
 /// Model access mode
 #[derive(Debug, Clone, PartialEq)]
 pub enum AccessMode {
@@ -76,70 +228,530 @@ pub enum AccessMode {
 pub struct AccessKey {
     pub model: Arc<Model>,
     session_id: SessionId,
+    scope: Option<String>,    // extra permission requested from auth server
+    server: Option<Arc<str>>, // auth server of the virtual host
+}
+
+impl AccessKey {
+    pub fn new(model: Arc<Model>, session_id: SessionId) -> Self {
+        AccessKey {
+            model,
+            session_id,
+            scope: None,
+            server: None,
+        }
+    }
+
+    /// Same key checked by the tenant auth server
+    pub fn for_tenant(self, tenant: &Tenant) -> Self {
+        AccessKey {
+            server: tenant.server.clone(),
+            ..self
+        }
+    }
+
+    /// Same key requesting extra permission scope
+    pub fn with_scope(self, scope: &str) -> Self {
+        AccessKey {
+            scope: Some(scope.to_owned()),
+            ..self
+        }
+    }
 }
 
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for AccessKey {
     type Error = ();
 
+    #[tracing::instrument(name = "access_guard", skip_all)]
     async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
-        let access_key = AccessKey {
-            model: Arc::new(req.guard::<Model>().await.unwrap()),
-            session_id: req.guard::<SessionId>().await.unwrap(),
+        // malformed names are rejected before any disk or auth work
+        let model = match req.guard::<Model>().await {
+            Outcome::Success(model) => Arc::new(model),
+            _ => return Outcome::Failure((Status::BadRequest, ())),
+        };
+        let tenant = match req.guard::<&Tenant>().await {
+            Outcome::Success(tenant) => tenant,
+            _ => return Outcome::Forward(()),
         };
+        let access_key =
+            AccessKey::new(model, req.guard::<SessionId>().await.unwrap()).for_tenant(tenant);
+
+        // hotlink protection, checked before the auth server call
+        let config = req.rocket().state::<Config<'_>>().unwrap();
+        let referer = req.headers().get_one("Referer");
+        if !referer::allowed(config.model(&access_key.model), referer) {
+            debug!("referer {:?} not allowed for {:?}", referer, &access_key.model);
+            return Outcome::Failure((Status::Forbidden, ()));
+        }
+
+        // signed token or share link grants access without the auth server call
+        if let Some(ref secret) = config.token.secret {
+            let cookie = req.cookies().get(&config.token.cookie_name);
+            let share = req.query_value::<&str>("share").and_then(|x| x.ok());
+            let tokens = cookie.map(|x| x.value()).into_iter().chain(share);
+            for value in tokens {
+                if token::verify(secret, value, &access_key.model, token::now()) {
+                    return Outcome::Success(access_key);
+                }
+            }
+        }
 
         let model_access = req.rocket().state::<ModelAccess>().unwrap();
+        let budget = config.model(&access_key.model).auth_budget.unwrap_or(config.access.budget);
+
+        match model_access.check_within(&access_key, Duration::from_millis(budget)).await {
+            AccessMode::Granted => {
+                if config.access.preauth && is_root_tileset(req, config, &access_key.model) {
+                    let registry = req.rocket().state::<ModelRegistry>().cloned();
+                    let access = model_access.clone();
+                    let (key, root) = (access_key.clone(), tenant.root.clone());
+                    tokio::spawn(async move {
+                        let models = match registry {
+                            Some(registry) => registry.models(&root).await.unwrap_or_default(),
+                            None => Default::default(),
+                        };
+                        let names = models
+                            .iter()
+                            .filter(|x| key.model.object.as_deref() == Some(x.object.as_str()))
+                            .map(|x| x.model.clone())
+                            .collect();
+                        access.preauthorize(&key, names).await;
+                    });
+                }
+                Outcome::Success(access_key)
+            }
+            AccessMode::Denied => Outcome::Failure((Status::Forbidden, ())),
+        }
+    }
+}
+
+/// Is the request for the model directory or its index file?
+fn is_root_tileset(req: &Request<'_>, config: &Config<'_>, model: &Model) -> bool {
+    match req.uri().path().segments().last() {
+        Some(name) => {
+            model.name.as_deref() == Some(name) || config.index(model).iter().any(|x| x == name)
+        }
+        None => false,
+    }
+}
+
+/// Statistics access guard, requires admin token or the stat scope
+#[derive(Debug)]
+pub struct StatAccess {
+    pub model: Arc<Model>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for StatAccess {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let model = match req.guard::<Model>().await {
+            Outcome::Success(model) => Arc::new(model),
+            _ => return Outcome::Failure((Status::BadRequest, ())),
+        };
+        if req.guard::<Admin>().await.is_success() {
+            return Outcome::Success(StatAccess { model });
+        }
 
-        match model_access.check(&access_key).await {
-            AccessMode::Granted => Outcome::Success(access_key),
+        let config = req.rocket().state::<Config<'_>>().unwrap();
+        let scope = match config.access.stat_scope {
+            Some(ref scope) => scope,
+            None => return Outcome::Failure((Status::Forbidden, ())),
+        };
+        let session_id = req.guard::<SessionId>().await.unwrap();
+        let mut key = AccessKey::new(model, session_id).with_scope(scope);
+        if let Outcome::Success(tenant) = req.guard::<&Tenant>().await {
+            key = key.for_tenant(tenant);
+        }
+
+        let model_access = req.rocket().state::<ModelAccess>().unwrap();
+        match model_access.check(&key).await {
+            AccessMode::Granted => Outcome::Success(StatAccess { model: key.model }),
             AccessMode::Denied => Outcome::Failure((Status::Forbidden, ())),
         }
     }
 }
 
+/// Access cache and auth server counters
+#[derive(Debug, Default)]
+struct AccessCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,     // expired or evicted by size, not invalidated
+    auth_requests: AtomicU64,
+    auth_errors: AtomicU64,   // transport errors
+    auth_time_us: AtomicU64,  // cumulative auth server response time
+    soft_grants: AtomicU64,   // granted by soft-fail policy
+    over_budget: AtomicU64,   // answered by budget policy
+}
+
+/// Access cache and auth server statistics
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct AccessStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub inserts: u64,
+    pub evictions: u64,
+    pub entries: u64,
+    pub auth_requests: u64,
+    pub auth_errors: u64,
+    pub auth_time_us: u64,
+    pub auth_latency_ms: f64, // average auth server response time
+    pub soft_grants: u64,     // granted on auth server errors
+    pub over_budget: u64,     // answered by budget policy before the auth server
+}
+
+/// Cached access decision
+#[derive(Debug, Clone)]
+struct Decision {
+    mode: AccessMode,
+    checked: Instant,             // auth server request time
+    refreshing: Arc<AtomicBool>,  // refresh-ahead task is started
+    soft: bool,                   // granted by soft-fail policy
+    ttl: Option<Duration>,        // overrides `cache_ttl`
+}
+
+impl Decision {
+    fn new(mode: AccessMode) -> Self {
+        Decision {
+            mode,
+            checked: Instant::now(),
+            refreshing: Arc::new(AtomicBool::new(false)),
+            soft: false,
+            ttl: None,
+        }
+    }
+
+    fn soft() -> Self {
+        Decision {
+            soft: true,
+            ttl: Some(SOFT_FAIL_TTL),
+            ..Decision::new(AccessMode::Granted)
+        }
+    }
+}
+
+/// Decision TTL from the auth server `Cache-Control` header
+fn cache_control_ttl(value: Option<&str>) -> Option<Duration> {
+    let mut ttl = None;
+    for directive in value?.split(',').map(str::trim) {
+        if directive.eq_ignore_ascii_case("no-store") || directive.eq_ignore_ascii_case("no-cache")
+        {
+            return Some(Duration::ZERO);
+        }
+        if let Some(secs) = directive.strip_prefix("max-age=") {
+            ttl = secs.trim_matches('"').parse().ok().map(Duration::from_secs);
+        }
+    }
+    ttl
+}
+
+/// Per-entry expiration, global TTL unless the decision sets own
+struct DecisionExpiry {
+    ttl: Duration,
+}
+
+impl Expiry<AccessKey, Decision> for DecisionExpiry {
+    fn expire_after_create(&self, _: &AccessKey, value: &Decision, _: Instant) -> Option<Duration> {
+        Some(value.ttl.unwrap_or(self.ttl))
+    }
+
+    fn expire_after_update(
+        &self,
+        key: &AccessKey,
+        value: &Decision,
+        updated_at: Instant,
+        _: Option<Duration>,
+    ) -> Option<Duration> {
+        self.expire_after_create(key, value, updated_at)
+    }
+}
+
 /// Model Access resolver
+#[derive(Clone)]
 pub struct ModelAccess {
-    cache: Cache<AccessKey, AccessMode>,
+    cache: Cache<AccessKey, Decision>,
     client: Client,
     config: AccessConfig,
+    events: Events,
+    shared: Option<SharedAccess>, // L2 cache shared by replicas
+    counters: Arc<AccessCounters>,
+    known: Option<Cache<AccessKey, ()>>, // granted keys for soft-fail and budget policies
+}
+
+/// Encode session id for the shared cache key
+fn encode_session(session: Option<&str>) -> String {
+    URL_SAFE_NO_PAD.encode(session.unwrap_or_default())
+}
+
+/// Drop cached decisions of the session
+fn invalidate_session(cache: &Cache<AccessKey, Decision>, session: String) {
+    let res = cache.invalidate_entries_if(move |key, _| key.session_id.value() == Some(&session));
+    if let Err(err) = res {
+        error!("failed to invalidate session access: {}", err);
+    }
 }
 
 impl ModelAccess {
-    pub fn new(config: &AccessConfig) -> Result<Self, Error> {
+    pub fn new(config: &AccessConfig, events: Events) -> Result<Self, Box<dyn Error>> {
+        let counters = Arc::new(AccessCounters::default());
+        let evicted = Arc::clone(&counters);
         let cache = Cache::builder()
             // Max 100,000 entries
             .max_capacity(100_000)
-            // Max TTL for items
-            .time_to_live(Duration::from_secs(config.cache_ttl))
             // Max TTI for items
             .time_to_idle(Duration::from_secs(config.cache_tti))
+            // TTL for items, may be set by auth server or soft-fail policy
+            .expire_after(DecisionExpiry {
+                ttl: Duration::from_secs(config.cache_ttl),
+            })
+            // Allow to revoke sessions
+            .support_invalidation_closures()
+            // Count expired and evicted decisions
+            .eviction_listener(move |_, _, cause| {
+                if cause.was_evicted() {
+                    evicted.evictions.fetch_add(1, Ordering::Relaxed);
+                }
+            })
             .build();
 
-        let client = Client::builder()
-            // Timeout 5s for request to remote server
-            .timeout(Duration::from_secs(5))
-            .build()?;
+        // Timeout 5s for request to remote server
+        let client = config.client.build(Duration::from_secs(5))?;
+
+        // shared cache, sessions revoked by other replicas are dropped from local cache
+        let shared = match config.redis {
+            Some(ref url) => {
+                let shared = SharedAccess::new(url, config.cache_ttl)?;
+                let local = cache.clone();
+                shared.subscribe(move |session| match URL_SAFE_NO_PAD.decode(session) {
+                    Ok(x) => invalidate_session(&local, String::from_utf8_lossy(&x).into_owned()),
+                    Err(_) => warn!("illegal revoked session: {}", session),
+                });
+                Some(shared)
+            }
+            None => None,
+        };
 
         Ok(ModelAccess {
             cache,
             client,
             config: config.clone(),
+            events,
+            shared,
+            counters,
+            known: [config.soft_fail, config.budget_policy]
+                .contains(&SoftFail::Known)
+                .then(|| {
+                    Cache::builder()
+                        .max_capacity(100_000)
+                        .time_to_live(KNOWN_TTL)
+                        .build()
+                }),
         })
     }
 
     // check access to model
     pub async fn check(&self, key: &AccessKey) -> AccessMode {
-        let mode = self
+        if self.config.mode == AuthMode::Disabled {
+            return AccessMode::Granted;
+        }
+        let entry = self
             .cache
-            .get_with(key.clone(), async { self.check_remote(key).await })
+            .entry_by_ref(key)
+            .or_insert_with(async {
+                match self.shared {
+                    Some(ref shared) => self.check_shared(shared, key).await,
+                    None => self.check_remote(key).await,
+                }
+            })
             .await;
-        debug!("access {:?} for {:?}", mode, &key);
-        mode
+        let hit = !entry.is_fresh();
+        self.decided(key, entry.into_value(), hit)
     }
 
-    async fn check_remote(&self, key: &AccessKey) -> AccessMode {
-        // url for request
-        let mut url = self.config.server.to_string();
+    // count the cache hit or miss, start refresh-ahead if due
+    fn decided(&self, key: &AccessKey, decision: Decision, hit: bool) -> AccessMode {
+        let counter = match hit {
+            true => &self.counters.hits,
+            false => &self.counters.misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        if self.refresh_due(&decision) {
+            self.refresh(key.clone());
+        }
+        debug!("access {:?} for {:?}", decision.mode, &key);
+        decision.mode
+    }
+
+    /// Check access within the time budget, answer by the budget policy
+    /// if the auth server is slow, its decision is cached in background
+    pub async fn check_within(&self, key: &AccessKey, budget: Duration) -> AccessMode {
+        if budget.is_zero() || self.config.mode == AuthMode::Disabled {
+            return self.check(key).await;
+        }
+        if let Some(decision) = self.cache.get(key).await {
+            return self.decided(key, decision, true);
+        }
+        let access = self.clone();
+        let pending = key.clone();
+        let check = tokio::spawn(async move { access.check(&pending).await });
+        match tokio::time::timeout(budget, check).await {
+            Ok(Ok(mode)) => mode,
+            Ok(Err(err)) => {
+                error!("access check failed: {}", err);
+                AccessMode::Denied
+            }
+            Err(_) => {
+                self.counters.over_budget.fetch_add(1, Ordering::Relaxed);
+                let grant = self.policy(self.config.budget_policy, key);
+                warn!(
+                    "auth check over budget, access {} to {:?} {:?}",
+                    if grant { "granted" } else { "denied" },
+                    key.model,
+                    key.session_id
+                );
+                match grant {
+                    true => AccessMode::Granted,
+                    false => AccessMode::Denied,
+                }
+            }
+        }
+    }
+
+    // is the decision requested close to expiration and not refreshing yet?
+    fn refresh_due(&self, decision: &Decision) -> bool {
+        let ahead = self.config.refresh_ahead;
+        ahead > 0
+            && decision.checked.elapsed().as_secs() + ahead
+                >= decision.ttl.map_or(self.config.cache_ttl, |x| x.as_secs())
+            && !decision.refreshing.swap(true, Ordering::Relaxed)
+    }
+
+    // re-check the decision in background, replaced entry gets new TTL
+    fn refresh(&self, key: AccessKey) {
+        let access = self.clone();
+        tokio::spawn(async move {
+            debug!("refresh ahead access for {:?}", &key);
+            let decision = access.check_remote(&key).await;
+            match access.shared {
+                Some(ref shared) if !decision.soft => {
+                    let shared_key = SharedAccess::key(
+                        &encode_session(key.session_id.value()),
+                        &access.url(&key),
+                    );
+                    shared
+                        .set(&shared_key, decision.mode == AccessMode::Granted, decision.ttl)
+                        .await;
+                }
+                _ => (),
+            }
+            // skip sessions revoked during the request
+            if access.cache.contains_key(&key) {
+                access.cache.insert(key, decision).await;
+            }
+        });
+    }
+
+    /// Check object access as a wildcard for its models, pre-populate
+    /// the cache with decisions for other models of the object if granted
+    pub async fn preauthorize(&self, key: &AccessKey, models: Vec<String>) {
+        if self.config.mode == AuthMode::Disabled {
+            return;
+        }
+        let object = AccessKey {
+            model: Arc::new(Model::new(key.model.object.as_deref(), None)),
+            ..key.clone()
+        };
+        let entry = self
+            .cache
+            .entry_by_ref(&object)
+            .or_insert_with(self.check_remote(&object))
+            .await;
+        let decision = entry.value();
+        if decision.mode != AccessMode::Granted || decision.soft {
+            return;
+        }
+        for name in models {
+            let model = Model::new(key.model.object.as_deref(), Some(&name));
+            let key = AccessKey {
+                model: Arc::new(model),
+                ..key.clone()
+            };
+            let entry = self
+                .cache
+                .entry(key)
+                .or_insert(Decision::new(AccessMode::Granted))
+                .await;
+            if entry.is_fresh() {
+                debug!("pre-authorized access for {:?}", entry.key());
+            }
+        }
+    }
+
+    /// Access cache and auth server statistics
+    pub async fn stats(&self) -> AccessStats {
+        // apply pending writes to get the actual entry count
+        self.cache.run_pending_tasks().await;
+        let c = &self.counters;
+        let auth_requests = c.auth_requests.load(Ordering::Relaxed);
+        let auth_time_us = c.auth_time_us.load(Ordering::Relaxed);
+        let misses = c.misses.load(Ordering::Relaxed);
+        AccessStats {
+            hits: c.hits.load(Ordering::Relaxed),
+            misses,
+            inserts: misses, // every miss inserts the decision
+            evictions: c.evictions.load(Ordering::Relaxed),
+            entries: self.cache.entry_count(),
+            auth_requests,
+            auth_errors: c.auth_errors.load(Ordering::Relaxed),
+            auth_time_us,
+            auth_latency_ms: match auth_requests {
+                0 => 0.0,
+                n => auth_time_us as f64 / n as f64 / 1000.0,
+            },
+            soft_grants: c.soft_grants.load(Ordering::Relaxed),
+            over_budget: c.over_budget.load(Ordering::Relaxed),
+        }
+    }
+
+    // revoke all cached decisions of the session on every replica
+    pub async fn revoke(&self, session: &str) {
+        invalidate_session(&self.cache, session.to_owned());
+        if let Some(ref shared) = self.shared {
+            shared
+                .revoke(&encode_session(Some(session)))
+                .await
+                .unwrap_or_else(|err| error!("failed to revoke shared session access: {}", err));
+        }
+    }
+
+    // check access in shared cache, fallback to auth server
+    async fn check_shared(&self, shared: &SharedAccess, key: &AccessKey) -> Decision {
+        let shared_key = SharedAccess::key(&encode_session(key.session_id.value()), &self.url(key));
+        match shared.get(&shared_key).await {
+            Some(true) => Decision::new(AccessMode::Granted),
+            Some(false) => Decision::new(AccessMode::Denied),
+            None => {
+                let decision = self.check_remote(key).await;
+                // soft-fail decisions are local only
+                if !decision.soft {
+                    shared
+                        .set(&shared_key, decision.mode == AccessMode::Granted, decision.ttl)
+                        .await;
+                }
+                decision
+            }
+        }
+    }
+
+    // auth server url for the key
+    fn url(&self, key: &AccessKey) -> String {
+        let mut url = match key.server {
+            Some(ref server) => server.to_string(),
+            None => self.config.server.to_string(),
+        };
 
         if let Some(ref x) = key.model.object {
             url.push_str(format!("/{}", x).as_ref());
@@ -149,6 +761,21 @@ impl ModelAccess {
             }
         }
 
+        // scope is encoded, it can't add query params
+        if let Some(ref x) = key.scope {
+            let mut query = reqwest::Url::parse("http://localhost/").expect("valid url");
+            query.query_pairs_mut().append_pair("scope", x);
+            url.push('?');
+            url.push_str(query.query().unwrap_or_default());
+        }
+        url
+    }
+
+    #[tracing::instrument(name = "auth_request", skip_all)]
+    async fn check_remote(&self, key: &AccessKey) -> Decision {
+        // url for request
+        let url = self.url(key);
+
         // prepare request to remote server
         debug!("request to remote server: {}", &url);
         let mut rq = self.client.get(&url);
@@ -156,39 +783,88 @@ impl ModelAccess {
         // add session id cookie if exists
         if let Some(id) = &key.session_id.0 {
             let cookie = format!("{}={}", self.config.cookie_name, id);
-            debug!("set cookie: {}", &cookie);
+            debug!("set cookie: {} {:?}", self.config.cookie_name, key.session_id);
             rq = rq.header("Cookie", &cookie);
         }
 
         // send request to remote server and interpret response
-        match rq.send().await {
-            Ok(res) if res.status() == StatusCode::OK => AccessMode::Granted,
-            Ok(_) => AccessMode::Denied,
+        let start = Instant::now();
+        let res = rq.send().await;
+        let c = &self.counters;
+        c.auth_requests.fetch_add(1, Ordering::Relaxed);
+        c.auth_time_us
+            .fetch_add(start.elapsed().as_micros() as u64, Ordering::Relaxed);
+        match res {
+            Ok(res) => {
+                let header = res.headers().get(CACHE_CONTROL);
+                let ttl = cache_control_ttl(header.and_then(|x| x.to_str().ok()));
+                let mode = match res.status() {
+                    StatusCode::OK => AccessMode::Granted,
+                    _ => AccessMode::Denied,
+                };
+                if let (AccessMode::Granted, Some(known)) = (&mode, &self.known) {
+                    known.insert(key.clone(), ()).await;
+                }
+                Decision {
+                    ttl,
+                    ..Decision::new(mode)
+                }
+            }
             Err(err) => {
+                c.auth_errors.fetch_add(1, Ordering::Relaxed);
                 error!("failed to get response from remote server: {}", &err);
-                AccessMode::Denied
+                self.events.send(Event::AuthDown {
+                    error: err.to_string(),
+                });
+                self.soft_fail(key).await
             }
         }
     }
+
+    // is access granted by the policy without the auth server?
+    fn policy(&self, policy: SoftFail, key: &AccessKey) -> bool {
+        match policy {
+            SoftFail::Off => false,
+            SoftFail::Grant => true,
+            SoftFail::Known => match self.known {
+                Some(ref known) => known.contains_key(key),
+                None => false,
+            },
+        }
+    }
+
+    // decision for the auth server transport error
+    async fn soft_fail(&self, key: &AccessKey) -> Decision {
+        if !self.policy(self.config.soft_fail, key) {
+            return Decision::new(AccessMode::Denied);
+        }
+        self.counters.soft_grants.fetch_add(1, Ordering::Relaxed);
+        warn!(
+            "SOFT-FAIL: auth server unreachable, access granted to {:?} {:?}",
+            key.model, key.session_id
+        );
+        Decision::soft()
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::mock_auth::{self, MockPolicy};
 
     fn get_model_access(server: &'static str) -> ModelAccess {
         let config = AccessConfig {
             server: Absolute::parse(server).unwrap(),
             ..Default::default()
         };
-        ModelAccess::new(&config).unwrap()
+        ModelAccess::new(&config, Events::default()).unwrap()
     }
 
     fn get_access_key() -> AccessKey {
-        AccessKey {
-            model: Arc::new(Model::new(Some("tver"), Some("panorama"))),
-            session_id: SessionId::from("secret_key"),
-        }
+        AccessKey::new(
+            Arc::new(Model::new(Some("tver"), Some("panorama"))),
+            SessionId::from("secret_key"),
+        )
     }
 
     #[test]
@@ -197,10 +873,20 @@ mod test {
         assert_eq!(
             cfg,
             AccessConfig {
+                mode: AuthMode::Server,
                 server: uri!("http://127.0.0.1:8888"),
                 cache_ttl: 30 * 60,
                 cache_tti: 5 * 60,
                 cookie_name: Cow::from("PHPSESSID"),
+                stat_scope: None,
+                redis: None,
+                refresh_ahead: 0,
+                soft_fail: SoftFail::Off,
+                budget: 0,
+                budget_policy: SoftFail::Known,
+                preauth: false,
+                client: ClientConfig::default(),
+                vary: vec!["Cookie".to_owned(), "Authorization".to_owned()],
             }
         )
     }
@@ -211,11 +897,248 @@ mod test {
             get_access_key(),
             AccessKey {
                 model: Arc::new(Model::new(Some("tver"), Some("panorama"))),
-                session_id: SessionId::from("secret_key")
+                session_id: SessionId::from("secret_key"),
+                scope: None,
+                server: None,
             }
         )
     }
 
+    #[test]
+    fn session_not_logged() {
+        let key = get_access_key();
+        let logged = format!("{:?}", key);
+        assert!(!logged.contains("secret_key"));
+        let same = SessionId::from("secret_key");
+        assert_eq!(format!("{:?}", key.session_id), format!("{:?}", same));
+        assert_eq!(format!("{:?}", SessionId(None)), "SessionId(None)");
+    }
+
+    #[test]
+    fn scoped_url() {
+        let model_access = get_model_access("http://127.0.0.1:8888/auth");
+        let key = get_access_key();
+        assert_eq!(
+            model_access.url(&key),
+            "http://127.0.0.1:8888/auth/tver/panorama"
+        );
+        assert_eq!(
+            model_access.url(&key.with_scope("stat")),
+            "http://127.0.0.1:8888/auth/tver/panorama?scope=stat"
+        );
+        assert_eq!(
+            model_access.url(&get_access_key().with_scope("a&admin=1#x")),
+            "http://127.0.0.1:8888/auth/tver/panorama?scope=a%26admin%3D1%23x"
+        );
+    }
+
+    #[rocket::async_test]
+    async fn access_stats() {
+        let key = get_access_key();
+        // nothing listens on the discard port, connection refused
+        let model_access = get_model_access("http://127.0.0.1:9");
+        assert_eq!(model_access.check(&key).await, AccessMode::Denied);
+        assert_eq!(model_access.check(&key).await, AccessMode::Denied);
+
+        let stats = model_access.stats().await;
+        assert_eq!((stats.hits, stats.misses, stats.inserts), (1, 1, 1));
+        assert_eq!((stats.auth_requests, stats.auth_errors), (1, 1));
+        assert_eq!((stats.entries, stats.evictions), (1, 0));
+    }
+
+    #[rocket::async_test]
+    async fn refresh_ahead() {
+        let config = AccessConfig {
+            server: Absolute::parse("http://127.0.0.1:9").unwrap(),
+            cache_ttl: 60,
+            refresh_ahead: 10,
+            ..Default::default()
+        };
+        let model_access = ModelAccess::new(&config, Events::default()).unwrap();
+        let key = get_access_key();
+
+        // fresh decision is not refreshed
+        let decision = Decision::new(AccessMode::Denied);
+        assert!(!model_access.refresh_due(&decision));
+
+        // decision close to TTL is refreshed once
+        let old = Decision {
+            checked: Instant::now() - Duration::from_secs(55),
+            ..decision
+        };
+        model_access.cache.insert(key.clone(), old).await;
+        model_access.check(&key).await;
+        assert!(!model_access.refresh_due(&model_access.cache.get(&key).await.unwrap()));
+
+        // background request replaced the entry with the fresh one
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let fresh = model_access.cache.get(&key).await.unwrap();
+        assert!(fresh.checked.elapsed() < Duration::from_secs(1));
+        assert_eq!(model_access.stats().await.auth_requests, 1);
+    }
+
+    #[rocket::async_test]
+    async fn soft_fail() {
+        let key = get_access_key();
+        let other = AccessKey::new(key.model.clone(), SessionId::from("other"));
+        let config = |soft_fail| AccessConfig {
+            server: Absolute::parse("http://127.0.0.1:9").unwrap(),
+            soft_fail,
+            ..Default::default()
+        };
+
+        let model_access = ModelAccess::new(&config(SoftFail::Grant), Events::default()).unwrap();
+        assert_eq!(model_access.check(&key).await, AccessMode::Granted);
+        assert!(model_access.cache.get(&key).await.unwrap().soft);
+        assert_eq!(model_access.stats().await.soft_grants, 1);
+
+        // only sessions granted before
+        let model_access = ModelAccess::new(&config(SoftFail::Known), Events::default()).unwrap();
+        model_access.known.as_ref().unwrap().insert(key.clone(), ()).await;
+        assert_eq!(model_access.check(&key).await, AccessMode::Granted);
+        assert_eq!(model_access.check(&other).await, AccessMode::Denied);
+    }
+
+    #[rocket::async_test]
+    async fn budget() {
+        use tokio::io::AsyncWriteExt;
+
+        // auth server granting access after a delay
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::time::sleep(Duration::from_millis(300)).await;
+                let res = "HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n";
+                stream.write_all(res.as_bytes()).await.ok();
+            }
+        });
+        let config = AccessConfig {
+            server: Absolute::parse_owned(format!("http://{}", addr)).unwrap(),
+            ..Default::default()
+        };
+        let model_access = ModelAccess::new(&config, Events::default()).unwrap();
+        let key = get_access_key();
+        let budget = Duration::from_millis(50);
+
+        // unknown session is denied by policy, the check completes in background
+        assert_eq!(model_access.check_within(&key, budget).await, AccessMode::Denied);
+        assert_eq!(model_access.stats().await.over_budget, 1);
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert_eq!(model_access.check_within(&key, budget).await, AccessMode::Granted);
+        assert_eq!(model_access.stats().await.auth_requests, 1);
+
+        // known session is granted while the slow check is pending
+        model_access.cache.invalidate(&key).await;
+        assert_eq!(model_access.check_within(&key, budget).await, AccessMode::Granted);
+        assert_eq!(model_access.stats().await.over_budget, 2);
+    }
+
+    #[rocket::async_test]
+    async fn disabled() {
+        let key = get_access_key();
+        let config = AccessConfig {
+            mode: AuthMode::Disabled,
+            server: Absolute::parse("http://127.0.0.1:9").unwrap(),
+            ..Default::default()
+        };
+        let model_access = ModelAccess::new(&config, Events::default()).unwrap();
+        assert_eq!(model_access.check(&key).await, AccessMode::Granted);
+        assert_eq!(model_access.stats().await.auth_requests, 0);
+    }
+
+    #[rocket::async_test]
+    async fn preauthorize() {
+        let key = get_access_key();
+        let model_access = get_model_access("http://127.0.0.1:9");
+        let other = |name| AccessKey {
+            model: Arc::new(Model::new(Some("tver"), Some(name))),
+            ..key.clone()
+        };
+        let models = vec!["panorama".to_owned(), "city".to_owned()];
+
+        // object access is not granted, nothing cached
+        model_access.preauthorize(&key, models.clone()).await;
+        assert!(model_access.cache.get(&other("city")).await.is_none());
+
+        // granted object access pre-populates model decisions
+        let object = AccessKey {
+            model: Arc::new(Model::new(Some("tver"), None)),
+            ..key.clone()
+        };
+        let granted = Decision::new(AccessMode::Granted);
+        model_access.cache.insert(object, granted).await;
+        model_access.preauthorize(&key, models).await;
+        assert_eq!(model_access.check(&other("city")).await, AccessMode::Granted);
+        assert_eq!(model_access.stats().await.auth_requests, 1);
+    }
+
+    #[test]
+    fn client_config() {
+        let timeout = Duration::from_secs(5);
+        let config = ClientConfig {
+            http2: true,
+            proxy: Some("http://proxy.example.com:3128".to_owned()),
+            ..Default::default()
+        };
+        assert!(config.build(timeout).is_ok());
+        let config = ClientConfig {
+            proxy: Some("not a url".to_owned()),
+            ..Default::default()
+        };
+        assert!(config.build(timeout).is_err());
+    }
+
+    #[rocket::async_test]
+    async fn pinned_address() {
+        // unresolvable host pinned to the local closed port
+        let config = AccessConfig {
+            server: Absolute::parse("http://auth.invalid:9").unwrap(),
+            client: ClientConfig {
+                resolve: [("auth.invalid".to_owned(), vec!["127.0.0.1".parse().unwrap()])].into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let model_access = ModelAccess::new(&config, Events::default()).unwrap();
+        let res = model_access.client.get("http://auth.invalid:9/").send().await;
+        assert!(res.unwrap_err().is_connect());
+    }
+
+    #[test]
+    fn cache_control() {
+        assert_eq!(cache_control_ttl(None), None);
+        assert_eq!(cache_control_ttl(Some("private")), None);
+        assert_eq!(
+            cache_control_ttl(Some("private, max-age=60")),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(cache_control_ttl(Some("max-age=60, no-store")), Some(Duration::ZERO));
+        assert_eq!(cache_control_ttl(Some("No-Cache")), Some(Duration::ZERO));
+        assert_eq!(cache_control_ttl(Some("max-age=abc")), None);
+    }
+
+    #[rocket::async_test]
+    async fn decision_ttl() {
+        let model_access = get_model_access("http://127.0.0.1:9");
+        let key = get_access_key();
+        let no_store = Decision {
+            ttl: Some(Duration::ZERO),
+            ..Decision::new(AccessMode::Granted)
+        };
+        model_access.cache.insert(key.clone(), no_store).await;
+        assert!(model_access.cache.get(&key).await.is_none());
+
+        let short = Decision {
+            ttl: Some(Duration::from_millis(100)),
+            ..Decision::new(AccessMode::Granted)
+        };
+        model_access.cache.insert(key.clone(), short).await;
+        assert!(model_access.cache.get(&key).await.is_some());
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(model_access.cache.get(&key).await.is_none());
+    }
+
     #[rocket::async_test]
     async fn access_check_timeout() {
         let key = get_access_key();
@@ -225,19 +1148,32 @@ mod test {
         assert_eq!(model_access.check(&key).await, AccessMode::Denied)
     }
 
+    /// Model access with embedded mock auth server
+    async fn get_mock_access(policy: &str) -> ModelAccess {
+        let policy = MockPolicy::parse(policy).unwrap();
+        let addr = mock_auth::spawn(policy, ([127, 0, 0, 1], 0).into())
+            .await
+            .unwrap();
+        let config = AccessConfig {
+            server: Absolute::parse_owned(format!("http://{}", addr)).unwrap(),
+            ..Default::default()
+        };
+        ModelAccess::new(&config, Events::default()).unwrap()
+    }
+
     #[rocket::async_test]
     async fn access_check_granted() {
         let key = get_access_key();
-        // set auth server to test server, always returns 200 OK
-        let model_access = get_model_access("https://httpbin.org/anything");
+        // mock auth server returns 200 OK for the model
+        let model_access = get_mock_access("tver/*").await;
         assert_eq!(model_access.check(&key).await, AccessMode::Granted)
     }
 
     #[rocket::async_test]
     async fn access_check_denied() {
         let key = get_access_key();
-        // set auth server to test server, returns 404 NOT FOUND
-        let model_access = get_model_access("https://httpbin.org/status/404");
+        // mock auth server returns 403 FORBIDDEN for other models
+        let model_access = get_mock_access("lake/*").await;
         assert_eq!(model_access.check(&key).await, AccessMode::Denied)
     }
 }
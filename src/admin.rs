@@ -0,0 +1,105 @@
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::serde::json::Json;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::State;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use crate::access::ModelAccess;
+use crate::model::validate_name;
+use crate::tenant::Tenant;
+use crate::tilestats::{TilesetStats, TilesetStatsCache};
+use crate::Config;
+
+/// Admin API configuration
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq)]
+pub struct AdminConfig {
+    pub token: Option<String>, // static bearer token, admin API disabled if not set
+    pub grpc: Option<SocketAddr>, // gRPC admin server address, disabled if not set
+    pub address: Option<SocketAddr>, // separate admin listener, served on public port if not set
+}
+
+/// Admin request guard, checks the bearer token
+#[derive(Debug)]
+pub struct Admin;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let config = req.rocket().state::<Config<'_>>().unwrap();
+        let token = match config.admin.token {
+            Some(ref token) => token,
+            None => return Outcome::Failure((Status::Forbidden, ())),
+        };
+
+        let bearer = req
+            .headers()
+            .get_one("Authorization")
+            .and_then(|x| x.strip_prefix("Bearer "))
+            .map(str::trim);
+
+        match bearer {
+            Some(x) if x == token => Outcome::Success(Admin),
+            _ => Outcome::Failure((Status::Unauthorized, ())),
+        }
+    }
+}
+
+/// Path to the model directory in the storage root
+pub fn model_dir(root: &Path, object: &str, model: &str) -> Result<PathBuf, Status> {
+    if validate_name(object).is_err() || validate_name(model).is_err() {
+        return Err(Status::BadRequest);
+    }
+    let mut dir = root.to_path_buf();
+    dir.push(object);
+    dir.push(model);
+    Ok(dir)
+}
+
+#[get("/admin/models/<object>/<model>/stats")]
+pub async fn model_stats(
+    _admin: Admin,
+    object: &str,
+    model: &str,
+    tenant: &Tenant,
+    stats: &State<TilesetStatsCache>,
+) -> Result<Json<TilesetStats>, Status> {
+    let dir = model_dir(&tenant.root, object, model)?;
+    match stats.get(&dir).await {
+        Ok(res) => Ok(Json(res.as_ref().clone())),
+        Err(err) => {
+            debug!("model stats error: {}", err);
+            Err(Status::NotFound)
+        }
+    }
+}
+
+/// Revoke cached access decisions of the session on all replicas
+#[post("/admin/access/revoke?<session>")]
+pub async fn revoke_session(_admin: Admin, session: &str, access: &State<ModelAccess>) -> Status {
+    access.revoke(session).await;
+    Status::NoContent
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn model_path() {
+        let root = Path::new("data");
+        assert_eq!(
+            model_dir(root, "tver", "panorama"),
+            Ok(PathBuf::from("data/tver/panorama"))
+        );
+        assert_eq!(
+            model_dir(root, "..", "panorama"),
+            Err(Status::BadRequest)
+        );
+        assert_eq!(model_dir(root, "tver", "a/b"), Err(Status::BadRequest));
+        assert_eq!(model_dir(root, "", "panorama"), Err(Status::BadRequest));
+    }
+}
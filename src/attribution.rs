@@ -0,0 +1,90 @@
+use bytes::Bytes;
+use rocket::serde::json::{self, Value};
+use std::io;
+use std::path::Path;
+
+use crate::cache::{CachedNamedFile, Content, FileCache, Forward};
+use crate::meta::Meta;
+
+/// Inject attribution to `asset.extras` of the tileset,
+/// None if the json is not a tileset with an asset object
+pub fn inject(tileset: &[u8], attribution: &str) -> Option<Bytes> {
+    let mut value: Value = json::from_slice(tileset).ok()?;
+    let extras = value
+        .get_mut("asset")?
+        .as_object_mut()?
+        .entry("extras")
+        .or_insert_with(|| json::json!({}))
+        .as_object_mut()?;
+
+    extras.insert("copyright".to_owned(), Value::from(attribution));
+    // credits displayed by CesiumJS
+    extras.insert(
+        "cesium".to_owned(),
+        json::json!({ "credits": [{ "html": attribution, "showOnScreen": true }] }),
+    );
+
+    json::to_string(&value).ok().map(Bytes::from)
+}
+
+/// Serve tileset with injected attribution, the result is cached,
+/// files without tileset asset are served unchanged
+pub async fn open_attributed(
+    path: &Path,
+    meta: &Meta,
+    attribution: &str,
+    cache: &FileCache,
+) -> io::Result<CachedNamedFile> {
+    // virtual cache key, can't match any real file
+    let key = path.join("#attribution");
+    let mut fwd = Forward::Miss;
+    if let Some(cnt) = cache.get(&key).await {
+        if cnt.meta().modified() == meta.modified() {
+            return Ok(CachedNamedFile::Cached(Box::new(cnt)));
+        }
+        cache.invalidate(&key).await;
+        fwd = Forward::Stale;
+    }
+
+    let tileset = Content::from_file(path).await?;
+    let body = match inject(tileset.body(), attribution) {
+        Some(body) => body,
+        None => {
+            debug!("no tileset asset, attribution skipped: {:?}", path);
+            tileset.body().clone()
+        }
+    };
+    let cnt = Content::new(
+        path.to_path_buf(),
+        Meta::new(body.len() as u64, meta.modified(), false),
+        body,
+    );
+    if !cache.fits(cnt.meta().len()) {
+        return Ok(CachedNamedFile::Loaded(Box::new(cnt), Forward::Bypass("too-large")));
+    }
+    cache.put(key, cnt.clone()).await;
+    Ok(CachedNamedFile::Loaded(Box::new(cnt), fwd))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn inject_attribution() {
+        let tileset = br#"{"asset":{"version":"1.0","extras":{"a":1}},"root":{}}"#;
+        let res = inject(tileset, "© City").unwrap();
+        let value: Value = json::from_slice(&res).unwrap();
+
+        assert_eq!(value["asset"]["version"], "1.0");
+        assert_eq!(value["asset"]["extras"]["a"], 1);
+        assert_eq!(value["asset"]["extras"]["copyright"], "© City");
+        assert_eq!(
+            value["asset"]["extras"]["cesium"]["credits"][0]["html"],
+            "© City"
+        );
+        assert!(inject(b"{}", "© City").is_none());
+        assert!(inject(br#"{"asset":[]}"#, "© City").is_none());
+        assert!(inject(b"not json", "© City").is_none());
+    }
+}
@@ -0,0 +1,101 @@
+use bytes::Bytes;
+use std::io;
+use std::path::Path;
+
+use crate::cache::{CachedNamedFile, Content, FileCache, Forward};
+use crate::meta::Meta;
+
+const HEADER_LEN: usize = 28;
+
+/// Is the path a glb file?
+pub fn is_glb(path: &Path) -> bool {
+    matches!(path.extension(), Some(ext) if ext.eq_ignore_ascii_case("glb"))
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Extract embedded glTF payload from b3dm tile, skip feature and batch tables
+pub fn extract_glb(data: &Bytes) -> io::Result<Bytes> {
+    if data.len() < HEADER_LEN || &data[0..4] != b"b3dm" {
+        return Err(invalid("not a b3dm tile"));
+    }
+    let field = |i: usize| {
+        let pos = 4 * i;
+        u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]) as usize
+    };
+    let byte_length = field(2);
+    let tables: usize = (3..7).map(field).sum();
+    let start = HEADER_LEN + tables;
+    if byte_length > data.len() || start > byte_length {
+        return Err(invalid("illegal b3dm header"));
+    }
+    let glb = data.slice(start..byte_length);
+    if !glb.starts_with(b"glTF") {
+        return Err(invalid("b3dm tile has no binary glTF payload"));
+    }
+    Ok(glb)
+}
+
+/// Serve glb payload of the b3dm tile, the result is cached by the glb path
+pub async fn open_glb(
+    glb: &Path,
+    b3dm: &Path,
+    meta: &Meta,
+    cache: &FileCache,
+) -> io::Result<CachedNamedFile> {
+    let glb = glb.to_path_buf();
+    let mut fwd = Forward::Miss;
+    if let Some(cnt) = cache.get(&glb).await {
+        if cnt.meta().modified() == meta.modified() {
+            return Ok(CachedNamedFile::Cached(Box::new(cnt)));
+        }
+        cache.invalidate(&glb).await;
+        fwd = Forward::Stale;
+    }
+
+    let tile = Content::from_file(b3dm).await?;
+    let body = extract_glb(tile.body())?;
+    let cnt = Content::new(
+        glb.clone(),
+        Meta::new(body.len() as u64, meta.modified(), false),
+        body,
+    );
+    if !cache.fits(cnt.meta().len()) {
+        return Ok(CachedNamedFile::Loaded(Box::new(cnt), Forward::Bypass("too-large")));
+    }
+    cache.put(glb, cnt.clone()).await;
+    Ok(CachedNamedFile::Loaded(Box::new(cnt), fwd))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn b3dm(feature_json: &[u8], glb: &[u8]) -> Bytes {
+        let len = HEADER_LEN + feature_json.len() + glb.len();
+        let mut data = Vec::new();
+        data.extend_from_slice(b"b3dm");
+        for x in [1, len, feature_json.len(), 0, 0, 0] {
+            data.extend_from_slice(&(x as u32).to_le_bytes());
+        }
+        data.extend_from_slice(feature_json);
+        data.extend_from_slice(glb);
+        Bytes::from(data)
+    }
+
+    #[test]
+    fn extract() {
+        let data = b3dm(br#"{"BATCH_LENGTH":0}  "#, b"glTF payload");
+        assert_eq!(
+            extract_glb(&data).unwrap(),
+            Bytes::from_static(b"glTF payload")
+        );
+
+        let data = b3dm(b"", b"not a glb");
+        assert!(extract_glb(&data).is_err());
+        assert!(extract_glb(&Bytes::from_static(b"glTF")).is_err());
+        assert!(is_glb(Path::new("tiles/0.GLB")));
+    }
+}
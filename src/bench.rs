@@ -0,0 +1,301 @@
+use reqwest::{Client, Url};
+use rocket::serde::json::{self, Value};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+
+use crate::cli::BenchArgs;
+use crate::replay;
+use crate::warm;
+
+/// Request paths of the model from access log lines, GET requests only
+pub fn recorded(log: &str, model: &str) -> Vec<String> {
+    let prefix = format!("/models/{}/", model);
+    log.lines()
+        .filter_map(replay::parse_line)
+        .map(|x| x.uri)
+        .filter(|uri| uri.contains(&prefix))
+        .collect()
+}
+
+/// Viewer-like request pattern from the tileset, coarse tiles are requested
+/// more often as every view starts from the root
+pub fn synthesized(tileset: &Value, base: &Url, depth: u32) -> Vec<Url> {
+    let mut tiles = Vec::new();
+    if let Some(root) = tileset.get("root") {
+        warm::collect(root, base, 0, depth, &mut tiles);
+    }
+    let mut pattern = vec![base.clone()];
+    for (url, d) in tiles {
+        for _ in d..=depth {
+            pattern.push(url.clone());
+        }
+    }
+    pattern
+}
+
+/// Single request outcome
+#[derive(Debug, Clone, Copy)]
+pub struct Sample {
+    ok: bool,
+    hit: bool, // served from the file cache
+    bytes: u64,
+    latency_us: u64,
+}
+
+/// Aggregated benchmark results
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub requests: u64,
+    pub errors: u64,
+    pub hits: u64,
+    pub bytes: u64,
+    pub elapsed: Duration,
+    latencies_us: Vec<u64>, // sorted
+}
+
+impl Summary {
+    pub fn new(samples: Vec<Sample>, elapsed: Duration) -> Self {
+        let mut summary = Summary {
+            elapsed,
+            ..Default::default()
+        };
+        for x in samples {
+            summary.requests += 1;
+            summary.errors += !x.ok as u64;
+            summary.hits += x.hit as u64;
+            summary.bytes += x.bytes;
+            summary.latencies_us.push(x.latency_us);
+        }
+        summary.latencies_us.sort_unstable();
+        summary
+    }
+
+    /// Latency percentile in milliseconds, `p` in 0..=100
+    pub fn percentile(&self, p: f64) -> f64 {
+        if self.latencies_us.is_empty() {
+            return 0.0;
+        }
+        let rank = (p / 100.0 * self.latencies_us.len() as f64).ceil() as usize;
+        let i = rank.clamp(1, self.latencies_us.len()) - 1;
+        self.latencies_us[i] as f64 / 1000.0
+    }
+
+    /// Human readable report
+    pub fn report(&self) -> String {
+        let secs = self.elapsed.as_secs_f64().max(1e-6);
+        let ratio = |x: u64| match self.requests {
+            0 => 0.0,
+            n => x as f64 * 100.0 / n as f64,
+        };
+        format!(
+            "requests: {} in {:.2}s, {} errors ({:.1}%)\n\
+             throughput: {:.1} req/s, {:.2} MiB/s\n\
+             latency ms: p50 {:.2}, p90 {:.2}, p99 {:.2}, max {:.2}\n\
+             cache hits: {} ({:.1}%)",
+            self.requests,
+            secs,
+            self.errors,
+            ratio(self.errors),
+            self.requests as f64 / secs,
+            self.bytes as f64 / secs / (1024.0 * 1024.0),
+            self.percentile(50.0),
+            self.percentile(90.0),
+            self.percentile(99.0),
+            self.percentile(100.0),
+            self.hits,
+            ratio(self.hits)
+        )
+    }
+}
+
+/// Request the url, response errors are counted, not reported
+pub async fn sample(client: &Client, url: Url, token: Option<&str>) -> Sample {
+    let start = Instant::now();
+    let mut req = client.get(url);
+    if let Some(token) = token {
+        req = req.query(&[("access_token", token)]);
+    }
+    let (ok, hit, bytes) = match req.send().await {
+        Ok(res) => {
+            let ok = res.status().is_success();
+            let hit = res
+                .headers()
+                .get("Cache-Status")
+                .and_then(|x| x.to_str().ok())
+                .is_some_and(|x| x.split(';').any(|x| x.trim() == "hit"));
+            match res.bytes().await {
+                Ok(body) => (ok, hit, body.len() as u64),
+                Err(_) => (false, hit, 0),
+            }
+        }
+        Err(_) => (false, false, 0),
+    };
+    Sample {
+        ok,
+        hit,
+        bytes,
+        latency_us: start.elapsed().as_micros() as u64,
+    }
+}
+
+/// Request pattern urls, recorded from the access log or synthesized
+async fn pattern(args: &BenchArgs, client: &Client, server: &str) -> Result<Vec<Url>, String> {
+    let origin = Url::parse(server).map_err(|e| format!("Illegal server url: {e}"))?;
+    if let Some(ref log) = args.log {
+        let log = std::fs::read_to_string(log).map_err(|e| format!("Problem read {log}: {e}"))?;
+        return Ok(recorded(&log, &args.model)
+            .iter()
+            .filter_map(|uri| origin.join(uri).ok())
+            .collect());
+    }
+    // root path serves the directory index tileset
+    let root = Url::parse(&format!(
+        "{}/models/{}/",
+        server.trim_end_matches('/'),
+        args.model
+    ))
+    .map_err(|e| format!("Illegal server url: {e}"))?;
+    let mut req = client.get(root.clone());
+    if let Some(ref token) = args.token {
+        req = req.query(&[("access_token", token)]);
+    }
+    let res = req.send().await.map_err(|e| e.to_string())?;
+    if !res.status().is_success() {
+        return Err(format!("{}: {}", root, res.status()));
+    }
+    let body = res.bytes().await.map_err(|e| e.to_string())?;
+    let tileset: Value = json::from_slice(&body).map_err(|e| format!("{}: {}", root, e))?;
+    Ok(synthesized(&tileset, &root, args.depth))
+}
+
+/// Replay the request pattern with concurrent workers, returns true if no errors
+pub async fn run(args: &BenchArgs, server: &str) -> bool {
+    let client = match Client::builder().timeout(Duration::from_secs(30)).build() {
+        Ok(client) => client,
+        Err(err) => {
+            eprintln!("Problem create http client: {err}");
+            return false;
+        }
+    };
+    let urls = match pattern(args, &client, server).await {
+        Ok(urls) if !urls.is_empty() => Arc::new(urls),
+        Ok(_) => {
+            eprintln!("No requests of {} to replay", args.model);
+            return false;
+        }
+        Err(err) => {
+            eprintln!("{err}");
+            return false;
+        }
+    };
+    println!(
+        "replaying {} requests over {} urls with {} workers",
+        args.requests,
+        urls.len(),
+        args.concurrency
+    );
+
+    let next = Arc::new(AtomicUsize::new(0));
+    let token: Option<Arc<str>> = args.token.as_deref().map(Arc::from);
+    let start = Instant::now();
+    let mut tasks = JoinSet::new();
+    for _ in 0..args.concurrency {
+        let (client, urls, next, token) = (
+            client.clone(),
+            Arc::clone(&urls),
+            Arc::clone(&next),
+            token.clone(),
+        );
+        let total = args.requests;
+        tasks.spawn(async move {
+            let mut samples = Vec::new();
+            loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                if i >= total {
+                    break samples;
+                }
+                let url = urls[i % urls.len()].clone();
+                samples.push(sample(&client, url, token.as_deref()).await);
+            }
+        });
+    }
+    let mut samples = Vec::with_capacity(args.requests);
+    while let Some(res) = tasks.join_next().await {
+        match res {
+            Ok(x) => samples.extend(x),
+            Err(err) => eprintln!("error: {err}"),
+        }
+    }
+    let summary = Summary::new(samples, start.elapsed());
+    println!("{}", summary.report());
+    summary.errors == 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn recorded_requests() {
+        let log = "\
+127.0.0.1 - - [2026-10-16T10:00:00Z] \"GET /3d/models/city/block/1/a.b3dm\" 200 10
+127.0.0.1 - - [2026-10-16T10:00:01Z] \"GET /3d/models/city/other/a.b3dm\" 200 10
+127.0.0.1 - - [2026-10-16T10:00:02Z] \"PUT /3d/admin/models/city/block/a.b3dm\" 201 -
+127.0.0.1 - - [2026-10-16T10:00:03Z] \"GET /3d/models/city/block/ HTTP/1.1\" 200 10
+garbage";
+        assert_eq!(
+            recorded(log, "city/block"),
+            ["/3d/models/city/block/1/a.b3dm", "/3d/models/city/block/"]
+        );
+    }
+
+    #[test]
+    fn synthesized_pattern() {
+        let tileset = json::json!({ "root": {
+            "content": { "uri": "root.b3dm" },
+            "children": [{ "content": { "uri": "1/a.b3dm" } }]
+        }});
+        let base = Url::parse("http://localhost/3d/models/city/block/").unwrap();
+        let urls: Vec<_> = synthesized(&tileset, &base, 1)
+            .into_iter()
+            .map(|x| x.path().to_owned())
+            .collect();
+        assert_eq!(
+            urls,
+            [
+                "/3d/models/city/block/",
+                "/3d/models/city/block/root.b3dm",
+                "/3d/models/city/block/root.b3dm",
+                "/3d/models/city/block/1/a.b3dm"
+            ]
+        );
+    }
+
+    #[test]
+    fn summary() {
+        let samples = (1..=100)
+            .map(|i| Sample {
+                ok: i != 7,
+                hit: i % 2 == 0,
+                bytes: 10,
+                latency_us: i * 1000,
+            })
+            .collect();
+        let summary = Summary::new(samples, Duration::from_secs(2));
+        assert_eq!(
+            (
+                summary.requests,
+                summary.errors,
+                summary.hits,
+                summary.bytes
+            ),
+            (100, 1, 50, 1000)
+        );
+        assert_eq!(summary.percentile(50.0), 50.0);
+        assert_eq!(summary.percentile(99.0), 99.0);
+        assert_eq!(summary.percentile(100.0), 100.0);
+        assert!(summary.report().contains("50.0 req/s"));
+    }
+}
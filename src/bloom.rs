@@ -0,0 +1,91 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// filter bits per path and hash functions, about 1% false positives
+const BITS_PER_PATH: usize = 10;
+const HASHES: u64 = 7;
+
+/// Path hash, stable for the process lifetime
+pub fn hash(path: &Path) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Bloom filter of existing storage paths, a miss means the path cannot exist
+pub struct PathFilter {
+    bits: Vec<AtomicU64>,
+    links: Vec<PathBuf>, // symlinks are not walked, paths under them always pass
+}
+
+impl PathFilter {
+    /// Build the filter from scanned path hashes
+    pub fn new(hashes: &[u64], links: Vec<PathBuf>) -> Self {
+        let words = (hashes.len() * BITS_PER_PATH).div_ceil(64).max(1);
+        let filter = PathFilter {
+            bits: (0..words).map(|_| AtomicU64::new(0)).collect(),
+            links,
+        };
+        for hash in hashes {
+            filter.insert_hash(*hash);
+        }
+        filter
+    }
+
+    // bit positions by double hashing
+    fn positions(&self, hash: u64) -> impl Iterator<Item = usize> {
+        let len = self.bits.len() as u64 * 64;
+        let (h1, h2) = (hash & 0xffff_ffff, hash >> 32 | 1);
+        (0..HASHES).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % len) as usize)
+    }
+
+    fn insert_hash(&self, hash: u64) {
+        for i in self.positions(hash) {
+            self.bits[i / 64].fetch_or(1 << (i % 64), Ordering::Relaxed);
+        }
+    }
+
+    /// Add the path created after the scan
+    pub fn insert(&self, path: &Path) {
+        self.insert_hash(hash(path))
+    }
+
+    /// Is the path scanned or added? False positives are possible
+    pub fn may_exist(&self, path: &Path) -> bool {
+        if self.links.iter().any(|x| path.starts_with(x)) {
+            return true;
+        }
+        self.positions(hash(path))
+            .all(|i| self.bits[i / 64].load(Ordering::Relaxed) & (1 << (i % 64)) != 0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn path_filter() {
+        let paths: Vec<PathBuf> = (0..1000)
+            .map(|i| PathBuf::from(format!("data/tver/panorama/{}/{}.b3dm", i / 10, i)))
+            .collect();
+        let hashes: Vec<u64> = paths.iter().map(|x| hash(x)).collect();
+        let filter = PathFilter::new(&hashes, vec![PathBuf::from("data/tver/live")]);
+
+        // no false negatives, few false positives
+        assert!(paths.iter().all(|x| filter.may_exist(x)));
+        let false_positives = (0..1000)
+            .filter(|i| filter.may_exist(Path::new(&format!("data/tver/missing/{}.b3dm", i))))
+            .count();
+        assert!(false_positives < 50, "{} false positives", false_positives);
+
+        // added after build and under not walked links
+        let added = Path::new("data/tver/panorama/new.b3dm");
+        assert!(!filter.may_exist(added));
+        filter.insert(added);
+        assert!(filter.may_exist(added));
+        assert!(filter.may_exist(Path::new("data/tver/live/0/0.b3dm")));
+    }
+}
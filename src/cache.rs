@@ -1,40 +1,98 @@
-use bytes::Bytes;
-// use dash cache variant to prevent using GC for eviction
-use moka::dash::Cache;
+use bytes::{Bytes, BytesMut};
+use moka::future::Cache;
+use memmap2::Mmap;
 
+use rocket::fairing::AdHoc;
 use rocket::fs::NamedFile;
-use rocket::http::{ContentType, Header};
-use rocket::request::Request;
-use rocket::response::{self, Responder, Response};
+use flate2::read::GzDecoder;
+use rocket::http::{ContentType, Header, Method, Status};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::response::{self, Builder, Responder, Response};
 use rocket::serde::{Deserialize, Serialize};
 
-use std::io::Cursor;
+use std::collections::hash_map::RandomState;
+use std::cmp::Reverse;
+use std::collections::hash_map::Entry;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{Cursor, Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
 use tokio::fs::File;
-use tokio::io::{self, AsyncReadExt};
-use tokio::sync::mpsc;
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncSeek, ReadBuf};
+use tokio::sync::{mpsc, Semaphore};
 use tokio::task;
 
+use crate::admin::Admin;
+use crate::events::{Event, Events};
+use crate::mime::is_vector_tile;
+use crate::meta::MetaCache;
+use crate::Config;
+use crate::ContentTypes;
 use crate::Meta;
 
+/// Default file read chunk size in Kbytes, 2 MB as tokio default
+pub const DEFAULT_READ_BUFFER: usize = 2048;
+
 /// File cache configuration
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub struct FileCacheConfig {
     pub size: u64, // cache size limit in Mbytes
+    pub mmap_max: u64, // memory-map files bypassing the cache up to this size in Mbytes
+    pub read_buffer: usize, // file read chunk size in Kbytes
+    pub stream_threshold: u64, // stream larger files from disk without caching, Mbytes
+    pub dedup: bool, // store identical bodies once, keyed by content hash
+    pub pin_budget: u64, // non-evictable entries limit in Mbytes
+    pub load_concurrency: usize, // parallel background file reads
+    pub missing_grace: u64, // serve cached files deleted on disk for seconds, 0 disables
 }
 
 impl Default for FileCacheConfig {
     fn default() -> Self {
         FileCacheConfig {
             size: 500,             // 500 MB
+            mmap_max: 0,           // disabled
+            read_buffer: DEFAULT_READ_BUFFER,
+            stream_threshold: 0,   // cache size only
+            dedup: false,
+            pin_budget: 0,         // pinning disabled
+            load_concurrency: 4,
+            missing_grace: 0,      // disabled
         }
     }
 }
 
 pub enum CachedNamedFile {
-    File(NamedFile, Meta),
+    File(NamedFile, Meta, Forward),
     Cached(Box<Content>),
+    Loaded(Box<Content>, Forward), // in-memory content, not from the cache
+    Missing(Box<Content>),         // cached content of the file deleted on disk
+}
+
+/// Why the response was not served from the cache, RFC 9211 `fwd` parameter
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Forward {
+    Miss,                 // not in the cache, stored for next requests
+    Stale,                // cached content outdated by the file change, stored again
+    Request,              // cache refresh requested by the client, stored again
+    Bypass(&'static str), // not stored, with the reason
+}
+
+impl Forward {
+    /// `Cache-Status` header value, the source is the serving path
+    fn cache_status(self, source: &str) -> String {
+        match self {
+            Forward::Miss => format!("rtiles; fwd=uri-miss; stored; detail={}", source),
+            Forward::Stale => format!("rtiles; fwd=stale; stored; detail={}", source),
+            Forward::Request => format!("rtiles; fwd=request; stored; detail={}", source),
+            Forward::Bypass(reason) => format!("rtiles; fwd=bypass; detail={}", reason),
+        }
+    }
 }
 
 impl CachedNamedFile {
@@ -46,7 +104,27 @@ impl CachedNamedFile {
             None => Meta::from(f.metadata().await?),
         };
 
-        Ok(CachedNamedFile::File(f, m))
+        Ok(CachedNamedFile::File(f, m, Forward::Bypass("uncached")))
+    }
+
+    /// Set the cache lookup outcome of the content not from the cache
+    pub fn forward(self, fwd: Forward) -> Self {
+        match self {
+            CachedNamedFile::File(f, m, _) => CachedNamedFile::File(f, m, fwd),
+            CachedNamedFile::Loaded(c, _) => CachedNamedFile::Loaded(c, fwd),
+            cached => cached,
+        }
+    }
+
+    /// Mark the content reloaded on the client request, bypass reasons are kept
+    pub fn refreshed(self) -> Self {
+        match self {
+            CachedNamedFile::File(_, _, Forward::Miss | Forward::Stale)
+            | CachedNamedFile::Loaded(_, Forward::Miss | Forward::Stale) => {
+                self.forward(Forward::Request)
+            }
+            res => res,
+        }
     }
 
     /// Get back cached content or open named file
@@ -54,138 +132,1028 @@ impl CachedNamedFile {
         path: &PathBuf,
         meta: &Meta,
         cache: &FileCache,
+    ) -> io::Result<Self> {
+        Self::open_with_detection(path, meta, cache, false).await
+    }
+
+    /// Same as [`Self::open_with_cache()`], with optional detection of
+    /// gzipped payloads of any file type fitting in the cache
+    #[tracing::instrument(name = "open", skip_all, fields(path = ?path))]
+    pub async fn open_with_detection(
+        path: &PathBuf,
+        meta: &Meta,
+        cache: &FileCache,
+        detect_gzip: bool,
     ) -> io::Result<Self> {
         // try to get content from cache
-        if let Some(cnt) = cache.get(path) {
+        let mut fresh = None;
+        let mut fwd = Forward::Miss;
+        if let Some(cnt) = cache.get(path).await {
             // compare metadata
             if &cnt.meta == meta {
                 return Ok(CachedNamedFile::Cached(Box::new(cnt)));
-            } else {
-                // invalidate cache entry if metadata differ
-                cache.invalidate(path)
             }
+            // either entry may be stale, recheck the file
+            let m = match cache.invalidation().recheck(path).await {
+                Ok(m) => m,
+                // deleted on disk, cached content is served during the grace period
+                Err(err) if err.kind() == io::ErrorKind::NotFound && cache.has_missing_grace() => {
+                    let cnt = cache.get_missing(path).await.ok_or(err)?;
+                    return Ok(CachedNamedFile::Missing(Box::new(cnt)));
+                }
+                Err(err) => return Err(err),
+            };
+            if cnt.meta == m {
+                return Ok(CachedNamedFile::Cached(Box::new(cnt)));
+            }
+            cache.invalidate(path).await;
+            fresh = Some(m);
+            fwd = Forward::Stale;
         }
+        let meta = fresh.as_ref().unwrap_or(meta);
 
-        // try to open a file from a given path
-        let f = Self::open(path, Some(meta)).await?;
+        // vector tiles are small and may be gzipped, load them to memory
+        // to inspect the payload encoding
+        if is_vector_tile(path) || detect_gzip && cache.fits(meta.len()) {
+            let cnt = Content::from_file_buffered(path, cache.read_buffer())
+                .await?
+                .detect_gzip(detect_gzip);
+            if !cache.fits(cnt.meta.len()) {
+                return Ok(CachedNamedFile::Loaded(Box::new(cnt), Forward::Bypass("too-large")));
+            }
+            cache.put(path.clone(), cnt.clone()).await;
+            return Ok(CachedNamedFile::Loaded(Box::new(cnt), fwd));
+        }
 
-        // check file length against cache size and u32::MAX (cache weigher limit )
-        let len = f.meta().len();
-        if len <= cache.size() && len <= u32::MAX as u64 {
+        let len = meta.len();
+        if cache.fits(len) {
+            // try to open a file from a given path
+            let f = Self::open(path, Some(meta)).await?;
             // insert file into cache
             cache
-                .insert(path)
-                .unwrap_or_else(|err| error!("error adding file to cache: {}", err))
-        } else {
-            warn!(
-                "file {} exceeds cache size or 4GB limit, not cached",
-                path.to_string_lossy()
-            )
+                .insert(path, len)
+                .unwrap_or_else(|err| error!("error adding file to cache: {}", err));
+            return Ok(f.forward(fwd));
         }
-        Ok(f)
+
+        // large files bypass the cache, map them to memory if enabled
+        if len <= cache.mmap_max() {
+            match map_file(path.clone(), meta.clone()).await {
+                Ok(body) => {
+                    let cnt = Content::new(path.clone(), meta.clone(), body);
+                    return Ok(CachedNamedFile::Loaded(Box::new(cnt), Forward::Bypass("mmap")));
+                }
+                Err(err) => warn!("error mapping file {}: {}", path.to_string_lossy(), err),
+            }
+        }
+
+        warn!(
+            "file {} exceeds cache limits, streamed from disk",
+            path.to_string_lossy()
+        );
+        Ok(Self::open(path, Some(meta))
+            .await?
+            .forward(Forward::Bypass("stream")))
     }
 
     /// Get content metadata
     pub fn meta(&self) -> &Meta {
         match self {
-            CachedNamedFile::File(_, m) => m,
-            CachedNamedFile::Cached(c) => &c.meta,
+            CachedNamedFile::File(_, m, _) => m,
+            CachedNamedFile::Cached(c)
+            | CachedNamedFile::Loaded(c, _)
+            | CachedNamedFile::Missing(c) => &c.meta,
         }
     }
 
     // Does the content come from the memory cache?
     pub fn is_cached(&self) -> bool {
         match self {
-            CachedNamedFile::File(..) => false,
-            CachedNamedFile::Cached(_) => true,
+            CachedNamedFile::File(..) | CachedNamedFile::Loaded(..) => false,
+            CachedNamedFile::Cached(_) | CachedNamedFile::Missing(_) => true,
+        }
+    }
+
+    /// `Cache-Status` header value, RFC 9211
+    pub fn cache_status(&self) -> String {
+        match self {
+            CachedNamedFile::File(_, _, fwd) => fwd.cache_status("disk"),
+            CachedNamedFile::Cached(_) => "rtiles; hit".to_owned(),
+            CachedNamedFile::Missing(_) => "rtiles; hit; detail=missing".to_owned(),
+            CachedNamedFile::Loaded(_, fwd) => fwd.cache_status("memory"),
         }
     }
 }
 
-/// Combined responder for named file and cached content
+/// Client request to serve the file from disk and refresh the cache entry,
+/// `Cache-Control: no-cache` if enabled or `X-Rtiles-Bypass-Cache` with admin token
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CacheBypass(pub bool);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for CacheBypass {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let no_cache = req.rocket().state::<Config<'_>>().is_some_and(|config| {
+            config.storage.no_cache_requests
+                && req
+                    .headers()
+                    .get("Cache-Control")
+                    .flat_map(|v| v.split(','))
+                    .any(|v| v.trim().eq_ignore_ascii_case("no-cache"))
+        });
+        let forced = req.headers().contains("X-Rtiles-Bypass-Cache")
+            && req.guard::<Admin>().await.is_success();
+        Outcome::Success(CacheBypass(no_cache || forced))
+    }
+}
+
+/// Combined responder for named file and cached content,
+/// validators and conditional requests are the same for all sources
 impl<'r> Responder<'r, 'static> for CachedNamedFile {
     fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
-        match self {
-            CachedNamedFile::File(f, _) => {
-                // set content type more properly...
-                let mime_type = match f.path().extension() {
-                    Some(ext) => ContentType::from_extension(&ext.to_string_lossy()),
-                    None => None,
-                };
-                let mut response = f.take_file().respond_to(req)?;
-                response.set_header(mime_type.unwrap_or(ContentType::Binary));
-                Ok(response)
+        let _span = tracing::info_span!("respond").entered();
+        let meta = self.meta().clone();
+        let cache_status = self.cache_status();
+        let mut builder = if is_not_modified(req, &meta) {
+            let mut builder = Response::build();
+            builder.status(Status::NotModified);
+            builder
+        } else {
+            match self {
+                CachedNamedFile::File(f, m, _) => file_response(f, &m, req)?,
+                // deleted files are reported by `Cache-Status` only, `Warning` is obsolete
+                CachedNamedFile::Cached(c)
+                | CachedNamedFile::Loaded(c, _)
+                | CachedNamedFile::Missing(c) => c.response(req)?,
             }
-            CachedNamedFile::Cached(c) => c.respond_to(req),
+        };
+        builder.raw_header("Cache-Status", cache_status);
+        for header in validators(&meta) {
+            builder.header(header);
+        }
+        builder.ok()
+    }
+}
+
+/// Stream file from disk, single ranges are read from the file position
+fn file_response(f: NamedFile, meta: &Meta, req: &Request<'_>) -> Result<Builder<'static>, Status> {
+    let mut builder = Response::build();
+    builder.header(content_types(req).get(f.path()));
+    builder.raw_header("Accept-Ranges", "bytes");
+
+    let len = meta.len();
+    let file = f.take_file();
+    match requested_range(req, meta, len) {
+        ByteRange::Partial(start, end) => {
+            let part = FilePart::new(file, start, end - start + 1).map_err(|err| {
+                error!("file seek error: {}", err);
+                Status::InternalServerError
+            })?;
+            builder
+                .status(Status::PartialContent)
+                .raw_header("Content-Range", format!("bytes {}-{}/{}", start, end, len))
+                .sized_body(Some((end - start + 1) as usize), part);
+        }
+        ByteRange::Unsatisfiable => {
+            builder
+                .status(Status::RangeNotSatisfiable)
+                .raw_header("Content-Range", format!("bytes */{}", len))
+                .sized_body(Some(0), Cursor::new(Bytes::new()));
+        }
+        // multiple ranges are answered from memory only, send the whole file
+        ByteRange::Full | ByteRange::Multi(_) => {
+            builder.sized_body(None, file);
         }
     }
+    Ok(builder)
+}
+
+/// Part of the file from a given position, the body size is known
+/// so seeking is never requested
+struct FilePart(io::Take<File>);
+
+impl FilePart {
+    fn new(file: File, start: u64, len: u64) -> io::Result<Self> {
+        // fresh file has no pending operations
+        let mut file = file
+            .try_into_std()
+            .map_err(|_| io::Error::other("file is busy"))?;
+        file.seek(SeekFrom::Start(start))?;
+        Ok(FilePart(File::from_std(file).take(len)))
+    }
+}
+
+impl AsyncRead for FilePart {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncSeek for FilePart {
+    fn start_seek(self: Pin<&mut Self>, _: SeekFrom) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        Poll::Ready(Ok(0))
+    }
+}
+
+/// Does the client have the current content? `If-None-Match` takes
+/// precedence over `If-Modified-Since`, the date must match exactly
+fn is_not_modified(req: &Request<'_>, meta: &Meta) -> bool {
+    Validators::new(req).not_modified(meta)
+}
+
+/// Request method and validators deciding if the response has a body
+#[derive(Debug, Clone, Copy)]
+pub struct Validators<'r> {
+    method: Method,
+    if_none_match: Option<&'r str>,
+    if_modified_since: Option<&'r str>,
+}
+
+// original request method, `HEAD` requests are routed to `GET` handlers
+struct RequestMethod(Method);
+
+/// Remember the request method before routing
+pub fn method_fairing() -> AdHoc {
+    AdHoc::on_request("request method", |req, _| {
+        Box::pin(async move {
+            req.local_cache(|| RequestMethod(req.method()));
+        })
+    })
+}
+
+impl<'r> Validators<'r> {
+    fn new(req: &'r Request<'_>) -> Self {
+        Validators {
+            method: req.local_cache(|| RequestMethod(req.method())).0,
+            if_none_match: req.headers().get_one("If-None-Match"),
+            if_modified_since: req.headers().get_one("If-Modified-Since"),
+        }
+    }
+
+    /// Is the response body omitted by the request method?
+    pub fn is_head(&self) -> bool {
+        self.method == Method::Head
+    }
+
+    /// Is the response `304 Not Modified` for the content metadata?
+    pub fn not_modified(&self, meta: &Meta) -> bool {
+        if !matches!(self.method, Method::Get | Method::Head) {
+            return false;
+        }
+        if let Some(tags) = self.if_none_match {
+            // weak comparison, RFC 9110 section 13.1.2
+            let etag = meta.etag();
+            let etag = etag.trim_start_matches("W/");
+            return tags
+                .split(',')
+                .map(|x| x.trim())
+                .any(|x| x == "*" || x.trim_start_matches("W/") == etag);
+        }
+        match self.if_modified_since {
+            Some(date) => meta.last_modified().as_deref() == Some(date.trim()),
+            None => false,
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Validators<'r> {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(Validators::new(req))
+    }
+}
+
+/// Get content types table from rocket managed state or defaults
+fn content_types<'r>(req: &'r Request<'_>) -> &'r ContentTypes {
+    static DEFAULT: OnceLock<ContentTypes> = OnceLock::new();
+    req.rocket()
+        .state::<ContentTypes>()
+        .unwrap_or_else(|| DEFAULT.get_or_init(ContentTypes::default))
 }
 
 /// Saved content
 #[derive(Clone)]
 pub struct Content {
-    meta: Meta,                     // file metadata
-    mime_type: Option<ContentType>, // content mime type
-    body: Bytes,                    // body in-memory buffer
+    meta: Meta,    // file metadata
+    path: PathBuf, // file path, used to resolve content type
+    body: Bytes,   // body in-memory buffer
+    detect_gzip: bool, // body may be gzipped, always for vector tiles
+    hash: Option<u64>, // body kept in the dedup store, cached entries only
+    variants: Option<Variants>, // encoded representations, cached entries only
 }
 
 impl Content {
+    /// Make content from in-memory buffer
+    pub fn new(path: PathBuf, meta: Meta, body: Bytes) -> Content {
+        let detect_gzip = is_vector_tile(&path);
+        Content {
+            meta,
+            path,
+            body,
+            detect_gzip,
+            hash: None,
+            variants: None,
+        }
+    }
+
+    /// Inspect the body for gzip magic number when responding
+    pub fn detect_gzip(mut self, detect: bool) -> Self {
+        self.detect_gzip |= detect;
+        self
+    }
+
+    /// Content metadata
+    pub fn meta(&self) -> &Meta {
+        &self.meta
+    }
+
+    /// Content body
+    pub fn body(&self) -> &Bytes {
+        &self.body
+    }
+
     /// Read file to content buffer
-    async fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Content> {
+    pub async fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Content> {
+        Self::from_file_buffered(path, DEFAULT_READ_BUFFER).await
+    }
+
+    /// Read file to content buffer with chunks of the given size in Kbytes
+    #[tracing::instrument(name = "disk_read", skip_all)]
+    pub async fn from_file_buffered<P: AsRef<Path>>(
+        path: P,
+        read_buffer: usize,
+    ) -> io::Result<Content> {
         // open file for reading
         let mut f = File::open(&path).await?;
+        f.set_max_buf_size(read_buffer.max(1) * 1024);
 
         // get content metadata
         let meta = Meta::from(f.metadata().await?);
 
-        // parse content type from file extension if the extension is
-        // recognized. See [`ContentType::from_extension()`] for more information.
-        let mime_type = match path.as_ref().extension() {
-            Some(ext) => ContentType::from_extension(&ext.to_string_lossy()),
-            None => None,
+        // read the whole file to the buffer of the known size
+        let len = meta.len() as usize;
+        let mut buf = BytesMut::with_capacity(len);
+        while buf.len() < len {
+            if f.read_buf(&mut buf).await? == 0 {
+                break;
+            }
+        }
+        if buf.len() != len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "file size changed while reading",
+            ));
+        }
+
+        Ok(Content::new(path.as_ref().to_path_buf(), meta, buf.freeze()))
+    }
+}
+
+impl Content {
+    // build response with the content body
+    fn response(self, req: &Request<'_>) -> Result<Builder<'static>, Status> {
+        let mut builder = Response::build();
+        let content_type = content_types(req).get(&self.path);
+        builder.header(content_type.clone());
+
+        let mut body = self.body;
+        let mut ranges = true;
+        if self.detect_gzip && is_gzip(&body) {
+            // send gzipped payload as is or decompress it for the client
+            builder.raw_header("Vary", "Accept-Encoding");
+            if accepts_gzip(req) {
+                builder.raw_header("Content-Encoding", "gzip");
+            } else {
+                // decoded body is cached for the next identity clients
+                let key = (self.path, Encoding::Identity);
+                match self.variants.as_ref().and_then(|x| x.get(&key)) {
+                    Some(decoded) => body = decoded,
+                    None => {
+                        let mut buf = Vec::new();
+                        GzDecoder::new(body.as_ref())
+                            .read_to_end(&mut buf)
+                            .map_err(|err| {
+                                error!("gzip decoding error: {}", err);
+                                Status::InternalServerError
+                            })?;
+                        body = Bytes::from(buf);
+                        if let Some(ref variants) = self.variants {
+                            variants.insert(key, body.clone());
+                        }
+                    }
+                }
+                // decoded body is not the stored representation
+                ranges = false;
+            }
+        }
+
+        if ranges {
+            builder.raw_header("Accept-Ranges", "bytes");
+            let len = body.len() as u64;
+            match requested_range(req, &self.meta, len) {
+                ByteRange::Full => (),
+                ByteRange::Partial(start, end) => {
+                    // slice shares the cached buffer, no copy
+                    body = body.slice(start as usize..=end as usize);
+                    builder.status(Status::PartialContent).raw_header(
+                        "Content-Range",
+                        format!("bytes {}-{}/{}", start, end, len),
+                    );
+                }
+                ByteRange::Multi(parts) => {
+                    let boundary = format!("{:016x}", RandomState::new().build_hasher().finish());
+                    body = multipart(&body, &parts, &content_type, &boundary);
+                    builder.status(Status::PartialContent).header(ContentType::new(
+                        "multipart",
+                        format!("byteranges; boundary={}", boundary),
+                    ));
+                }
+                ByteRange::Unsatisfiable => {
+                    builder
+                        .status(Status::RangeNotSatisfiable)
+                        .raw_header("Content-Range", format!("bytes */{}", len));
+                    body = Bytes::new();
+                }
+            }
+        }
+
+        builder.sized_body(Some(body.len()), Cursor::new(body));
+        Ok(builder)
+    }
+}
+
+/// ETag and Last-Modified headers for the content metadata
+pub fn validators(meta: &Meta) -> Vec<Header<'static>> {
+    let mut headers = vec![Header::new("ETag", meta.etag())];
+    if let Some(date) = meta.last_modified() {
+        headers.push(Header::new("Last-Modified", date));
+    }
+    headers
+}
+
+/// Byte range of the content to send
+#[derive(Debug, PartialEq)]
+enum ByteRange {
+    Full,
+    Partial(u64, u64), // first and last byte positions, inclusive
+    Multi(Vec<(u64, u64)>), // several disjoint ranges in ascending order
+    Unsatisfiable,
+}
+
+// more ranges in one request are ignored
+const MAX_RANGES: usize = 16;
+
+/// Build `multipart/byteranges` body with the parts of the content
+fn multipart(body: &Bytes, parts: &[(u64, u64)], ct: &ContentType, boundary: &str) -> Bytes {
+    let len = body.len();
+    let mut buf = BytesMut::new();
+    for &(start, end) in parts {
+        let head = format!(
+            "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+            boundary, ct, start, end, len
+        );
+        buf.extend_from_slice(head.as_bytes());
+        buf.extend_from_slice(&body[start as usize..=end as usize]);
+    }
+    buf.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+    buf.freeze()
+}
+
+/// Get the range requested with `Range` and `If-Range` headers
+fn requested_range(req: &Request<'_>, meta: &Meta, len: u64) -> ByteRange {
+    let range = match req.headers().get_one("Range") {
+        Some(range) => range,
+        None => return ByteRange::Full,
+    };
+    // send the whole content if the date does not match, etags are weak
+    // and never match `If-Range` which requires strong comparison
+    if let Some(validator) = req.headers().get_one("If-Range") {
+        if Some(validator.trim()) != meta.last_modified().as_deref() {
+            return ByteRange::Full;
+        }
+    }
+    parse_range(range, len)
+}
+
+/// Parse `bytes` ranges, invalid, too many or summed over the content length
+/// ranges are ignored, overlapping and adjacent ones are coalesced
+fn parse_range(range: &str, len: u64) -> ByteRange {
+    let specs = match range.trim().strip_prefix("bytes=") {
+        Some(specs) => specs.split(',').map(str::trim),
+        None => return ByteRange::Full,
+    };
+    let mut parts = Vec::new();
+    for spec in specs {
+        match parse_spec(spec, len) {
+            ByteRange::Partial(start, end) => parts.push((start, end)),
+            ByteRange::Unsatisfiable => (),
+            _ => return ByteRange::Full,
+        }
+    }
+    if parts.len() > MAX_RANGES || parts.iter().map(|(s, e)| e - s + 1).sum::<u64>() > len {
+        return ByteRange::Full;
+    }
+    match coalesce(parts).as_slice() {
+        [] => ByteRange::Unsatisfiable,
+        [(start, end)] => ByteRange::Partial(*start, *end),
+        parts => ByteRange::Multi(parts.to_vec()),
+    }
+}
+
+/// Merge overlapping and adjacent ranges, RFC 9110 section 14.2
+fn coalesce(mut parts: Vec<(u64, u64)>) -> Vec<(u64, u64)> {
+    parts.sort_unstable();
+    let mut merged: Vec<(u64, u64)> = Vec::with_capacity(parts.len());
+    for (start, end) in parts {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 + 1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+/// Parse single range spec like `0-99`, `100-` or `-100`
+fn parse_spec(spec: &str, len: u64) -> ByteRange {
+    let (first, last) = match spec.split_once('-') {
+        Some(x) => x,
+        None => return ByteRange::Full,
+    };
+    let (start, end) = match (first.parse::<u64>(), last.parse::<u64>()) {
+        (Ok(start), Ok(end)) if start <= end => (start, end.min(len.saturating_sub(1))),
+        (Ok(start), Err(_)) if last.is_empty() => (start, len.saturating_sub(1)),
+        // suffix range, last n bytes
+        (Err(_), Ok(n)) if first.is_empty() && n > 0 => {
+            (len.saturating_sub(n), len.saturating_sub(1))
+        }
+        (Err(_), Ok(_)) if first.is_empty() => return ByteRange::Unsatisfiable,
+        _ => return ByteRange::Full,
+    };
+    if start >= len {
+        ByteRange::Unsatisfiable
+    } else {
+        ByteRange::Partial(start, end)
+    }
+}
+
+/// Map file to memory, pages are shared with the OS page cache
+async fn map_file(path: PathBuf, meta: Meta) -> io::Result<Bytes> {
+    task::spawn_blocking(move || {
+        let file = std::fs::File::open(&path)?;
+        // SAFETY: model files are replaced by rename, not truncated in place,
+        // the mapped length is checked against the expected metadata
+        let mmap = unsafe { Mmap::map(&file)? };
+        if mmap.len() as u64 != meta.len() {
+            return Err(io::Error::other("file size changed since metadata read"));
+        }
+        #[cfg(unix)]
+        mmap.advise(memmap2::Advice::Sequential)?;
+        Ok(Bytes::from_owner(mmap))
+    })
+    .await?
+}
+
+/// Check gzip magic number
+fn is_gzip(body: &[u8]) -> bool {
+    body.starts_with(&[0x1f, 0x8b])
+}
+
+/// Does the client accept gzip encoded content?
+fn accepts_gzip(req: &Request<'_>) -> bool {
+    gzip_accepted(req.headers().get("Accept-Encoding"))
+}
+
+/// Check `Accept-Encoding` values, explicit gzip quality wins over `*`, `q=0` refuses
+fn gzip_accepted<'a>(values: impl Iterator<Item = &'a str>) -> bool {
+    let (mut gzip, mut any) = (None, None);
+    for coding in values.flat_map(|v| v.split(',')) {
+        let mut params = coding.split(';').map(str::trim);
+        let name = params.next().unwrap_or_default();
+        let q = params
+            .filter_map(|x| x.strip_prefix("q="))
+            .find_map(|x| x.parse::<f32>().ok())
+            .unwrap_or(1.0);
+        if name.eq_ignore_ascii_case("gzip") || name.eq_ignore_ascii_case("x-gzip") {
+            gzip = Some(q);
+        } else if name == "*" {
+            any = Some(q);
+        }
+    }
+    gzip.or(any).is_some_and(|q| q > 0.0)
+}
+
+// cache usage percent to notify about
+const FULL_THRESHOLD: u64 = 90;
+
+// cache usage check interval
+const FULL_CHECK: Duration = Duration::from_secs(10);
+
+/// Notify if the cache usage is over the threshold, checked on a timer
+/// so inserts don't run cache maintenance, ended when the cache is dropped
+async fn watch_full(
+    store: Store,
+    size: u64,
+    events: Events,
+    alive: mpsc::WeakSender<(PathBuf, u64)>,
+) {
+    let mut timer = tokio::time::interval(FULL_CHECK);
+    timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    loop {
+        timer.tick().await;
+        if alive.upgrade().is_none() {
+            break;
+        }
+        // apply pending inserts and evictions to get the actual size
+        store.run_pending_tasks().await;
+        let bytes = store.weighted_size();
+        if bytes * 100 >= size * FULL_THRESHOLD {
+            events.send(Event::CacheFull {
+                bytes,
+                capacity: size,
+            });
+        }
+    }
+}
+
+// weight of the path entry with deduplicated body, approximate key and metadata size
+const SHARED_WEIGHT: u32 = 256;
+
+/// Content coding of the served representation, stored gzipped payloads
+/// are served as is from the path entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    Identity, // decoded for clients without gzip support
+}
+
+// all representations of the path stored as variants
+const ENCODINGS: [Encoding; 1] = [Encoding::Identity];
+
+/// Representations of cached content differing from the stored encoding,
+/// sync cache as the decoded body is stored from the blocking responder
+type Variants = moka::sync::Cache<(PathBuf, Encoding), Bytes>;
+
+// part of the cache size reserved for variants, 1/N
+const VARIANTS_SHARE: u64 = 8;
+
+/// Pinned paths, never evicted, content is reloaded after invalidation
+#[derive(Default)]
+struct Pinned {
+    entries: HashMap<PathBuf, Option<Content>>,
+    bytes: u64,
+    budget: u64,
+}
+
+impl Pinned {
+    /// Replace content of the pinned path, the pin is dropped if over the budget
+    fn replace(&mut self, path: &Path, content: Option<Content>) -> bool {
+        let entry = match self.entries.get_mut(path) {
+            Some(entry) => entry,
+            None => return false,
+        };
+        let old = entry.as_ref().map_or(0, |x| x.body.len() as u64);
+        let new = content.as_ref().map_or(0, |x| x.body.len() as u64);
+        if self.bytes - old + new > self.budget {
+            self.bytes -= old;
+            self.entries.remove(path);
+            return false;
+        }
+        self.bytes = self.bytes - old + new;
+        *entry = content;
+        true
+    }
+}
+
+/// Snapshot of the model directory cache entries served during a dataset update,
+/// held outside the cache and the pin budget
+#[derive(Default)]
+struct Freeze {
+    entries: HashMap<PathBuf, Content>, // reloaded from disk on unfreeze
+}
+
+/// Path entries with optional store of deduplicated bodies
+#[derive(Clone)]
+struct Store {
+    paths: Cache<PathBuf, Content>,
+    variants: Variants, // reserved part of the cache size
+    bodies: Option<Cache<u64, Bytes>>, // keyed by body hash, same size limit
+    pinned: Arc<RwLock<Pinned>>,
+    hasher: RandomState,
+    missing: Option<Cache<PathBuf, Instant>>, // first failed lookup of cached files
+    frozen: Arc<RwLock<HashMap<PathBuf, Freeze>>>, // keyed by model directory
+}
+
+impl Store {
+    /// Insert content, identical bodies share the stored buffer
+    async fn insert(&self, path: PathBuf, content: Content) {
+        if self.is_frozen(&path) {
+            return;
+        }
+        self.invalidate_variants(&path);
+        if let Some(ref missing) = self.missing {
+            missing.invalidate(&path).await;
+        }
+        if self.pinned.write().unwrap().replace(&path, Some(content.clone())) {
+            return;
+        }
+        let bodies = match self.bodies {
+            Some(ref bodies) => bodies,
+            None => return self.paths.insert(path, content).await,
         };
+        let hash = self.hasher.hash_one(&content.body[..]);
+        let body = bodies.get_with(hash, async { content.body.clone() }).await;
+        if body == content.body {
+            let shared = Content {
+                body: Bytes::new(),
+                hash: Some(hash),
+                ..content
+            };
+            self.paths.insert(path, shared).await
+        } else {
+            // hash collision, the entry keeps its own body
+            self.paths.insert(path, content).await
+        }
+    }
 
-        // read the whole file to
-        let mut buf = Vec::with_capacity(meta.len() as usize);
-        let bytes = f.read_to_end(&mut buf).await?;
+    /// Get content with the body restored from the dedup store
+    async fn get(&self, path: &PathBuf) -> Option<Content> {
+        if let Some(cnt) = self.frozen_content(path) {
+            return Some(cnt);
+        }
+        let pinned = self.pinned.read().unwrap().entries.get(path).cloned();
+        let mut cnt = match pinned.flatten() {
+            Some(cnt) => cnt,
+            None => self.paths.get(path).await?,
+        };
+        cnt.variants = Some(self.variants.clone());
+        let hash = match cnt.hash {
+            Some(hash) => hash,
+            None => return Some(cnt),
+        };
+        match self.bodies.as_ref()?.get(&hash).await {
+            Some(body) => Some(Content {
+                body,
+                hash: None,
+                ..cnt
+            }),
+            None => {
+                // body evicted, drop the dangling path entry
+                self.paths.invalidate(path).await;
+                None
+            }
+        }
+    }
 
-        assert_eq!(bytes as u64, meta.len());
+    /// Is the path under a frozen directory?
+    fn is_frozen(&self, path: &Path) -> bool {
+        let frozen = self.frozen.read().unwrap();
+        !frozen.is_empty() && frozen.keys().any(|dir| path.starts_with(dir))
+    }
 
-        Ok(Content {
-            meta,
-            mime_type,
-            body: Bytes::from(buf),
+    /// Content of the path in the frozen snapshot
+    fn frozen_content(&self, path: &Path) -> Option<Content> {
+        let frozen = self.frozen.read().unwrap();
+        if frozen.is_empty() {
+            return None;
+        }
+        let cnt = frozen.values().find_map(|x| x.entries.get(path))?;
+        Some(Content {
+            variants: Some(self.variants.clone()),
+            ..cnt.clone()
         })
     }
+
+    /// Is the path content cached, pinned or frozen?
+    fn contains(&self, path: &PathBuf) -> bool {
+        let pinned = self.pinned.read().unwrap();
+        pinned.entries.get(path).is_some_and(Option::is_some)
+            || self.paths.contains_key(path)
+            || self.frozen_content(path).is_some()
+    }
+
+    /// Pin the path content, false if over the budget
+    async fn pin(&self, path: PathBuf, content: Content) -> bool {
+        {
+            let mut pinned = self.pinned.write().unwrap();
+            pinned.entries.entry(path.clone()).or_insert(None);
+            if !pinned.replace(&path, Some(content)) {
+                return false;
+            }
+        }
+        // pinned content is not counted in the cache
+        self.invalidate_variants(&path);
+        self.paths.invalidate(&path).await;
+        true
+    }
+
+    /// Drop encoded representations of the path
+    fn invalidate_variants(&self, path: &Path) {
+        for encoding in ENCODINGS {
+            self.variants.invalidate(&(path.to_path_buf(), encoding));
+        }
+    }
+
+    async fn run_pending_tasks(&self) {
+        self.paths.run_pending_tasks().await;
+        self.variants.run_pending_tasks();
+        if let Some(ref bodies) = self.bodies {
+            bodies.run_pending_tasks().await;
+        }
+    }
+
+    fn weighted_size(&self) -> u64 {
+        self.paths.weighted_size()
+            + self.variants.weighted_size()
+            + self.bodies.as_ref().map_or(0, |x| x.weighted_size())
+    }
 }
 
-/// Streams the content to the client
-impl<'r> Responder<'r, 'static> for Content {
-    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
-        Response::build()
-            .header(self.mime_type.unwrap_or(ContentType::Binary))
-            .header(Header::new("Cache-Status", "rtiles; hit"))
-            .sized_body(Some(self.meta.len() as usize), Cursor::new(self.body))
-            .ok()
+// first failed lookups are kept for a day, files found again are cleared on insert
+const MISSING_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+// queued background loads, requests over the limit are rejected
+const LOAD_QUEUE: usize = 500;
+
+/// Background cache loads, up to N files are read concurrently,
+/// the smallest queued file first so bursts of large files don't delay hot tiles
+struct Loader {
+    store: Store,
+    events: Events,
+    read_buffer: usize,
+}
+
+impl Loader {
+    async fn run(self, mut rx: mpsc::Receiver<(PathBuf, u64)>, concurrency: usize) {
+        let limit = Arc::new(Semaphore::new(concurrency));
+        let loading = Arc::new(Mutex::new(HashSet::new()));
+        let mut queue = BinaryHeap::new();
+        let this = Arc::new(self);
+        loop {
+            // wait for requests if nothing is queued
+            if queue.is_empty() {
+                match rx.recv().await {
+                    Some((path, len)) => queue.push(Reverse((len, path))),
+                    None => break,
+                }
+            }
+            let permit = match Arc::clone(&limit).acquire_owned().await {
+                Ok(permit) => permit,
+                Err(_) => break,
+            };
+            // requests received while waiting for a free slot compete by size
+            while queue.len() < LOAD_QUEUE {
+                match rx.try_recv() {
+                    Ok((path, len)) => queue.push(Reverse((len, path))),
+                    Err(_) => break,
+                }
+            }
+            let Some(Reverse((_, path))) = queue.pop() else {
+                continue;
+            };
+            // already in cache or being loaded, skip
+            if this.store.contains(&path) || !loading.lock().unwrap().insert(path.clone()) {
+                continue;
+            }
+            let (this, loading) = (Arc::clone(&this), Arc::clone(&loading));
+            task::spawn(async move {
+                this.load(&path).await;
+                loading.lock().unwrap().remove(&path);
+                drop(permit);
+            });
+        }
+        debug!("cache file upload task completed");
+    }
+
+    /// Load content and insert to cache
+    async fn load(&self, path: &Path) {
+        match Content::from_file_buffered(path, self.read_buffer).await {
+            Ok(cnt) => {
+                self.events.send(Event::CacheInsert {
+                    path: path.to_string_lossy().into_owned(),
+                    bytes: cnt.meta.len(),
+                });
+                self.store.insert(path.to_path_buf(), cnt).await;
+            }
+            Err(err) => {
+                error!("cache file loading error: {}", err)
+            }
+        }
+    }
+}
+
+/// Invalidation of file content and metadata of the same paths together,
+/// so the caches can't disagree until the metadata expires
+#[derive(Clone)]
+pub struct Invalidation {
+    store: Store,
+    meta: Option<MetaCache>,
+    events: Events,
+}
+
+impl Invalidation {
+    /// Invalidate cached content and metadata of the path, frozen ones are kept
+    pub async fn invalidate(&self, path: &PathBuf) {
+        if self.store.is_frozen(path) {
+            return;
+        }
+        self.events.send(Event::CacheInvalidate {
+            path: path.to_string_lossy().into_owned(),
+        });
+        if let Some(ref meta) = self.meta {
+            meta.invalidate(path).await;
+        }
+        self.store.invalidate_variants(path);
+        // keep the pin, content is reloaded on the next request
+        self.store.pinned.write().unwrap().replace(path, None);
+        self.store.paths.invalidate(path).await
+    }
+
+    /// Invalidate cached content and metadata with the path prefix,
+    /// returns content entries count
+    pub async fn purge(&self, prefix: &Path) -> u64 {
+        if let Some(ref meta) = self.meta {
+            meta.purge(prefix);
+        }
+        // collect keys first, then invalidate one by one
+        let mut keys: Vec<PathBuf> = self
+            .store
+            .paths
+            .iter()
+            .map(|(key, _)| key.as_ref().clone())
+            .filter(|x| x.starts_with(prefix))
+            .collect();
+        keys.extend(
+            self.store
+                .pinned
+                .read()
+                .unwrap()
+                .entries
+                .iter()
+                .filter(|(key, cnt)| cnt.is_some() && key.starts_with(prefix))
+                .map(|(key, _)| key.clone())
+                .collect::<Vec<_>>(),
+        );
+        for key in &keys {
+            self.invalidate(key).await;
+        }
+        keys.len() as u64
+    }
+
+    /// Current metadata of the path, cached metadata is refreshed
+    pub async fn recheck(&self, path: &PathBuf) -> io::Result<Meta> {
+        match self.meta {
+            Some(ref meta) => {
+                meta.invalidate(path).await;
+                meta.metadata(path).await
+            }
+            None => Meta::from_path(path).await,
+        }
     }
 }
 
 /// File cache
+#[derive(Clone)]
 pub struct FileCache {
-    cache: Cache<PathBuf, Content>,
-    tx: mpsc::Sender<PathBuf>,
+    cache: Store,
+    invalidation: Invalidation,
+    tx: mpsc::Sender<(PathBuf, u64)>,
     size: u64,
+    missing_grace: Duration,
+    missing_served: Arc<AtomicU64>, // responses of cached files deleted on disk
+    mmap_max: u64,
+    stream_threshold: u64,
+    read_buffer: usize,
+    events: Events,
 }
 
 impl FileCache {
-    pub fn new(config: FileCacheConfig) -> Self {
+    pub fn new(config: FileCacheConfig, events: Events) -> Self {
         // cache size in bytes
         let size = config.size * 1024 * 1024;
+        let mmap_max = config.mmap_max * 1024 * 1024;
+        let stream_threshold = config.stream_threshold * 1024 * 1024;
+        let read_buffer = config.read_buffer;
+        // variants share the cache size, the total stays in the budget
+        let variants_size = size / VARIANTS_SHARE;
+        let entries_size = size - variants_size;
         // build cache
         let cache = Cache::builder()
             // closure to calculate item size
             .weigher(|key: &PathBuf, value: &Content| -> u32 {
-                if value.meta.len() > u32::MAX as u64 {
+                if value.hash.is_some() {
+                    SHARED_WEIGHT
+                } else if value.meta.len() > u32::MAX as u64 {
                     error!(
                         "file size for caching exceeds 4G! file: {}, size: {}",
                         key.to_string_lossy(),
@@ -197,56 +1165,247 @@ impl FileCache {
                 }
             })
             // max cache size
-            .max_capacity(size)
+            .max_capacity(entries_size)
             .build();
+        let bodies = config.dedup.then(|| {
+            Cache::builder()
+                .weigher(|_: &u64, body: &Bytes| body.len().try_into().unwrap_or(u32::MAX))
+                .max_capacity(entries_size)
+                .build()
+        });
+        let variants = moka::sync::Cache::builder()
+            .weigher(|_: &(PathBuf, Encoding), body: &Bytes| {
+                body.len().try_into().unwrap_or(u32::MAX)
+            })
+            .max_capacity(variants_size)
+            .build();
+        let cache = Store {
+            paths: cache,
+            variants,
+            bodies,
+            pinned: Arc::new(RwLock::new(Pinned {
+                budget: config.pin_budget * 1024 * 1024,
+                ..Default::default()
+            })),
+            hasher: RandomState::new(),
+            frozen: Arc::default(),
+            missing: (config.missing_grace > 0).then(|| {
+                Cache::builder()
+                    .max_capacity(10_000)
+                    .time_to_live(MISSING_TTL)
+                    .build()
+            }),
+        };
 
         // share same cache with the detached task (this is cheap operation)
-        let cache_rx = cache.clone();
-        let (tx, mut rx) = mpsc::channel(500);
-
-        // spawn a detached async task
+        let (tx, rx) = mpsc::channel::<(PathBuf, u64)>(LOAD_QUEUE);
+        let loader = Loader {
+            store: cache.clone(),
+            events: events.clone(),
+            read_buffer,
+        };
+        task::spawn(watch_full(cache.clone(), size, events.clone(), tx.downgrade()));
         // task ended when the channel has been closed
-        task::spawn(async move {
-            while let Some(path) = rx.recv().await {
-                // check cache for the path
-                if cache_rx.get(&path).is_some() {
-                    // already in cache, skip
-                    continue;
-                }
-                // load content and insert to cache
-                match Content::from_file(&path).await {
-                    Ok(cnt) => cache_rx.insert(path, cnt),
-                    Err(err) => {
-                        error!("cache file loading error: {}", err)
-                    }
-                }
-            }
-            debug!("cache file upload task completed");
-        });
+        task::spawn(loader.run(rx, config.load_concurrency.max(1)));
+
+        let invalidation = Invalidation {
+            store: cache.clone(),
+            meta: None,
+            events: events.clone(),
+        };
+        FileCache {
+            cache,
+            invalidation,
+            tx,
+            size,
+            missing_grace: Duration::from_secs(config.missing_grace),
+            missing_served: Arc::default(),
+            mmap_max,
+            stream_threshold,
+            read_buffer,
+            events,
+        }
+    }
 
-        FileCache { cache, tx, size }
+    /// Invalidate metadata of the paths along with the content
+    pub fn with_meta(mut self, meta: &MetaCache) -> Self {
+        self.invalidation.meta = Some(meta.clone());
+        self
     }
 
-    /// Schedule file save to cache
-    pub fn insert(&self, path: &Path) -> Result<(), mpsc::error::TrySendError<PathBuf>> {
+    /// Invalidation shared with the metadata cache
+    pub fn invalidation(&self) -> &Invalidation {
+        &self.invalidation
+    }
+
+    /// Schedule file save to cache, smaller files are loaded first
+    pub fn insert(
+        &self,
+        path: &Path,
+        len: u64,
+    ) -> Result<(), mpsc::error::TrySendError<(PathBuf, u64)>> {
         // fails if no capacity in the channel
-        self.tx.try_send(path.to_path_buf())
+        self.tx.try_send((path.to_path_buf(), len))
+    }
+
+    /// Save content to cache immediately
+    pub async fn put(&self, path: PathBuf, content: Content) {
+        self.events.send(Event::CacheInsert {
+            path: path.to_string_lossy().into_owned(),
+            bytes: content.meta.len(),
+        });
+        self.cache.insert(path, content).await;
     }
 
     /// Get cached content
-    pub fn get(&self, path: &PathBuf) -> Option<Content> {
-        self.cache.get(path)
+    #[tracing::instrument(name = "cache_get", skip_all)]
+    pub async fn get(&self, path: &PathBuf) -> Option<Content> {
+        self.cache.get(path).await
+    }
+
+    /// Is serving cached files deleted on disk enabled?
+    pub fn has_missing_grace(&self) -> bool {
+        self.cache.missing.is_some()
+    }
+
+    /// Cached content of the file missing on disk, served for the grace
+    /// period from the first failed lookup and dropped after it
+    pub async fn get_missing(&self, path: &PathBuf) -> Option<Content> {
+        let missing = self.cache.missing.as_ref()?;
+        let since = missing.get_with(path.clone(), async { Instant::now() }).await;
+        if since.elapsed() >= self.missing_grace {
+            missing.invalidate(path).await;
+            self.invalidation.invalidate(path).await;
+            return None;
+        }
+        let content = self.cache.get(path).await?;
+        warn!("serving cached {:?} missing on disk", path);
+        self.missing_served.fetch_add(1, Ordering::Relaxed);
+        Some(content)
+    }
+
+    /// Responses of cached files missing on disk
+    pub fn missing_served(&self) -> u64 {
+        self.missing_served.load(Ordering::Relaxed)
+    }
+
+    /// Is the file cached?
+    pub fn contains(&self, path: &PathBuf) -> bool {
+        self.cache.contains(path)
+    }
+
+    /// Files waiting in the queue for the loader task
+    pub fn queue_depth(&self) -> usize {
+        self.tx.max_capacity() - self.tx.capacity()
+    }
+
+    /// Load the file and keep it in memory until restart, false if over the pin budget
+    pub async fn pin(&self, path: PathBuf) -> io::Result<bool> {
+        if self.cache.is_frozen(&path) {
+            return Ok(false);
+        }
+        let cnt = Content::from_file_buffered(&path, self.read_buffer).await?;
+        Ok(self.cache.pin(path, cnt).await)
+    }
+
+    /// Move cached entries of the directory to the frozen snapshot and reject
+    /// their refreshes, returns snapshot entries and bytes, `None` if already frozen
+    pub async fn freeze(&self, dir: &Path) -> Option<(u64, u64)> {
+        match self.cache.frozen.write().unwrap().entry(dir.to_path_buf()) {
+            Entry::Occupied(_) => return None,
+            Entry::Vacant(entry) => entry.insert(Freeze::default()),
+        };
+        let paths: Vec<PathBuf> = self
+            .cache
+            .paths
+            .iter()
+            .map(|(key, _)| key.as_ref().clone())
+            .filter(|x| x.starts_with(dir))
+            .collect();
+        let mut freeze = Freeze::default();
+        for path in paths {
+            if let Some(cnt) = self.cache.get(&path).await {
+                freeze.entries.insert(path, cnt);
+            }
+        }
+        let entries = freeze.entries.len() as u64;
+        let bytes = freeze.entries.values().map(|x| x.body.len() as u64).sum();
+        let moved: Vec<PathBuf> = freeze.entries.keys().cloned().collect();
+        self.cache.frozen.write().unwrap().insert(dir.to_path_buf(), freeze);
+        // snapshot entries are served before the cache, drop the evictable copies
+        for path in &moved {
+            self.cache.paths.invalidate(path).await;
+        }
+        info!("frozen {:?}, {} entries, {} bytes", dir, entries, bytes);
+        Some((entries, bytes))
+    }
+
+    /// Drop the freeze of the directory, purge its entries and reload the
+    /// frozen ones from disk, returns purged and queued entries
+    pub async fn unfreeze(&self, dir: &Path) -> Option<(u64, u64)> {
+        let freeze = self.cache.frozen.write().unwrap().remove(dir)?;
+        let purged = freeze.entries.len() as u64 + self.purge(dir).await;
+        let queued = freeze
+            .entries
+            .iter()
+            .filter(|(path, cnt)| self.insert(path, cnt.meta.len()).is_ok())
+            .count() as u64;
+        info!("unfrozen {:?}, {} entries purged, {} queued", dir, purged, queued);
+        Some((purged, queued))
     }
 
-    /// Invalidate file in ca
-    pub fn invalidate(&self, path: &PathBuf) {
-        self.cache.invalidate(path)
+    /// Is the file under a frozen model directory?
+    pub fn is_frozen(&self, path: &Path) -> bool {
+        self.cache.is_frozen(path)
+    }
+
+    /// Total size of pinned entries in bytes
+    pub fn pinned_size(&self) -> u64 {
+        self.cache.pinned.read().unwrap().bytes
+    }
+
+    /// Invalidate file in cache and its metadata
+    pub async fn invalidate(&self, path: &PathBuf) {
+        self.invalidation.invalidate(path).await
+    }
+
+    /// Invalidate cached entries with the path prefix, returns entries count
+    pub async fn purge(&self, prefix: &Path) -> u64 {
+        self.invalidation.purge(prefix).await
     }
 
     /// Cache size in bytes
     pub fn size(&self) -> u64 {
         self.size
     }
+
+    /// Can the content of this size be loaded to the cache?
+    pub fn fits(&self, len: u64) -> bool {
+        // u32::MAX is the cache weigher limit
+        len <= self.size
+            && len <= u32::MAX as u64
+            && (self.stream_threshold == 0 || len <= self.stream_threshold)
+    }
+
+    /// File read chunk size in Kbytes
+    pub fn read_buffer(&self) -> usize {
+        self.read_buffer
+    }
+
+    /// Max size of memory-mapped files in bytes
+    pub fn mmap_max(&self) -> u64 {
+        self.mmap_max
+    }
+
+    /// Number of cached entries
+    pub fn entry_count(&self) -> u64 {
+        self.cache.paths.entry_count()
+    }
+
+    /// Total size of cached entries in bytes
+    pub fn weighted_size(&self) -> u64 {
+        self.cache.weighted_size()
+    }
 }
 
 #[cfg(test)]
@@ -258,12 +1417,38 @@ mod test {
     use std::time::Duration;
     use tokio::time::sleep;
 
+    #[tokio::test]
+    async fn load_order() {
+        let events = Events::new(16);
+        let mut rx = events.subscribe();
+        let config = FileCacheConfig {
+            load_concurrency: 1,
+            ..Default::default()
+        };
+        let cache = FileCache::new(config, events);
+        // queued before the loader runs, the first one takes the only slot
+        for (path, len) in [("README.md", 1), ("LICENSE", 100), ("Cargo.toml", 10)] {
+            cache.insert(Path::new(path), len).unwrap();
+        }
+        cache.insert(Path::new("README.md"), 1).unwrap();
+        let mut loaded = Vec::new();
+        while loaded.len() < 3 {
+            if let Event::CacheInsert { path, .. } = rx.recv().await.unwrap() {
+                loaded.push(path);
+            }
+        }
+        assert_eq!(loaded, ["README.md", "Cargo.toml", "LICENSE"]);
+        sleep(Duration::from_millis(100)).await;
+        cache.cache.run_pending_tasks().await;
+        assert_eq!(cache.entry_count(), 3);
+    }
+
     #[tokio::test]
     async fn content_from_file() {
         let path = "README.md";
 
         let cnt = Content::from_file(path).await.unwrap();
-        println!("{} bytes read, type: {:?}", cnt.meta.len(), cnt.mime_type,);
+        println!("{} bytes read, path: {:?}", cnt.meta.len(), cnt.path);
 
         let mut r = cnt.body.reader();
         let mut dst1 = Vec::new();
@@ -280,12 +1465,12 @@ mod test {
     async fn file_cache() {
         let path = PathBuf::from("README.md");
 
-        let cache = FileCache::new(FileCacheConfig::default());
-        cache.insert(&path).unwrap();
+        let cache = FileCache::new(FileCacheConfig::default(), Events::default());
+        cache.insert(&path, 0).unwrap();
         // ...starting async file reading...
         // delay before get back content
         sleep(Duration::from_millis(100)).await;
-        let cnt = cache.get(&path).unwrap();
+        let cnt = cache.get(&path).await.unwrap();
 
         let mut r = cnt.body.reader();
         let mut dst1 = Vec::new();
@@ -298,11 +1483,40 @@ mod test {
         assert_eq!(dst1, dst2);
     }
 
+    #[tokio::test]
+    async fn missing_grace() {
+        let path = PathBuf::from("deleted/tileset.json");
+        let content = Content::new(path.clone(), Meta::new(3, None, false), Bytes::from("abc"));
+        let config = FileCacheConfig {
+            missing_grace: 1,
+            ..Default::default()
+        };
+        let mut cache = FileCache::new(config, Events::default());
+        cache.missing_grace = Duration::from_millis(100);
+        cache.put(path.clone(), content.clone()).await;
+
+        // served during the grace period, dropped after it
+        assert_eq!(cache.get_missing(&path).await.unwrap().body, "abc");
+        assert_eq!(cache.missing_served(), 1);
+        sleep(Duration::from_millis(150)).await;
+        assert!(cache.get_missing(&path).await.is_none());
+        assert!(cache.get(&path).await.is_none());
+
+        // file found and cached again gets a new grace period
+        cache.put(path.clone(), content.clone()).await;
+        assert!(cache.get_missing(&path).await.is_some());
+
+        // deleted while the request metadata differs from the cached one
+        let meta = Meta::new(4, None, false);
+        let f = CachedNamedFile::open_with_cache(&path, &meta, &cache).await.unwrap();
+        assert!(matches!(f, CachedNamedFile::Missing(_)));
+    }
+
     #[tokio::test]
     async fn cached_named_file() {
         let path = PathBuf::from("README.md");
         let meta = Meta::from_path(&path).await.unwrap();
-        let cache = FileCache::new(FileCacheConfig::default());
+        let cache = FileCache::new(FileCacheConfig::default(), Events::default());
         let mut buf = (Vec::new(), Vec::new(), Vec::new(), Vec::new());
 
         // get from file
@@ -310,8 +1524,8 @@ mod test {
             .await
             .unwrap()
         {
-            CachedNamedFile::File(mut f, _) => f.read_to_end(&mut buf.0).await.unwrap(),
-            CachedNamedFile::Cached(_) => panic!("named file expected!"),
+            CachedNamedFile::File(mut f, ..) => f.read_to_end(&mut buf.0).await.unwrap(),
+            _ => panic!("named file expected!"),
         };
 
         // delay and get from cache
@@ -320,21 +1534,21 @@ mod test {
             .await
             .unwrap()
         {
-            CachedNamedFile::File(..) => panic!("cached expected!"),
             CachedNamedFile::Cached(c) => c.body.reader().read_to_end(&mut buf.1).unwrap(),
+            _ => panic!("cached expected!"),
         };
 
         assert_ne!(buf.0.len(), 0);
         assert_eq!(buf.0, buf.1);
 
-        // change metadata and get from file, now we invalidate the cache
+        // stale metadata is rechecked, the file is unchanged and still cached
         let meta2 = Meta::from_path(&PathBuf::from("LICENSE")).await.unwrap();
         match CachedNamedFile::open_with_cache(&path, &meta2, &cache)
             .await
             .unwrap()
         {
-            CachedNamedFile::File(mut f, _) => f.read_to_end(&mut buf.2).await.unwrap(),
-            CachedNamedFile::Cached(_) => panic!("named file expected!"),
+            CachedNamedFile::Cached(c) => c.body.reader().read_to_end(&mut buf.2).unwrap(),
+            _ => panic!("cached expected!"),
         };
 
         // delay and get again from cache
@@ -343,11 +1557,431 @@ mod test {
             .await
             .unwrap()
         {
-            CachedNamedFile::File(..) => panic!("cached expected!"),
             CachedNamedFile::Cached(c) => c.body.reader().read_to_end(&mut buf.3).unwrap(),
+            _ => panic!("cached expected!"),
         };
 
         assert_ne!(buf.2.len(), 0);
         assert_eq!(buf.2, buf.3);
     }
+
+    #[tokio::test]
+    async fn dedup() {
+        let meta = Meta::from_path(&PathBuf::from("README.md")).await.unwrap();
+        let body = Bytes::from(vec![0u8; meta.len() as usize]);
+        let config = FileCacheConfig {
+            dedup: true,
+            ..Default::default()
+        };
+        let cache = FileCache::new(config, Events::default());
+        for name in ["a/water.b3dm", "b/water.b3dm"] {
+            let body = Bytes::copy_from_slice(&body);
+            cache.put(name.into(), Content::new(name.into(), meta.clone(), body)).await;
+        }
+
+        // both paths reference the same buffer stored once
+        let a = cache.get(&"a/water.b3dm".into()).await.unwrap();
+        let b = cache.get(&"b/water.b3dm".into()).await.unwrap();
+        assert_eq!(a.body, body);
+        assert_eq!(a.body.as_ptr(), b.body.as_ptr());
+        cache.cache.run_pending_tasks().await;
+        assert_eq!(cache.entry_count(), 2);
+        assert_eq!(cache.weighted_size(), meta.len() + 2 * SHARED_WEIGHT as u64);
+
+        // evicted body drops the path entries referencing it
+        cache.cache.bodies.as_ref().unwrap().invalidate_all();
+        assert!(cache.get(&"a/water.b3dm".into()).await.is_none());
+        assert!(!cache.contains(&"a/water.b3dm".into()));
+    }
+
+    #[tokio::test]
+    async fn coupled_invalidation() {
+        let path = std::env::temp_dir().join("rtiles-invalidation-test.b3dm");
+        std::fs::write(&path, "old").unwrap();
+        let metacache = MetaCache::new(Default::default());
+        let cache =
+            FileCache::new(FileCacheConfig::default(), Events::default()).with_meta(&metacache);
+        let old = metacache.metadata(&path).await.unwrap();
+
+        // content loaded after the file change is served despite stale metadata
+        std::fs::write(&path, "newer").unwrap();
+        cache.put(path.clone(), Content::from_file(&path).await.unwrap()).await;
+        match CachedNamedFile::open_with_cache(&path, &old, &cache).await.unwrap() {
+            CachedNamedFile::Cached(c) => assert_eq!(c.body, "newer"),
+            _ => panic!("cached expected!"),
+        };
+        assert_eq!(metacache.metadata(&path).await.unwrap().len(), 5);
+
+        // content invalidation drops the metadata too
+        std::fs::write(&path, "newest").unwrap();
+        cache.invalidate(&path).await;
+        assert_eq!(metacache.metadata(&path).await.unwrap().len(), 6);
+        assert!(cache.get(&path).await.is_none());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn pinning() {
+        let path = std::env::temp_dir().join("rtiles-pin-test.b3dm");
+        std::fs::write(&path, "pinned").unwrap();
+        let config = FileCacheConfig {
+            size: 1,
+            pin_budget: 1,
+            ..Default::default()
+        };
+        let cache = FileCache::new(config, Events::default());
+        assert!(cache.pin(path.clone()).await.unwrap());
+        assert_eq!(cache.pinned_size(), 6);
+
+        // pinned entries are not evicted with the cache entries
+        cache.cache.paths.invalidate_all();
+        assert_eq!(cache.get(&path).await.unwrap().body, "pinned");
+
+        // invalidation keeps the pin, the next load is pinned again
+        std::fs::write(&path, "changed").unwrap();
+        cache.invalidate(&path).await;
+        assert!(!cache.contains(&path));
+        cache.put(path.clone(), Content::from_file(&path).await.unwrap()).await;
+        cache.cache.paths.invalidate_all();
+        assert_eq!(cache.get(&path).await.unwrap().body, "changed");
+        assert_eq!(cache.pinned_size(), 7);
+
+        // over the budget
+        let big = PathBuf::from("LICENSE");
+        let config = FileCacheConfig {
+            pin_budget: 0,
+            ..Default::default()
+        };
+        let cache = FileCache::new(config, Events::default());
+        assert!(!cache.pin(big.clone()).await.unwrap());
+        assert!(!cache.contains(&big));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn freeze() {
+        let dir = PathBuf::from("frozen/model");
+        let path = dir.join("tileset.json");
+        let content = |body: &'static str| {
+            let meta = Meta::new(body.len() as u64, None, false);
+            Content::new(path.clone(), meta, Bytes::from(body))
+        };
+        // snapshot is held without the pin budget
+        let cache = FileCache::new(FileCacheConfig::default(), Events::default());
+        cache.put(path.clone(), content("old")).await;
+        assert_eq!(cache.freeze(&dir).await, Some((1, 3)));
+        assert_eq!(cache.freeze(&dir).await, None);
+        assert!(cache.is_frozen(&path));
+
+        // refreshes are rejected while frozen
+        cache.invalidate(&path).await;
+        cache.put(path.clone(), content("new")).await;
+        assert_eq!(cache.get(&path).await.unwrap().body, "old");
+
+        // missing entries are not loaded while frozen
+        assert!(cache.get(&dir.join("other.json")).await.is_none());
+
+        // snapshot is dropped, the missing file is not reloaded
+        assert_eq!(cache.unfreeze(&dir).await, Some((1, 1)));
+        assert!(cache.get(&path).await.is_none());
+        assert_eq!(cache.unfreeze(&dir).await, None);
+    }
+
+    #[tokio::test]
+    async fn gzip_detection() {
+        let path = std::env::temp_dir().join("rtiles-gzip-test.json");
+        std::fs::write(&path, [0x1f, 0x8b, 0x08, 0x00]).unwrap();
+        let meta = Meta::from_path(&path).await.unwrap();
+        let cache = FileCache::new(FileCacheConfig::default(), Events::default());
+
+        // plain extension is served from file without detection
+        match CachedNamedFile::open_with_cache(&path, &meta, &cache).await.unwrap() {
+            CachedNamedFile::File(..) => (),
+            _ => panic!("named file expected!"),
+        };
+
+        let cache = FileCache::new(FileCacheConfig::default(), Events::default());
+        match CachedNamedFile::open_with_detection(&path, &meta, &cache, true)
+            .await
+            .unwrap()
+        {
+            CachedNamedFile::Loaded(c, _) => assert!(c.detect_gzip && is_gzip(&c.body)),
+            _ => panic!("loaded content expected!"),
+        };
+        // detection flag is kept in the cache
+        assert!(cache.get(&path).await.unwrap().detect_gzip);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    fn gzip_tile_path() -> PathBuf {
+        std::env::temp_dir().join("rtiles-variants-test.pbf")
+    }
+
+    // rocket declares an unused uri macro reexport per route
+    #[allow(unused_imports)]
+    mod handlers {
+        use super::*;
+
+        #[get("/tile")]
+        pub async fn gzip_tile(cache: &rocket::State<FileCache>) -> CachedNamedFile {
+            CachedNamedFile::Cached(Box::new(cache.get(&gzip_tile_path()).await.unwrap()))
+        }
+
+        #[get("/<cached>")]
+        pub async fn license(cached: bool) -> CachedNamedFile {
+            let cnt = Content::from_file("LICENSE").await.unwrap();
+            if cached {
+                CachedNamedFile::Cached(Box::new(cnt))
+            } else {
+                CachedNamedFile::open("LICENSE", Some(cnt.meta())).await.unwrap()
+            }
+        }
+    }
+
+    #[rocket::async_test]
+    async fn encoding_variants() {
+        use flate2::{write::GzEncoder, Compression};
+        use rocket::local::asynchronous::Client;
+        use std::io::Write;
+
+        let path = gzip_tile_path();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"vector tile").unwrap();
+        std::fs::write(&path, encoder.finish().unwrap()).unwrap();
+
+        let cache = FileCache::new(FileCacheConfig::default(), Events::default());
+        cache.put(path.clone(), Content::from_file(&path).await.unwrap()).await;
+        let rocket = rocket::build()
+            .manage(cache.clone())
+            .mount("/", routes![handlers::gzip_tile]);
+        let client = Client::tracked(rocket).await.unwrap();
+
+        // decoded once, then served from the identity variant
+        let key = (path.clone(), Encoding::Identity);
+        for _ in 0..2 {
+            let res = client.get("/tile").dispatch().await;
+            assert_eq!(res.headers().get_one("Content-Encoding"), None);
+            assert_eq!(res.into_string().await.unwrap(), "vector tile");
+            assert!(cache.cache.variants.contains_key(&key));
+        }
+        let res = client
+            .get("/tile")
+            .header(Header::new("Accept-Encoding", "gzip"))
+            .dispatch()
+            .await;
+        assert_eq!(res.headers().get_one("Content-Encoding"), Some("gzip"));
+
+        // replaced content drops its variants
+        cache.put(path.clone(), Content::from_file(&path).await.unwrap()).await;
+        assert!(!cache.cache.variants.contains_key(&key));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn mapped_file() {
+        let path = PathBuf::from("README.md");
+        let meta = Meta::from_path(&path).await.unwrap();
+        // zero cache size, every file bypasses the cache
+        let config = FileCacheConfig {
+            size: 0,
+            mmap_max: 1,
+            ..Default::default()
+        };
+        let cache = FileCache::new(config, Events::default());
+        match CachedNamedFile::open_with_cache(&path, &meta, &cache)
+            .await
+            .unwrap()
+        {
+            CachedNamedFile::Loaded(c, _) => {
+                assert_eq!(c.body.as_ref(), std::fs::read(&path).unwrap())
+            }
+            _ => panic!("mapped content expected!"),
+        };
+        assert!(cache.get(&path).await.is_none());
+
+        // stale metadata is rejected, fallback to named file
+        let meta2 = Meta::from_path(&PathBuf::from("LICENSE")).await.unwrap();
+        assert!(map_file(path.clone(), meta2.clone()).await.is_err());
+        match CachedNamedFile::open_with_cache(&path, &meta2, &cache)
+            .await
+            .unwrap()
+        {
+            CachedNamedFile::File(..) => (),
+            _ => panic!("named file expected!"),
+        };
+    }
+
+    #[tokio::test]
+    async fn stream_threshold() {
+        let config = FileCacheConfig {
+            stream_threshold: 1,
+            read_buffer: 1,
+            ..Default::default()
+        };
+        let cache = FileCache::new(config, Events::default());
+        assert!(cache.fits(1024 * 1024));
+        assert!(!cache.fits(1024 * 1024 + 1));
+
+        // small chunks read the whole file
+        let cnt = Content::from_file_buffered("LICENSE", cache.read_buffer())
+            .await
+            .unwrap();
+        assert_eq!(cnt.body.as_ref(), std::fs::read("LICENSE").unwrap());
+    }
+
+    #[test]
+    fn gzip_encoding() {
+        let accepted = |v: &str| gzip_accepted(std::iter::once(v));
+        assert!(accepted("gzip, deflate, br"));
+        assert!(accepted("br;q=1.0, gzip;q=0.8"));
+        assert!(accepted("*"));
+        assert!(!accepted("gzip;q=0"));
+        assert!(!accepted("gzip; q=0.0, *"));
+        assert!(!accepted("*;q=0"));
+        assert!(!accepted("identity"));
+        assert!(!accepted("gzipx"));
+        assert!(!gzip_accepted(std::iter::empty()));
+    }
+
+    #[test]
+    fn byte_ranges() {
+        assert_eq!(parse_range("bytes=0-9", 100), ByteRange::Partial(0, 9));
+        assert_eq!(parse_range("bytes=90-200", 100), ByteRange::Partial(90, 99));
+        assert_eq!(parse_range("bytes=50-", 100), ByteRange::Partial(50, 99));
+        assert_eq!(parse_range("bytes=-10", 100), ByteRange::Partial(90, 99));
+        assert_eq!(parse_range("bytes=-200", 100), ByteRange::Partial(0, 99));
+        assert_eq!(parse_range("bytes=100-", 100), ByteRange::Unsatisfiable);
+        assert_eq!(parse_range("bytes=-0", 100), ByteRange::Unsatisfiable);
+        assert_eq!(parse_range("bytes=0-", 0), ByteRange::Unsatisfiable);
+        // ignored ranges
+        assert_eq!(parse_range("bytes=9-0", 100), ByteRange::Full);
+        assert_eq!(parse_range("bytes=0-1,a-b", 100), ByteRange::Full);
+        assert_eq!(parse_range("items=0-1", 100), ByteRange::Full);
+        assert_eq!(parse_range("bytes=a-b", 100), ByteRange::Full);
+        let many = vec!["0-1"; MAX_RANGES + 1].join(",");
+        assert_eq!(parse_range(&format!("bytes={many}"), 100), ByteRange::Full);
+        // repeated ranges summed over the length
+        assert_eq!(parse_range("bytes=0-,0-", 100), ByteRange::Full);
+        assert_eq!(parse_range("bytes=0-59,40-99", 100), ByteRange::Full);
+    }
+
+    #[test]
+    fn multi_ranges() {
+        assert_eq!(
+            parse_range("bytes=0-1, 5-6,-2", 100),
+            ByteRange::Multi(vec![(0, 1), (5, 6), (98, 99)])
+        );
+        // overlapping and adjacent parts are coalesced in ascending order
+        assert_eq!(
+            parse_range("bytes=50-59,0-9,5-14,15-19", 100),
+            ByteRange::Multi(vec![(0, 19), (50, 59)])
+        );
+        assert_eq!(parse_range("bytes=0-9,0-9", 100), ByteRange::Partial(0, 9));
+        // unsatisfiable parts are skipped
+        assert_eq!(
+            parse_range("bytes=0-1,200-300", 100),
+            ByteRange::Partial(0, 1)
+        );
+        assert_eq!(
+            parse_range("bytes=100-,200-300", 100),
+            ByteRange::Unsatisfiable
+        );
+
+        let body = Bytes::from_static(b"0123456789");
+        let out = multipart(&body, &[(0, 1), (8, 9)], &ContentType::JSON, "b");
+        assert_eq!(
+            out.as_ref(),
+            b"\r\n--b\r\nContent-Type: application/json\r\nContent-Range: bytes 0-1/10\r\n\r\n01\
+              \r\n--b\r\nContent-Type: application/json\r\nContent-Range: bytes 8-9/10\r\n\r\n89\
+              \r\n--b--\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn cache_status() {
+        let path = PathBuf::from("LICENSE");
+        let meta = Meta::from_path(&path).await.unwrap();
+        let cache = FileCache::new(FileCacheConfig::default(), Events::default());
+        let status = |f: CachedNamedFile| f.cache_status();
+
+        let f = CachedNamedFile::open_with_cache(&path, &meta, &cache).await.unwrap();
+        assert_eq!(status(f), "rtiles; fwd=uri-miss; stored; detail=disk");
+        cache.put(path.clone(), Content::from_file(&path).await.unwrap()).await;
+        let f = CachedNamedFile::open_with_cache(&path, &meta, &cache).await.unwrap();
+        assert_eq!(status(f), "rtiles; hit");
+
+        // cached entry differs from the file
+        let other = Content::new(path.clone(), Meta::new(1, None, false), Bytes::from("x"));
+        cache.put(path.clone(), other).await;
+        let f = CachedNamedFile::open_with_cache(&path, &meta, &cache).await.unwrap();
+        assert_eq!(status(f), "rtiles; fwd=stale; stored; detail=disk");
+
+        let config = FileCacheConfig {
+            size: 0,
+            ..Default::default()
+        };
+        let cache = FileCache::new(config, Events::default());
+        let f = CachedNamedFile::open_with_cache(&path, &meta, &cache).await.unwrap();
+        assert_eq!(status(f), "rtiles; fwd=bypass; detail=stream");
+    }
+
+    #[rocket::async_test]
+    async fn conditional_requests() {
+        use rocket::local::asynchronous::Client;
+
+        let rocket = rocket::build().mount("/", routes![handlers::license]);
+        let client = Client::tracked(rocket)
+            .await
+            .unwrap();
+        let license = std::fs::read("LICENSE").unwrap();
+        // file and cached content respond the same way
+        for uri in ["/false", "/true"] {
+            let res = client.get(uri).dispatch().await;
+            let etag = res.headers().get_one("ETag").unwrap().to_owned();
+            let modified = res.headers().get_one("Last-Modified").unwrap().to_owned();
+
+            let res = client
+                .get(uri)
+                .header(Header::new("If-None-Match", etag.clone()))
+                .dispatch()
+                .await;
+            assert_eq!(res.status(), Status::NotModified);
+            assert_eq!(res.headers().get_one("ETag"), Some(etag.as_str()));
+            let res = client
+                .get(uri)
+                .header(Header::new("If-Modified-Since", modified.clone()))
+                .dispatch()
+                .await;
+            assert_eq!(res.status(), Status::NotModified);
+            let res = client
+                .get(uri)
+                .header(Header::new("If-None-Match", "\"other\""))
+                .dispatch()
+                .await;
+            assert_eq!(res.status(), Status::Ok);
+
+            // weak etag is not a valid `If-Range` validator
+            assert!(etag.starts_with("W/"));
+            let res = client
+                .get(uri)
+                .header(Header::new("Range", "bytes=10-19"))
+                .header(Header::new("If-Range", etag))
+                .dispatch()
+                .await;
+            assert_eq!(res.status(), Status::Ok);
+
+            let res = client
+                .get(uri)
+                .header(Header::new("Range", "bytes=10-19"))
+                .header(Header::new("If-Range", modified))
+                .dispatch()
+                .await;
+            assert_eq!(res.status(), Status::PartialContent);
+            assert_eq!(
+                res.headers().get_one("Content-Range"),
+                Some(format!("bytes 10-19/{}", license.len()).as_str())
+            );
+            assert_eq!(res.into_bytes().await.unwrap(), &license[10..20]);
+        }
+    }
 }
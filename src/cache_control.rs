@@ -0,0 +1,258 @@
+use regex::Regex;
+use rocket::http::ContentType;
+use rocket::request::Request;
+use rocket::response::{self, Responder};
+use rocket::serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::config::ModelConfig;
+use crate::Config;
+
+/// Max-age of immutable content, one year
+pub const IMMUTABLE_MAX_AGE: u32 = 365 * 24 * 60 * 60;
+
+/// File name pattern of content never changing under the same name, like hashed names
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct NamePattern(Regex);
+
+impl NamePattern {
+    /// Does the file name match the pattern?
+    pub fn matches(&self, file: &Path) -> bool {
+        file.file_name()
+            .and_then(|x| x.to_str())
+            .is_some_and(|x| self.0.is_match(x))
+    }
+}
+
+impl TryFrom<String> for NamePattern {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Regex::new(&s).map(NamePattern).map_err(|e| e.to_string())
+    }
+}
+
+impl From<NamePattern> for String {
+    fn from(x: NamePattern) -> Self {
+        x.0.as_str().to_owned()
+    }
+}
+
+/// `Cache-Control` directives of responses with a given content type
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct CachePolicy {
+    pub public: bool,                        // allow shared caches, private otherwise
+    pub max_age: Option<u32>,                // seconds, storage `max_age` if not set
+    pub s_maxage: Option<u32>,               // shared cache lifetime, seconds
+    pub stale_while_revalidate: Option<u32>, // seconds to serve stale while revalidating
+    pub immutable: bool,                     // content never changes under the same url
+    pub no_transform: bool,                  // forbid intermediaries to recompress or convert
+}
+
+impl CachePolicy {
+    /// Header value, `max_age` is used if not set by the policy
+    pub fn header_value(&self, max_age: u32) -> String {
+        let mut directives = vec![
+            if self.public { "public" } else { "private" }.to_owned(),
+            format!("max-age={}", self.max_age.unwrap_or(max_age)),
+        ];
+        if let Some(s) = self.s_maxage {
+            directives.push(format!("s-maxage={}", s));
+        }
+        if let Some(s) = self.stale_while_revalidate {
+            directives.push(format!("stale-while-revalidate={}", s));
+        }
+        if self.immutable {
+            directives.push("immutable".to_owned());
+        }
+        if self.no_transform {
+            directives.push("no-transform".to_owned());
+        }
+        directives.join(", ")
+    }
+}
+
+/// Find policy for the media type, `type/*` keys match any subtype
+pub fn policy<'a>(
+    policies: &'a HashMap<String, CachePolicy>,
+    ct: &ContentType,
+) -> Option<&'a CachePolicy> {
+    let find = |top: &str, sub: &str| {
+        policies.iter().find_map(|(key, policy)| {
+            let (t, s) = key.split_once('/')?;
+            (t.eq_ignore_ascii_case(top) && s.eq_ignore_ascii_case(sub)).then_some(policy)
+        })
+    };
+    find(ct.top().as_str(), ct.sub().as_str()).or_else(|| find(ct.top().as_str(), "*"))
+}
+
+/// Join `Vary` header values skipping duplicates
+pub fn merge_vary(values: &[&str]) -> String {
+    let mut names: Vec<&str> = Vec::new();
+    for name in values.iter().flat_map(|x| x.split(',')).map(str::trim) {
+        if !name.is_empty() && !names.iter().any(|x| x.eq_ignore_ascii_case(name)) {
+            names.push(name);
+        }
+    }
+    names.join(", ")
+}
+
+/// Response with `Vary` and the `Cache-Control` header chosen by its content type
+pub struct CacheControl<R> {
+    pub responder: R,
+    pub max_age: u32,                  // default max-age, seconds
+    pub max_age_override: Option<u32>, // model or extension setting, overrides the policy
+    pub public: Option<bool>,          // model setting, overrides the media type policy
+    pub no_transform: bool,
+    pub immutable: bool,   // file name matches the immutable pattern
+    pub vary_accept: bool, // model variants are chosen by `Accept`
+}
+
+impl<R> CacheControl<R> {
+    pub fn new(responder: R, max_age: u32) -> Self {
+        CacheControl {
+            responder,
+            max_age,
+            max_age_override: None,
+            public: None,
+            no_transform: false,
+            immutable: false,
+            vary_accept: false,
+        }
+    }
+
+    /// Apply model specific settings
+    pub fn for_model(self, model: &ModelConfig) -> Self {
+        CacheControl {
+            max_age_override: model.max_age.or(self.max_age_override),
+            public: model.public,
+            no_transform: model.no_transform,
+            vary_accept: !model.variants.is_empty(),
+            ..self
+        }
+    }
+
+    /// Mark content as immutable for a year if the file name matches the pattern
+    pub fn for_file(self, pattern: Option<&NamePattern>, file: &Path) -> Self {
+        CacheControl {
+            immutable: pattern.is_some_and(|x| x.matches(file)),
+            ..self
+        }
+    }
+
+    /// Override max-age of the media type policy if set
+    pub fn with_max_age(self, max_age: Option<u32>) -> Self {
+        CacheControl {
+            max_age_override: max_age.or(self.max_age_override),
+            ..self
+        }
+    }
+}
+
+impl<'r, R: Responder<'r, 'static>> Responder<'r, 'static> for CacheControl<R> {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let mut res = self.responder.respond_to(req)?;
+        let config = req.rocket().state::<Config<'_>>();
+
+        // response depends on the user session and maybe client capabilities
+        let mut vary: Vec<&str> = res.headers().get("Vary").collect();
+        if let Some(config) = config {
+            vary.extend(config.access.vary.iter().map(String::as_str));
+        }
+        if self.vary_accept {
+            vary.push("Accept");
+        }
+        let vary = merge_vary(&vary);
+        if !vary.is_empty() {
+            res.set_raw_header("Vary", vary);
+        }
+
+        let policies = config.map(|x| &x.cache_control);
+        let mut policy = match (policies, res.content_type()) {
+            (Some(policies), Some(ct)) => policy(policies, &ct).cloned(),
+            _ => None,
+        }
+        .unwrap_or_default();
+        // model settings take precedence
+        if let Some(public) = self.public {
+            policy.public = public;
+        }
+        if let Some(max_age) = self.max_age_override {
+            policy.max_age = Some(max_age);
+        }
+        // hashed names never change, no other setting applies
+        if self.immutable {
+            policy.max_age = Some(IMMUTABLE_MAX_AGE);
+            policy.immutable = true;
+        }
+        policy.no_transform |= self.no_transform;
+        res.set_raw_header("Cache-Control", policy.header_value(self.max_age));
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn directives() {
+        assert_eq!(
+            CachePolicy::default().header_value(1800),
+            "private, max-age=1800"
+        );
+        let policy = CachePolicy {
+            public: true,
+            max_age: Some(60),
+            s_maxage: Some(3600),
+            stale_while_revalidate: Some(600),
+            immutable: true,
+            no_transform: true,
+        };
+        assert_eq!(
+            policy.header_value(1800),
+            "public, max-age=60, s-maxage=3600, stale-while-revalidate=600, immutable, no-transform"
+        );
+    }
+
+    #[test]
+    fn vary() {
+        assert_eq!(
+            merge_vary(&["Accept-Encoding", "Cookie, Authorization", "cookie"]),
+            "Accept-Encoding, Cookie, Authorization"
+        );
+        assert_eq!(merge_vary(&[]), "");
+    }
+
+    #[test]
+    fn media_types() {
+        let public = CachePolicy {
+            public: true,
+            ..Default::default()
+        };
+        let immutable = CachePolicy {
+            immutable: true,
+            ..Default::default()
+        };
+        let policies: HashMap<String, CachePolicy> = [
+            ("application/json".to_owned(), public.clone()),
+            ("image/*".to_owned(), immutable.clone()),
+        ]
+        .into();
+        assert_eq!(policy(&policies, &ContentType::JSON), Some(&public));
+        assert_eq!(policy(&policies, &ContentType::PNG), Some(&immutable));
+        assert_eq!(policy(&policies, &ContentType::Binary), None);
+    }
+
+    #[test]
+    fn name_pattern() {
+        let pattern = NamePattern::try_from(r"\.[0-9a-f]{8,}\.\w+$".to_owned()).unwrap();
+        assert!(pattern.matches(Path::new("0/tile.3f2a9c1b.b3dm")));
+        assert!(!pattern.matches(Path::new("0/tile.b3dm")));
+        assert!(!pattern.matches(Path::new("0.3f2a9c1b.d/tileset.json")));
+        assert!(NamePattern::try_from("(".to_owned()).is_err());
+    }
+}
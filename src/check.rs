@@ -0,0 +1,67 @@
+use reqwest::Client;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::Config;
+
+/// Check that the storage root is a readable directory
+pub fn check_storage(root: &Path) -> Result<String, String> {
+    let entries = std::fs::read_dir(root).map_err(|e| format!("{}: {}", root.display(), e))?;
+    let objects = entries
+        .filter_map(Result::ok)
+        .filter(|x| x.path().is_dir())
+        .count();
+    Ok(format!("{}: {} objects", root.display(), objects))
+}
+
+/// Check that the auth server responds, any HTTP status is fine
+pub async fn check_auth(server: &str) -> Result<String, String> {
+    let client = Client::builder()
+        .timeout(Duration::from_secs(5))
+        .build()
+        .map_err(|e| e.to_string())?;
+    match client.get(server).send().await {
+        Ok(res) => Ok(format!("{}: {}", server, res.status())),
+        Err(err) => Err(format!("{}: {}", server, err)),
+    }
+}
+
+/// Run self checks and print results, returns true if all passed
+pub async fn run(config: &Config<'_>) -> bool {
+    let results = [
+        ("config", Ok("parsed".to_owned())),
+        ("storage", check_storage(&config.storage.root)),
+        ("auth", check_auth(&config.access.server.to_string()).await),
+    ];
+    let mut passed = true;
+    for (name, res) in results {
+        match res {
+            Ok(msg) => println!("ok    {:<8} {}", name, msg),
+            Err(msg) => {
+                println!("fail  {:<8} {}", name, msg);
+                passed = false;
+            }
+        }
+    }
+    passed
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn storage() {
+        let root = std::env::temp_dir().join(format!("rtiles-check-{}", std::process::id()));
+        assert!(check_storage(&root).is_err());
+        std::fs::create_dir_all(root.join("city")).unwrap();
+        assert!(check_storage(&root).unwrap().ends_with("1 objects"));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn auth_unreachable() {
+        // nothing listens on the discard port
+        assert!(check_auth("http://127.0.0.1:9").await.is_err());
+    }
+}
@@ -0,0 +1,357 @@
+/// Command line usage
+pub const USAGE: &str = "\
+usage: rtiles [command]
+
+commands:
+    serve    start the server (default)
+             [--dev-auth allow|deny|<object/model,object/*>] with embedded mock
+             auth server, for builds with the `dev-auth` feature
+    check    verify config, storage and auth server, exit with status
+    warm     request model tiles through the running server to fill its cache
+             --model <object/name> [--depth N] [--url <server>] [--token <session>]
+    bench    replay tile requests against the running server, report throughput,
+             latency and cache hit ratio
+             --model <object/name> [--concurrency N] [--requests N] [--depth N]
+             [--log <access log>] [--url <server>] [--token <session>]
+    replay   replay GET requests of the access log against the running server
+             <access log> [--speed X] original timing scaled by X, as fast as
+             possible if not set [--concurrency N] [--url <server>] [--token <session>]
+    stat     print traffic metrics from the admin API of the running server
+             <object> [model] [--url <server>] [--token <admin token>]
+    help     print this message";
+
+/// Cache warm params
+#[derive(Debug, PartialEq)]
+pub struct WarmArgs {
+    pub model: String,         // `object/name`
+    pub depth: u32,            // tileset tree depth to walk
+    pub url: Option<String>,   // server base url, from config if not set
+    pub token: Option<String>, // session id sent as `access_token` query param
+}
+
+/// Load test params
+#[derive(Debug, PartialEq)]
+pub struct BenchArgs {
+    pub model: String,         // `object/name`
+    pub concurrency: usize,    // parallel workers
+    pub requests: usize,       // total requests
+    pub depth: u32,            // tileset tree depth of the synthesized pattern
+    pub log: Option<String>,   // access log to replay recorded requests from
+    pub url: Option<String>,   // server base url, from config if not set
+    pub token: Option<String>, // session id sent as `access_token` query param
+}
+
+/// Access log replay params
+#[derive(Debug, PartialEq)]
+pub struct ReplayArgs {
+    pub log: String,           // access log file
+    pub speed: Option<f64>,    // original timing multiplier, no delays if not set
+    pub concurrency: usize,    // max requests in flight
+    pub url: Option<String>,   // server base url, from config if not set
+    pub token: Option<String>, // session id sent as `access_token` query param
+}
+
+/// Stats query params
+#[derive(Debug, PartialEq)]
+pub struct StatArgs {
+    pub object: String,
+    pub model: Option<String>,
+    pub url: Option<String>,   // admin base url, from config if not set
+    pub token: Option<String>, // admin token, from config if not set
+}
+
+/// Command line mode
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Serve,
+    DevServe(String), // serve with mock auth server policy
+    Check,
+    Warm(WarmArgs),
+    Bench(BenchArgs),
+    Replay(ReplayArgs),
+    Stat(StatArgs),
+    Help,
+}
+
+/// Parse `--name value` options
+fn options<I: Iterator<Item = String>>(
+    mut args: I,
+    names: &[&str],
+) -> Result<Vec<(String, String)>, String> {
+    let mut res = Vec::new();
+    while let Some(name) = args.next() {
+        let key = name
+            .strip_prefix("--")
+            .filter(|x| names.contains(x))
+            .ok_or_else(|| format!("unexpected argument: {}", name))?;
+        let value = args
+            .next()
+            .ok_or_else(|| format!("missing value for {}", name))?;
+        res.push((key.to_owned(), value));
+    }
+    Ok(res)
+}
+
+impl WarmArgs {
+    fn parse<I: Iterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut warm = WarmArgs {
+            model: String::new(),
+            depth: 3,
+            url: None,
+            token: None,
+        };
+        for (key, value) in options(args, &["model", "depth", "url", "token"])? {
+            match key.as_str() {
+                "model" => warm.model = value,
+                "depth" => {
+                    warm.depth = value
+                        .parse()
+                        .map_err(|_| format!("illegal depth: {}", value))?
+                }
+                "url" => warm.url = Some(value),
+                _ => warm.token = Some(value),
+            }
+        }
+        match warm.model.split_once('/') {
+            Some((object, name)) if !object.is_empty() && !name.is_empty() => Ok(warm),
+            _ => Err("--model <object/name> is required".to_owned()),
+        }
+    }
+}
+
+impl BenchArgs {
+    fn parse<I: Iterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut bench = BenchArgs {
+            model: String::new(),
+            concurrency: 64,
+            requests: 1000,
+            depth: 3,
+            log: None,
+            url: None,
+            token: None,
+        };
+        let names = ["model", "concurrency", "requests", "depth", "log", "url", "token"];
+        for (key, value) in options(args, &names)? {
+            let number = || {
+                value
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|x| *x > 0)
+                    .ok_or_else(|| format!("illegal {}: {}", key, value))
+            };
+            match key.as_str() {
+                "model" => bench.model = value,
+                "concurrency" => bench.concurrency = number()?,
+                "requests" => bench.requests = number()?,
+                "depth" => {
+                    bench.depth = value
+                        .parse()
+                        .map_err(|_| format!("illegal depth: {}", value))?
+                }
+                "log" => bench.log = Some(value),
+                "url" => bench.url = Some(value),
+                _ => bench.token = Some(value),
+            }
+        }
+        match bench.model.split_once('/') {
+            Some((object, name)) if !object.is_empty() && !name.is_empty() => Ok(bench),
+            _ => Err("--model <object/name> is required".to_owned()),
+        }
+    }
+}
+
+impl ReplayArgs {
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Self, String> {
+        let mut replay = ReplayArgs {
+            log: args
+                .next()
+                .filter(|x| !x.starts_with("--"))
+                .ok_or_else(|| "<access log> is required".to_owned())?,
+            speed: None,
+            concurrency: 64,
+            url: None,
+            token: None,
+        };
+        for (key, value) in options(args, &["speed", "concurrency", "url", "token"])? {
+            match key.as_str() {
+                "speed" => {
+                    replay.speed = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|x: &f64| *x > 0.0)
+                            .ok_or_else(|| format!("illegal speed: {}", value))?,
+                    )
+                }
+                "concurrency" => {
+                    replay.concurrency = value
+                        .parse()
+                        .ok()
+                        .filter(|x| *x > 0)
+                        .ok_or_else(|| format!("illegal concurrency: {}", value))?
+                }
+                "url" => replay.url = Some(value),
+                _ => replay.token = Some(value),
+            }
+        }
+        Ok(replay)
+    }
+}
+
+impl StatArgs {
+    fn parse<I: Iterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut args = args.peekable();
+        let mut positional = Vec::new();
+        while let Some(x) = args.next_if(|x| !x.starts_with("--")) {
+            positional.push(x);
+        }
+        let mut positional = positional.into_iter();
+        let mut stat = StatArgs {
+            object: positional
+                .next()
+                .ok_or_else(|| "<object> is required".to_owned())?,
+            model: positional.next(),
+            url: None,
+            token: None,
+        };
+        if let Some(x) = positional.next() {
+            return Err(format!("unexpected argument: {}", x));
+        }
+        for (key, value) in options(args, &["url", "token"])? {
+            match key.as_str() {
+                "url" => stat.url = Some(value),
+                _ => stat.token = Some(value),
+            }
+        }
+        Ok(stat)
+    }
+}
+
+impl Command {
+    /// Parse command line arguments without the program name
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut args = args.into_iter();
+        let cmd = match args.next().as_deref() {
+            None | Some("serve") => match options(args, &["dev-auth"])?.pop() {
+                Some((_, policy)) => return Ok(Command::DevServe(policy)),
+                None => return Ok(Command::Serve),
+            },
+            Some("--dev-auth") => match args.next() {
+                Some(policy) if args.next().is_none() => return Ok(Command::DevServe(policy)),
+                _ => return Err("usage: --dev-auth <policy>".to_owned()),
+            },
+            Some("check") => Command::Check,
+            Some("warm") => return WarmArgs::parse(args).map(Command::Warm),
+            Some("bench") => return BenchArgs::parse(args).map(Command::Bench),
+            Some("replay") => return ReplayArgs::parse(args).map(Command::Replay),
+            Some("stat") => return StatArgs::parse(args).map(Command::Stat),
+            Some("help" | "-h" | "--help") => Command::Help,
+            Some(x) => return Err(format!("unknown command: {}", x)),
+        };
+        match args.next() {
+            Some(x) => Err(format!("unexpected argument: {}", x)),
+            None => Ok(cmd),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command, String> {
+        Command::parse(args.iter().map(|x| x.to_string()))
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(parse(&[]), Ok(Command::Serve));
+        assert_eq!(parse(&["serve"]), Ok(Command::Serve));
+        assert_eq!(parse(&["check"]), Ok(Command::Check));
+        assert_eq!(parse(&["--help"]), Ok(Command::Help));
+        assert!(parse(&["unknown"]).is_err());
+        assert!(parse(&["check", "extra"]).is_err());
+        assert!(parse(&["serve", "extra"]).is_err());
+    }
+
+    #[test]
+    fn dev_serve() {
+        let allow = Ok(Command::DevServe("allow".to_owned()));
+        assert_eq!(parse(&["serve", "--dev-auth", "allow"]), allow);
+        assert_eq!(parse(&["--dev-auth", "allow"]), allow);
+        assert!(parse(&["--dev-auth"]).is_err());
+        assert!(parse(&["--dev-auth", "allow", "extra"]).is_err());
+    }
+
+    #[test]
+    fn warm() {
+        assert_eq!(
+            parse(&["warm", "--model", "city/block", "--depth", "5"]),
+            Ok(Command::Warm(WarmArgs {
+                model: "city/block".to_owned(),
+                depth: 5,
+                url: None,
+                token: None,
+            }))
+        );
+        assert!(parse(&["warm"]).is_err());
+        assert!(parse(&["warm", "--model", "city"]).is_err());
+        assert!(parse(&["warm", "--model", "city/block", "--depth"]).is_err());
+        assert!(parse(&["warm", "--model", "city/block", "--size", "1"]).is_err());
+    }
+
+    #[test]
+    fn bench() {
+        assert_eq!(
+            parse(&["bench", "--model", "city/block", "--concurrency", "8"]),
+            Ok(Command::Bench(BenchArgs {
+                model: "city/block".to_owned(),
+                concurrency: 8,
+                requests: 1000,
+                depth: 3,
+                log: None,
+                url: None,
+                token: None,
+            }))
+        );
+        assert!(parse(&["bench"]).is_err());
+        assert!(parse(&["bench", "--model", "city/block", "--concurrency", "0"]).is_err());
+        assert!(parse(&["bench", "--model", "city/block", "--requests", "x"]).is_err());
+    }
+
+    #[test]
+    fn replay() {
+        assert_eq!(
+            parse(&["replay", "access.log", "--speed", "2"]),
+            Ok(Command::Replay(ReplayArgs {
+                log: "access.log".to_owned(),
+                speed: Some(2.0),
+                concurrency: 64,
+                url: None,
+                token: None,
+            }))
+        );
+        assert!(parse(&["replay"]).is_err());
+        assert!(parse(&["replay", "--speed", "2"]).is_err());
+        assert!(parse(&["replay", "access.log", "--speed", "0"]).is_err());
+    }
+
+    #[test]
+    fn stat() {
+        assert_eq!(
+            parse(&["stat", "city", "--token", "secret"]),
+            Ok(Command::Stat(StatArgs {
+                object: "city".to_owned(),
+                model: None,
+                url: None,
+                token: Some("secret".to_owned()),
+            }))
+        );
+        assert!(matches!(
+            parse(&["stat", "city", "block"]),
+            Ok(Command::Stat(StatArgs { model: Some(_), .. }))
+        ));
+        assert!(parse(&["stat"]).is_err());
+        assert!(parse(&["stat", "city", "block", "extra"]).is_err());
+    }
+}
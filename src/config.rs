@@ -1,20 +1,70 @@
 use rocket::http::uri::Origin;
+use rocket::request::{FromRequest, Outcome, Request};
 use rocket::serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::OnceLock;
+use std::path::{Path, PathBuf};
 
+use crate::cache::DEFAULT_READ_BUFFER;
+use crate::cache_control::{CachePolicy, NamePattern};
+use crate::mime::default_content_types;
+use crate::admin::AdminConfig;
+use crate::ion::IonConfig;
+use crate::logger::LogConfig;
+use crate::preview::PreviewConfig;
+use crate::metrics::MetricsConfig;
+use crate::peers::PeersConfig;
+use crate::proxy::{Cidr, Forwarded};
+use crate::token::TokenConfig;
+use crate::upload::StorageQuotaConfig;
+use crate::quota::QuotaConfig;
+use crate::tenant::{HostsConfig, ObjectPathsConfig, Tenant};
+use crate::timeout::TimeoutConfig;
+use crate::runtime::RuntimeConfig;
+use crate::stat::StatConfig;
+use crate::usage::UsageConfig;
+use crate::webhook::WebhookConfig;
 use crate::AccessConfig;
+use crate::Model;
+use crate::RasterConfig;
 
 pub const SERVER_NAME: &str = env!("CARGO_PKG_NAME");
 pub const SERVER_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 /// Configuration params for rtiles
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Config<'a> {
     pub ident: String,
     pub cli_colors: bool,
     pub base_path: Origin<'a>,
     pub storage: ConfigStorage,
     pub access: AccessConfig,
+    pub content_types: HashMap<String, String>, // file extension to MIME type
+    pub raster: RasterConfig,
+    pub ion: IonConfig, // Cesium ion asset id to model mapping
+    pub models: HashMap<String, ModelConfig>, // keyed by `object/model` or `object`
+    pub admin: AdminConfig,
+    pub preview: PreviewConfig,
+    pub usage: UsageConfig,
+    pub stat: StatConfig, // stat queue capacity and overflow policy
+    pub quota: QuotaConfig,
+    pub webhooks: Vec<WebhookConfig>,
+    pub logging: LogConfig,
+    pub hosts: HostsConfig, // virtual hosts with own storage, auth server and base path
+    pub object_host: Option<String>, // host pattern like `{object}.tiles.example.com`
+    pub object_paths: ObjectPathsConfig, // legacy base paths like `/city` to objects
+    pub trusted_proxies: Vec<Cidr>,  // peers allowed to set `X-Forwarded-*` headers
+    pub token: TokenConfig,
+    pub peers: PeersConfig, // replicas sharing file caches
+    pub storage_quota: StorageQuotaConfig,
+    pub metrics: MetricsConfig, // Prometheus histogram buckets
+    pub cache_control: HashMap<String, CachePolicy>, // keyed by media type like `image/*`
+    pub headers: HashMap<String, String>, // extra headers of model responses
+    pub timeouts: TimeoutConfig,
+    pub read_only: bool, // replica mode, storage mutating routes are not mounted
+    pub security_headers: bool, // rocket `Shield` headers like `X-Frame-Options`, off for embedding
+    pub runtime: RuntimeConfig, // worker and blocking thread pools
 }
 
 impl Default for Config<'_> {
@@ -25,16 +75,124 @@ impl Default for Config<'_> {
             base_path: Origin::path_only("/3d"),
             storage: ConfigStorage::default(),
             access: AccessConfig::default(),
+            content_types: default_content_types(),
+            raster: RasterConfig::default(),
+            ion: IonConfig::default(),
+            models: HashMap::new(),
+            admin: AdminConfig::default(),
+            preview: PreviewConfig::default(),
+            usage: UsageConfig::default(),
+            stat: StatConfig::default(),
+            quota: QuotaConfig::default(),
+            webhooks: Vec::new(),
+            logging: LogConfig::default(),
+            hosts: HostsConfig::default(),
+            object_host: None,
+            object_paths: ObjectPathsConfig::new(),
+            trusted_proxies: Vec::new(),
+            token: TokenConfig::default(),
+            peers: PeersConfig::default(),
+            storage_quota: StorageQuotaConfig::default(),
+            metrics: MetricsConfig::default(),
+            cache_control: HashMap::new(),
+            headers: HashMap::new(),
+            timeouts: TimeoutConfig::default(),
+            read_only: false,
+            security_headers: true,
+            runtime: RuntimeConfig::default(),
         }
     }
 }
 
+impl Config<'_> {
+    /// Get directory index files for the model
+    #[allow(clippy::should_implement_trait)]
+    pub fn index(&self, model: &Model) -> &[String] {
+        self.model(model)
+            .index
+            .as_deref()
+            .unwrap_or(&self.storage.index)
+    }
+
+    /// Get model specific config, fallback to object config and defaults
+    pub fn model(&self, model: &Model) -> &ModelConfig {
+        static DEFAULT: OnceLock<ModelConfig> = OnceLock::new();
+        let object = model.object.as_deref().unwrap_or_default();
+        model
+            .name
+            .as_ref()
+            .and_then(|name| self.models.get(&format!("{}/{}", object, name)))
+            .or_else(|| self.models.get(object))
+            .unwrap_or_else(|| DEFAULT.get_or_init(ModelConfig::default))
+    }
+}
+
+/// Model specific params
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct ModelConfig {
+    // Accept header param of disabled client capability (e.g. `dracoDecoding`)
+    // to the directory name of model variant without this feature
+    pub variants: HashMap<String, String>,
+    pub index: Option<Vec<String>>, // directory index files, overrides storage setting
+    pub attribution: Option<String>, // injected into `asset.extras` of index tilesets
+    pub title: Option<String>,       // human readable model title
+    pub referers: Option<Vec<String>>, // allowed `Referer` hosts like `*.example.com`
+    pub allow_empty_referer: bool,     // allow requests without `Referer` if restricted
+    pub detect_gzip: bool, // send gzipped payloads of any file type with `Content-Encoding`
+    pub public: Option<bool>, // `Cache-Control` public or private, media type policy if not set
+    pub no_transform: bool,   // add `no-transform` to `Cache-Control`
+    pub headers: HashMap<String, String>, // extra response headers, replace global ones
+    pub auth_budget: Option<u64>, // auth check time budget in ms, overrides `access.budget`
+    pub max_age: Option<u32>, // `Cache-Control` max-age, overrides storage and media type settings
+    pub max_age_ext: HashMap<String, u32>, // max-age by file extension like `json` or `b3dm`
+}
+
+impl ModelConfig {
+    /// Max-age of the file: model by extension, model, storage by extension
+    pub fn max_age_for(&self, storage: &ConfigStorage, file: &Path) -> Option<u32> {
+        let ext = file.extension().and_then(|x| x.to_str());
+        let by_ext = |map: &HashMap<String, u32>| {
+            let ext = ext?;
+            map.iter()
+                .find_map(|(k, v)| k.eq_ignore_ascii_case(ext).then_some(*v))
+        };
+        by_ext(&self.max_age_ext)
+            .or(self.max_age)
+            .or_else(|| by_ext(&storage.max_age_ext))
+    }
+}
+
 /// Storage and client cache params
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ConfigStorage {
     pub root: PathBuf,
     pub max_age: u32,
-    pub cache_size: u64
+    pub max_age_ext: HashMap<String, u32>, // max-age by file extension, overrides media type policy
+    pub immutable: Option<NamePattern>, // file name regex of immutable content, e.g. hashed names
+    pub cache_size: u64,
+    pub extract_glb: bool, // serve glb payload of b3dm tile for `.glb` requests
+    pub index: Vec<String>, // directory index files in order of preference
+    pub listing: bool,      // allow directory listing for users with model access
+    pub scan_interval: u64, // storage inventory rescan interval, seconds
+    pub digest: bool,       // send SHA-256 `Digest` and `Repr-Digest` headers
+    pub verify_manifests: bool, // check models against `manifest.sha256` on startup
+    pub mmap_max: u64,      // memory-map uncached files up to this size in MB, 0 disables
+    pub read_buffer: usize, // file read chunk size in KB
+    pub stream_threshold: u64, // stream larger files without caching, MB, 0 for cache size only
+    pub max_depth: usize,   // path segments under the model directory
+    pub max_segment: usize, // path segment length, bytes
+    pub preload: usize,     // `Link` preload headers of root tileset children, 0 disables
+    pub attachments: Vec<String>, // extensions sent with `Content-Disposition: attachment`
+    pub dedup: bool,        // store identical cached bodies once, keyed by content hash
+    pub path_filter: bool,  // reject paths missing in the last scan without filesystem calls
+    pub pin_budget: u64,    // memory for entries pinned by the admin API, MB, 0 disables
+    pub no_cache_requests: bool, // `Cache-Control: no-cache` requests refresh the cache entry
+    pub load_concurrency: usize, // parallel background reads of files to cache
+    pub degrade_errors: u32, // storage IO errors in a row switching to cache-only, 0 disables
+    pub recover_probe: u64,  // degraded storage root check interval, seconds
+    pub missing_grace: u64,  // serve cached files deleted on disk for seconds, 0 disables
+    pub placeholders: HashMap<String, PathBuf>, // payload files by extension served on 5xx errors
 }
 
 impl Default for ConfigStorage {
@@ -42,7 +200,129 @@ impl Default for ConfigStorage {
         ConfigStorage {
             root: PathBuf::from("data"),
             max_age: 30 * 60,  // 30 minutes
+            max_age_ext: HashMap::new(),
+            immutable: None,
             cache_size: 500,   // 500 MB  
+            extract_glb: false,
+            index: vec!["tileset.json".to_owned()],
+            listing: false,
+            scan_interval: 60, // 1 minute
+            digest: false,
+            verify_manifests: false,
+            mmap_max: 0,
+            read_buffer: DEFAULT_READ_BUFFER,
+            stream_threshold: 0,
+            max_depth: 12,
+            max_segment: 255,
+            preload: 0,
+            attachments: Vec::new(),
+            dedup: false,
+            path_filter: false,
+            pin_budget: 0,
+            no_cache_requests: false,
+            load_concurrency: 4,
+            degrade_errors: 5,
+            recover_probe: 5,
+            missing_grace: 0,
+            placeholders: HashMap::new(),
         }
     }
 }
+
+impl ConfigStorage {
+    /// Check path under the model directory against depth and segment limits
+    pub fn check_path(&self, path: &Path) -> Result<(), String> {
+        let mut depth = 0;
+        for segment in path.iter() {
+            depth += 1;
+            if depth > self.max_depth {
+                return Err(format!("path deeper than {} segments", self.max_depth));
+            }
+            if segment.len() > self.max_segment {
+                return Err(format!("path segment longer than {}", self.max_segment));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Absolute url of the server base path, derived from the request host
+#[derive(Debug, Clone, PartialEq)]
+pub struct BaseUrl(pub String);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for BaseUrl {
+    type Error = Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let host = req
+            .host()
+            .map(|h| h.to_string())
+            .unwrap_or_else(|| "localhost".to_owned());
+        let path = match req.guard::<&Tenant>().await {
+            Outcome::Success(tenant) => tenant.base_path.clone(),
+            _ => {
+                let config = req.rocket().state::<Config<'_>>().unwrap();
+                config.base_path.path().as_str().trim_end_matches('/').to_owned()
+            }
+        };
+
+        let proto = Forwarded::of(req).proto;
+
+        Outcome::Success(BaseUrl(format!("{}://{}{}", proto, host, path)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn model_config() {
+        let mut config = Config::default();
+        let variants = |dir: &str| ModelConfig {
+            variants: [("dracoDecoding".to_owned(), dir.to_owned())].into(),
+            ..Default::default()
+        };
+        config.models.insert("tver".to_owned(), variants("object"));
+        config.models.insert("tver/panorama".to_owned(), variants("model"));
+
+        let model = Model::new(Some("tver"), Some("panorama"));
+        assert_eq!(config.model(&model), &variants("model"));
+        let model = Model::new(Some("tver"), Some("center"));
+        assert_eq!(config.model(&model), &variants("object"));
+        let model = Model::new(Some("lake"), Some("panorama"));
+        assert_eq!(config.model(&model), &ModelConfig::default());
+    }
+
+    #[test]
+    fn max_age_overrides() {
+        let storage = ConfigStorage {
+            max_age_ext: [("b3dm".to_owned(), 86400)].into(),
+            ..Default::default()
+        };
+        let mut model = ModelConfig::default();
+        let (json, b3dm) = (Path::new("tileset.json"), Path::new("0/tile.B3DM"));
+        assert_eq!(model.max_age_for(&storage, json), None);
+        assert_eq!(model.max_age_for(&storage, b3dm), Some(86400));
+        model.max_age = Some(600);
+        assert_eq!(model.max_age_for(&storage, json), Some(600));
+        assert_eq!(model.max_age_for(&storage, b3dm), Some(600));
+        model.max_age_ext.insert("json".to_owned(), 60);
+        assert_eq!(model.max_age_for(&storage, json), Some(60));
+        assert_eq!(model.max_age_for(&storage, Path::new("data")), Some(600));
+    }
+
+    #[test]
+    fn path_limits() {
+        let storage = ConfigStorage {
+            max_depth: 3,
+            max_segment: 10,
+            ..Default::default()
+        };
+        assert!(storage.check_path(Path::new("a/b/tile.b3dm")).is_ok());
+        assert!(storage.check_path(Path::new("a/b/c/tile.b3dm")).is_err());
+        assert!(storage.check_path(Path::new("a/long_tile.b3dm")).is_err());
+        assert!(storage.check_path(Path::new("")).is_ok());
+    }
+}
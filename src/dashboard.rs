@@ -0,0 +1,111 @@
+use rocket::response::content::RawHtml;
+use rocket::serde::json::Json;
+use rocket::serde::Serialize;
+use rocket::State;
+
+use crate::access::{AccessStats, ModelAccess};
+use crate::admin::Admin;
+use crate::cache::FileCache;
+use crate::stat::{Metrics, Stat, StatKey};
+
+const PAGE: &str = include_str!("../static/dashboard.html");
+
+// max count of models in the dashboard
+const TOP_MODELS: usize = 20;
+
+/// File cache usage
+#[derive(Debug, Serialize)]
+pub struct CacheUsage {
+    pub entries: u64,
+    pub bytes: u64,
+    pub capacity: u64,
+}
+
+/// Model metrics
+#[derive(Debug, Serialize, PartialEq)]
+pub struct ModelMetrics {
+    pub object: String,
+    pub model: String,
+    pub metrics: Metrics,
+}
+
+/// Server statistics summary
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    pub total: Metrics,
+    pub cache: CacheUsage,
+    pub access: AccessStats, // access cache and auth server
+    pub models: Vec<ModelMetrics>, // top models by hits
+}
+
+/// Select top models by hits from stat entries
+pub fn top_models(entries: Vec<(StatKey, Metrics)>, n: usize) -> Vec<ModelMetrics> {
+    let mut models: Vec<_> = entries
+        .into_iter()
+        // skip status class breakdown
+        .filter(|(key, _)| key.class.is_none())
+        .filter_map(|(key, metrics)| {
+            Some(ModelMetrics {
+                object: key.model.object.clone()?,
+                model: key.model.name.clone()?,
+                metrics,
+            })
+        })
+        .collect();
+    models.sort_by_key(|x| std::cmp::Reverse(x.metrics.hits));
+    models.truncate(n);
+    models
+}
+
+/// Dashboard page, data is requested with the admin token
+#[get("/admin/dashboard")]
+pub fn dashboard() -> RawHtml<&'static str> {
+    RawHtml(PAGE)
+}
+
+#[get("/admin/stat")]
+pub async fn summary(
+    _admin: Admin,
+    stat: &State<Stat>,
+    cache: &State<FileCache>,
+    access: &State<ModelAccess>,
+) -> Json<Summary> {
+    Json(Summary {
+        total: stat.get(&StatKey::default()).await,
+        cache: CacheUsage {
+            entries: cache.entry_count(),
+            bytes: cache.weighted_size(),
+            capacity: cache.size(),
+        },
+        access: access.stats().await,
+        models: top_models(stat.entries().await, TOP_MODELS),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn top() {
+        let metrics = |hits| Metrics {
+            hits,
+            ..Default::default()
+        };
+        let entries = vec![
+            (StatKey::default(), metrics(10)),
+            (StatKey::new(Some("tver"), None), metrics(10)),
+            (StatKey::new(Some("tver"), Some("a")), metrics(3)),
+            (StatKey::new(Some("tver"), Some("b")), metrics(7)),
+        ];
+        let top = top_models(entries, 1);
+        assert_eq!(
+            top,
+            vec![ModelMetrics {
+                object: "tver".to_owned(),
+                model: "b".to_owned(),
+                metrics: metrics(7)
+            }]
+        );
+    }
+}
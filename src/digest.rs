@@ -0,0 +1,118 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use moka::future::Cache;
+use rocket::http::Header;
+use rocket::request::Request;
+use rocket::response::{self, Responder};
+use sha2::{Digest, Sha256};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::Meta;
+
+/// Compute SHA-256 of the file
+pub async fn sha256(path: &Path) -> io::Result<[u8; 32]> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let mut f = std::fs::File::open(path)?;
+        let mut hasher = Sha256::new();
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            match f.read(&mut buf)? {
+                0 => break,
+                n => hasher.update(&buf[..n]),
+            }
+        }
+        Ok(hasher.finalize().into())
+    })
+    .await?
+}
+
+/// Cache of file digests, validated by metadata
+#[derive(Clone)]
+pub struct DigestCache {
+    cache: Cache<PathBuf, (Meta, Arc<str>)>,
+}
+
+impl DigestCache {
+    pub fn new() -> Self {
+        let cache = Cache::builder()
+            // Max 100,000 entries
+            .max_capacity(100_000)
+            .build();
+        DigestCache { cache }
+    }
+
+    /// Get cached or compute file digest
+    pub async fn get(&self, path: &PathBuf, meta: &Meta) -> io::Result<Arc<str>> {
+        if let Some((m, digest)) = self.cache.get(path).await {
+            if &m == meta {
+                return Ok(digest);
+            }
+        }
+        let digest: Arc<str> = STANDARD.encode(sha256(path).await?).into();
+        self.cache
+            .insert(path.clone(), (meta.clone(), digest.clone()))
+            .await;
+        Ok(digest)
+    }
+
+    /// Recompute file digest and compare with the cached one, cache is updated
+    pub async fn verify(&self, path: &PathBuf, meta: &Meta) -> io::Result<(Arc<str>, bool)> {
+        let digest: Arc<str> = STANDARD.encode(sha256(path).await?).into();
+        let valid = match self.cache.get(path).await {
+            Some((m, cached)) if &m == meta => cached == digest,
+            _ => true,
+        };
+        self.cache
+            .insert(path.clone(), (meta.clone(), digest.clone()))
+            .await;
+        Ok((digest, valid))
+    }
+}
+
+/// Response with the content digest headers
+pub struct Digested<R> {
+    pub inner: R,
+    pub digest: Option<Arc<str>>, // base64 SHA-256
+    pub verified: Option<bool>,   // result of admin verify mode
+}
+
+impl<'r, R: Responder<'r, 'static>> Responder<'r, 'static> for Digested<R> {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let mut res = self.inner.respond_to(req)?;
+        if let Some(digest) = self.digest {
+            res.set_header(Header::new("Digest", format!("sha-256={}", digest)));
+            res.set_header(Header::new("Repr-Digest", format!("sha-256=:{}:", digest)));
+        }
+        if let Some(valid) = self.verified {
+            let value = if valid { "ok" } else { "mismatch" };
+            res.set_header(Header::new("X-Digest-Verify", value));
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn digest() {
+        let path = std::env::temp_dir().join("rtiles-digest-test");
+        std::fs::write(&path, "abc").unwrap();
+        let meta = Meta::from_path(&path).await.unwrap();
+
+        let cache = DigestCache::new();
+        let digest = cache.get(&path, &meta).await.unwrap();
+        assert_eq!(&*digest, "ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0=");
+        assert!(cache.verify(&path, &meta).await.unwrap().1);
+
+        // corrupted file with the same metadata
+        std::fs::write(&path, "abd").unwrap();
+        let (_, valid) = cache.verify(&path, &meta).await.unwrap();
+        assert!(!valid);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
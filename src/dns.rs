@@ -0,0 +1,59 @@
+use moka::future::Cache;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// DNS resolver caching system lookups for a fixed time
+#[derive(Clone)]
+pub struct CachingResolver {
+    cache: Cache<String, Arc<Vec<SocketAddr>>>,
+}
+
+impl CachingResolver {
+    pub fn new(ttl: Duration) -> Self {
+        let cache = Cache::builder()
+            .max_capacity(1000)
+            .time_to_live(ttl)
+            .build();
+        CachingResolver { cache }
+    }
+
+    /// Resolve host addresses, failed lookups are not cached
+    pub async fn lookup(&self, host: &str) -> io::Result<Arc<Vec<SocketAddr>>> {
+        self.cache
+            .try_get_with(host.to_owned(), async {
+                let addrs = tokio::net::lookup_host((host, 0)).await?;
+                Ok(Arc::new(addrs.collect()))
+            })
+            .await
+            .map_err(|err: Arc<io::Error>| io::Error::new(err.kind(), err.to_string()))
+    }
+}
+
+impl Resolve for CachingResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = self.clone();
+        Box::pin(async move {
+            let addrs = resolver.lookup(name.as_str()).await?;
+            Ok(Box::new(addrs.as_ref().clone().into_iter()) as Addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn cached_lookup() {
+        let resolver = CachingResolver::new(Duration::from_secs(60));
+        let addrs = resolver.lookup("localhost").await.unwrap();
+        assert!(addrs.iter().any(|x| x.ip().is_loopback()));
+        assert!(resolver.cache.contains_key("localhost"));
+
+        assert!(resolver.lookup("no-such-host.invalid").await.is_err());
+        assert!(!resolver.cache.contains_key("no-such-host.invalid"));
+    }
+}
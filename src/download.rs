@@ -0,0 +1,79 @@
+use rocket::http::Header;
+use rocket::request::Request;
+use rocket::response::{self, Responder};
+use std::path::Path;
+
+/// Should the file be sent as an attachment: requested by `?download=1`
+/// or its extension is listed in the storage config
+pub fn is_attachment(path: &Path, download: Option<&str>, extensions: &[String]) -> bool {
+    if matches!(download, Some("1" | "true")) {
+        return true;
+    }
+    match path.extension() {
+        Some(ext) => extensions.iter().any(|x| ext.eq_ignore_ascii_case(x)),
+        None => false,
+    }
+}
+
+/// `Content-Disposition` value, non-ASCII names are sent with `filename*`
+pub fn disposition(filename: &str) -> String {
+    let ascii: String = filename
+        .chars()
+        .map(|c| match c {
+            '"' | '\\' => '_',
+            c if c.is_ascii() && !c.is_ascii_control() => c,
+            _ => '_',
+        })
+        .collect();
+    if ascii == filename {
+        return format!("attachment; filename=\"{}\"", ascii);
+    }
+    let encoded: String = filename
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'.' | b'-' | b'_' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect();
+    format!(
+        "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+        ascii, encoded
+    )
+}
+
+/// Response with optional `Content-Disposition: attachment`
+pub struct Attachment<R> {
+    pub inner: R,
+    pub filename: Option<String>,
+}
+
+impl<'r, R: Responder<'r, 'static>> Responder<'r, 'static> for Attachment<R> {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let mut res = self.inner.respond_to(req)?;
+        if let Some(filename) = self.filename {
+            res.set_header(Header::new("Content-Disposition", disposition(&filename)));
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn attachment() {
+        let exts = ["las".to_owned()];
+        assert!(is_attachment(Path::new("a/tile.glb"), Some("1"), &[]));
+        assert!(is_attachment(Path::new("a/points.LAS"), None, &exts));
+        assert!(!is_attachment(Path::new("a/tile.glb"), Some("0"), &exts));
+        assert!(!is_attachment(Path::new("a/tile.glb"), None, &exts));
+
+        assert_eq!(disposition("tile.glb"), "attachment; filename=\"tile.glb\"");
+        assert_eq!(
+            disposition("тверь 1.glb"),
+            "attachment; filename=\"_____ 1.glb\"; \
+             filename*=UTF-8''%D1%82%D0%B2%D0%B5%D1%80%D1%8C%201.glb"
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use rocket::http::Status;
+use rocket::request::Request;
+use rocket::response::{self, Responder};
+use std::io::{self, ErrorKind};
+
+use crate::metrics::ServerMetrics;
+
+/// Request handler error, mapped to the response status
+#[derive(Debug)]
+pub enum Error {
+    BadRequest(String),  // 400
+    NotFound(String),    // 404
+    Forbidden(String),   // 403
+    Unavailable(String), // 503, temporary failure
+    Internal(String),    // 500
+    Timeout(String),     // 504, handler took too long
+}
+
+impl Error {
+    pub fn status(&self) -> Status {
+        match self {
+            Error::BadRequest(_) => Status::BadRequest,
+            Error::NotFound(_) => Status::NotFound,
+            Error::Forbidden(_) => Status::Forbidden,
+            Error::Unavailable(_) => Status::ServiceUnavailable,
+            Error::Internal(_) => Status::InternalServerError,
+            Error::Timeout(_) => Status::GatewayTimeout,
+        }
+    }
+
+    fn message(&self) -> &str {
+        match self {
+            Error::BadRequest(msg)
+            | Error::NotFound(msg)
+            | Error::Forbidden(msg)
+            | Error::Unavailable(msg)
+            | Error::Internal(msg)
+            | Error::Timeout(msg) => msg,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        let msg = e.to_string();
+        match e.kind() {
+            ErrorKind::NotFound | ErrorKind::NotADirectory | ErrorKind::IsADirectory => {
+                Error::NotFound(msg)
+            }
+            ErrorKind::PermissionDenied => Error::Forbidden(msg),
+            ErrorKind::TimedOut
+            | ErrorKind::WouldBlock
+            | ErrorKind::Interrupted
+            | ErrorKind::ResourceBusy
+            | ErrorKind::OutOfMemory => Error::Unavailable(msg),
+            _ => Error::Internal(msg),
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for Error {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let status = self.status();
+        match self {
+            Error::BadRequest(_) | Error::NotFound(_) => {
+                debug!("{} {}: {}", status, req.uri(), self.message())
+            }
+            Error::Forbidden(_) | Error::Unavailable(_) | Error::Timeout(_) => {
+                warn!("{} {}: {}", status, req.uri(), self.message())
+            }
+            Error::Internal(_) => error!("{} {}: {}", status, req.uri(), self.message()),
+        }
+        if let Some(metrics) = req.rocket().state::<ServerMetrics>() {
+            metrics.error(&self);
+        }
+        // messages may contain storage paths and are logged only,
+        // bad request messages describe the client input
+        let body = match self {
+            Error::BadRequest(msg) => msg,
+            _ => status.to_string(),
+        };
+        (status, body).respond_to(req)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn io_status() {
+        let status = |kind: ErrorKind| Error::from(io::Error::from(kind)).status();
+        assert_eq!(status(ErrorKind::NotFound), Status::NotFound);
+        assert_eq!(status(ErrorKind::PermissionDenied), Status::Forbidden);
+        assert_eq!(status(ErrorKind::TimedOut), Status::ServiceUnavailable);
+        assert_eq!(status(ErrorKind::InvalidData), Status::InternalServerError);
+        assert_eq!(
+            Error::from(io::Error::other("disk failure")).status(),
+            Status::InternalServerError
+        );
+    }
+
+    #[rocket::async_test]
+    async fn body() {
+        let client = rocket::local::asynchronous::Client::debug_with(vec![])
+            .await
+            .unwrap();
+        let req = client.get("/");
+        let body = |err: Error| async {
+            let mut res = err.respond_to(req.inner()).unwrap();
+            res.body_mut().to_string().await.unwrap()
+        };
+        let err = Error::NotFound("no index file in /data/tver/panorama".to_owned());
+        assert_eq!(body(err).await, "404 Not Found");
+        let err = Error::BadRequest("path deeper than 12 segments".to_owned());
+        assert_eq!(body(err).await, "path deeper than 12 segments");
+    }
+}
@@ -0,0 +1,165 @@
+use rocket::response::stream::{Event as SseEvent, EventStream};
+use rocket::serde::Serialize;
+use rocket::tokio::select;
+use rocket::tokio::sync::broadcast::{self, error::RecvError};
+use rocket::{Shutdown, State};
+
+use crate::admin::Admin;
+use crate::stat::Metrics;
+
+/// Server event
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    // metrics delta for the model
+    Stat {
+        object: Option<String>,
+        model: Option<String>,
+        metrics: Metrics,
+    },
+    CacheInsert {
+        path: String,
+        bytes: u64,
+    },
+    CacheInvalidate {
+        path: String,
+    },
+    // client request or byte quota exceeded
+    QuotaExceeded {
+        client: String,
+        status: u16,
+    },
+    // auth server request failed
+    AuthDown {
+        error: String,
+    },
+    // file cache usage over the threshold
+    CacheFull {
+        bytes: u64,
+        capacity: u64,
+    },
+    // model file uploaded with the admin API
+    ModelUploaded {
+        object: String,
+        model: String,
+        path: String,
+        bytes: u64,
+    },
+    ModelDeleted {
+        object: String,
+        model: String,
+    },
+    // staged model version switched live
+    ModelActivated {
+        object: String,
+        model: String,
+        version: String,
+    },
+    // storage IO errors in a row, misses are answered with 503
+    StorageDegraded {
+        error: String,
+    },
+    StorageRecovered,
+}
+
+impl Event {
+    /// Event type name, same as serialized `type` field
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Event::Stat { .. } => "stat",
+            Event::CacheInsert { .. } => "cache_insert",
+            Event::CacheInvalidate { .. } => "cache_invalidate",
+            Event::QuotaExceeded { .. } => "quota_exceeded",
+            Event::AuthDown { .. } => "auth_down",
+            Event::CacheFull { .. } => "cache_full",
+            Event::ModelUploaded { .. } => "model_uploaded",
+            Event::ModelDeleted { .. } => "model_deleted",
+            Event::ModelActivated { .. } => "model_activated",
+            Event::StorageDegraded { .. } => "storage_degraded",
+            Event::StorageRecovered => "storage_recovered",
+        }
+    }
+
+    /// Is the event worth alerting operators?
+    pub fn is_notable(&self) -> bool {
+        matches!(
+            self,
+            Event::QuotaExceeded { .. }
+                | Event::AuthDown { .. }
+                | Event::CacheFull { .. }
+                | Event::StorageDegraded { .. }
+        )
+    }
+}
+
+/// Server events bus
+#[derive(Clone)]
+pub struct Events(broadcast::Sender<Event>);
+
+impl Events {
+    pub fn new(capacity: usize) -> Self {
+        let (tx, _) = broadcast::channel(capacity);
+        Events(tx)
+    }
+
+    /// Publish event to all subscribers, if any
+    pub fn send(&self, event: Event) {
+        // error means no active subscribers, ignore
+        let _ = self.0.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.0.subscribe()
+    }
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        Events::new(1024)
+    }
+}
+
+/// Stream server events with SSE
+#[get("/admin/stat/live")]
+pub fn live(_admin: Admin, events: &State<Events>, mut shutdown: Shutdown) -> EventStream![] {
+    let mut rx = events.subscribe();
+    EventStream! {
+        loop {
+            let event = select! {
+                msg = rx.recv() => match msg {
+                    Ok(event) => event,
+                    Err(RecvError::Closed) => break,
+                    Err(RecvError::Lagged(n)) => {
+                        yield SseEvent::comment(format!("{} events skipped", n));
+                        continue;
+                    }
+                },
+                _ = &mut shutdown => break,
+            };
+            yield SseEvent::json(&event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn bus() {
+        let events = Events::default();
+        // no subscribers, event is dropped
+        events.send(Event::CacheInvalidate { path: "a".into() });
+
+        let mut rx = events.subscribe();
+        events.send(Event::CacheInsert {
+            path: "b".into(),
+            bytes: 10,
+        });
+        let event = rx.recv().await.unwrap();
+        assert_eq!(
+            rocket::serde::json::to_string(&event).unwrap(),
+            r#"{"type":"cache_insert","path":"b","bytes":10}"#
+        );
+    }
+}
@@ -0,0 +1,201 @@
+use rocket::serde::json::{self, Json, Value};
+use rocket::serde::Serialize;
+use rocket::State;
+use std::io;
+
+use crate::access::AccessKey;
+use crate::meta::MetaCache;
+use crate::tenant::Tenant;
+use crate::{Config, Error};
+
+// WGS84 ellipsoid params
+const A: f64 = 6378137.0;
+const F: f64 = 1.0 / 298.257223563;
+
+/// Model extent in WGS84 coordinates, degrees and meters
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Extent {
+    pub bbox: [f64; 4],   // west, south, east, north
+    pub center: [f64; 3], // longitude, latitude, height
+}
+
+/// Convert ECEF coordinates to longitude, latitude (degrees) and height
+pub fn ecef_to_wgs84(p: [f64; 3]) -> [f64; 3] {
+    let e2 = F * (2.0 - F);
+    let b = A * (1.0 - F);
+    let ep2 = (A * A - b * b) / (b * b);
+    let r = (p[0] * p[0] + p[1] * p[1]).sqrt();
+    let th = (p[2] * A).atan2(r * b);
+    let lon = p[1].atan2(p[0]);
+    let lat = (p[2] + ep2 * b * th.sin().powi(3)).atan2(r - e2 * A * th.cos().powi(3));
+    let n = A / (1.0 - e2 * lat.sin().powi(2)).sqrt();
+    let h = if lat.cos().abs() > 1e-10 {
+        r / lat.cos() - n
+    } else {
+        p[2].abs() - b
+    };
+    [lon.to_degrees(), lat.to_degrees(), h]
+}
+
+/// Apply column-major 4x4 transform to the point
+fn transform(m: &[f64; 16], p: [f64; 3]) -> [f64; 3] {
+    [
+        m[0] * p[0] + m[4] * p[1] + m[8] * p[2] + m[12],
+        m[1] * p[0] + m[5] * p[1] + m[9] * p[2] + m[13],
+        m[2] * p[0] + m[6] * p[1] + m[10] * p[2] + m[14],
+    ]
+}
+
+fn numbers<const N: usize>(v: &Value) -> Option<[f64; N]> {
+    let v: Vec<f64> = v.as_array()?.iter().filter_map(Value::as_f64).collect();
+    v.try_into().ok()
+}
+
+impl Extent {
+    /// Compute extent of the root tile bounding volume
+    pub fn from_tileset(tileset: &Value) -> Option<Self> {
+        let root = tileset.get("root")?;
+        let volume = root.get("boundingVolume")?;
+
+        if let Some(r) = volume.get("region").and_then(numbers::<6>) {
+            // region is in radians, heights in meters
+            let [lo, hi] = [r[4], r[5]];
+            let [w, s, e, n] = [r[0], r[1], r[2], r[3]].map(f64::to_degrees);
+            return Some(Extent {
+                bbox: [w, s, e, n],
+                center: [(w + e) / 2.0, (s + n) / 2.0, (lo + hi) / 2.0],
+            });
+        }
+
+        const IDENTITY: [f64; 16] = [
+            1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0,
+        ];
+        let m = root
+            .get("transform")
+            .and_then(numbers::<16>)
+            .unwrap_or(IDENTITY);
+
+        // box center and half axes
+        let (c, axes) = if let Some(b) = volume.get("box").and_then(numbers::<12>) {
+            let axis = |i: usize| [b[i], b[i + 1], b[i + 2]];
+            ([b[0], b[1], b[2]], [axis(3), axis(6), axis(9)])
+        } else {
+            let s = volume.get("sphere").and_then(numbers::<4>)?;
+            let r = s[3];
+            (
+                [s[0], s[1], s[2]],
+                [[r, 0.0, 0.0], [0.0, r, 0.0], [0.0, 0.0, r]],
+            )
+        };
+
+        let mut bbox = [f64::MAX, f64::MAX, f64::MIN, f64::MIN];
+        for i in 0..8 {
+            let mut p = c;
+            for (k, axis) in axes.iter().enumerate() {
+                let sign = if i & (1 << k) == 0 { -1.0 } else { 1.0 };
+                for j in 0..3 {
+                    p[j] += sign * axis[j];
+                }
+            }
+            let [lon, lat, _] = ecef_to_wgs84(transform(&m, p));
+            bbox = [
+                bbox[0].min(lon),
+                bbox[1].min(lat),
+                bbox[2].max(lon),
+                bbox[3].max(lat),
+            ];
+        }
+        Some(Extent {
+            bbox,
+            center: ecef_to_wgs84(transform(&m, c)),
+        })
+    }
+}
+
+#[get("/models/<_>/<_>/extent")]
+pub async fn extent(
+    key: AccessKey,
+    tenant: &Tenant,
+    config: &State<Config<'_>>,
+    metacache: &State<MetaCache>,
+) -> Result<Json<Extent>, Error> {
+    let mut dir = tenant.root.clone();
+    dir.push(key.model.object.as_ref().unwrap());
+    dir.push(key.model.name.as_ref().unwrap());
+
+    // find root tileset file
+    let mut file = None;
+    for name in config.index(&key.model) {
+        let f = dir.join(name);
+        if metacache.metadata(&f).await.is_ok() {
+            file = Some(f);
+            break;
+        }
+    }
+    let file = file.ok_or_else(|| Error::NotFound("root tileset not found".to_owned()))?;
+
+    let s = tokio::fs::read_to_string(&file).await?;
+    let tileset: Value =
+        json::from_str(&s).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Extent::from_tileset(&tileset)
+        .map(Json)
+        .ok_or_else(|| Error::NotFound("no root bounding volume".to_owned()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn approx(a: &[f64], b: &[f64], eps: f64) -> bool {
+        a.iter().zip(b).all(|(x, y)| (x - y).abs() < eps)
+    }
+
+    #[test]
+    fn ecef() {
+        // point on the equator at Greenwich
+        assert!(approx(
+            &ecef_to_wgs84([A, 0.0, 0.0]),
+            &[0.0, 0.0, 0.0],
+            1e-6
+        ));
+        // Tver, 35.9E 56.86N, 150 m
+        let p = [2831206.016, 2049451.363, 5317518.700];
+        assert!(approx(&ecef_to_wgs84(p), &[35.9, 56.86, 150.0], 1e-3));
+    }
+
+    #[test]
+    fn region() {
+        let tileset = json::json!({ "root": { "boundingVolume": {
+            "region": [0.0, 0.5, 0.1, 0.6, 0.0, 100.0]
+        }}});
+        let e = Extent::from_tileset(&tileset).unwrap();
+        assert!(approx(
+            &e.bbox,
+            &[
+                0.0,
+                0.5f64.to_degrees(),
+                0.1f64.to_degrees(),
+                0.6f64.to_degrees()
+            ],
+            1e-9
+        ));
+        assert!(approx(
+            &e.center,
+            &[0.05f64.to_degrees(), 0.55f64.to_degrees(), 50.0],
+            1e-9
+        ));
+    }
+
+    #[test]
+    fn transformed_box() {
+        // unit box moved to the equator at Greenwich
+        let tileset = json::json!({ "root": {
+            "transform": [1,0,0,0, 0,1,0,0, 0,0,1,0, A,0,0,1],
+            "boundingVolume": { "box": [0,0,0, 10,0,0, 0,10,0, 0,0,10] }
+        }});
+        let e = Extent::from_tileset(&tileset).unwrap();
+        assert!(approx(&e.center, &[0.0, 0.0, 0.0], 1e-6));
+        assert!(e.bbox[0] < 0.0 && e.bbox[2] > 0.0);
+        assert!(e.bbox[1] < 0.0 && e.bbox[3] > 0.0);
+    }
+}
@@ -0,0 +1,51 @@
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::serde::Serialize;
+use rocket::State;
+
+use crate::admin::{model_dir, Admin};
+use crate::cache::FileCache;
+use crate::tenant::Tenant;
+
+/// Freeze result
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Frozen {
+    pub entries: u64, // cached entries in the snapshot
+    pub bytes: u64,
+}
+
+/// Unfreeze result
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Unfrozen {
+    pub purged: u64, // invalidated cache entries
+    pub queued: u64, // frozen entries scheduled for reload
+}
+
+/// Serve cached files of the model unchanged while its dataset is updated,
+/// files not cached are answered with 503 until unfreeze
+#[post("/admin/models/<object>/<model>/freeze")]
+pub async fn freeze(
+    _admin: Admin,
+    object: &str,
+    model: &str,
+    tenant: &Tenant,
+    cache: &State<FileCache>,
+) -> Result<Json<Frozen>, Status> {
+    let dir = model_dir(&tenant.root, object, model)?;
+    let (entries, bytes) = cache.freeze(&dir).await.ok_or(Status::Conflict)?;
+    Ok(Json(Frozen { entries, bytes }))
+}
+
+/// Drop the model freeze, purge its cache entries and reload them from disk
+#[post("/admin/models/<object>/<model>/unfreeze")]
+pub async fn unfreeze(
+    _admin: Admin,
+    object: &str,
+    model: &str,
+    tenant: &Tenant,
+    cache: &State<FileCache>,
+) -> Result<Json<Unfrozen>, Status> {
+    let dir = model_dir(&tenant.root, object, model)?;
+    let (purged, queued) = cache.unfreeze(&dir).await.ok_or(Status::Conflict)?;
+    Ok(Json(Unfrozen { purged, queued }))
+}
@@ -0,0 +1,172 @@
+// tonic::Status is large by design, interceptors must return it
+#![allow(clippy::result_large_err)]
+
+use rocket::fairing::AdHoc;
+use std::net::SocketAddr;
+use std::path::Path;
+use tonic::service::interceptor::InterceptedService;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+
+use crate::cache::FileCache;
+use crate::pin::is_plain_path;
+use crate::stat::{Stat, StatKey};
+use crate::tenant::Tenant;
+use crate::Config;
+
+#[allow(clippy::all)]
+pub mod proto {
+    tonic::include_proto!("rtiles.admin");
+}
+
+use proto::admin_server::{Admin, AdminServer};
+use proto::{GetStatRequest, Metrics, PurgeCacheRequest, PurgeCacheResponse};
+
+/// gRPC admin service
+pub struct AdminService {
+    config: Config<'static>,
+    cache: FileCache,
+    stat: Stat,
+}
+
+#[tonic::async_trait]
+impl Admin for AdminService {
+    async fn purge_cache(
+        &self,
+        request: Request<PurgeCacheRequest>,
+    ) -> Result<Response<PurgeCacheResponse>, Status> {
+        let req = request.into_inner();
+        if !req.prefix.is_empty() && !is_plain_path(Path::new(&req.prefix)) {
+            return Err(Status::invalid_argument("illegal path prefix"));
+        }
+        let tenant = Tenant::resolve(&self.config, req.host.as_deref());
+        if req.host.is_some() && tenant.host.is_none() {
+            return Err(Status::invalid_argument("unknown virtual host"));
+        }
+        let entries = self.cache.purge(&tenant.root.join(req.prefix)).await;
+        info!("grpc: purged {} cache entries", entries);
+        Ok(Response::new(PurgeCacheResponse { entries }))
+    }
+
+    async fn get_stat(
+        &self,
+        request: Request<GetStatRequest>,
+    ) -> Result<Response<Metrics>, Status> {
+        let req = request.into_inner();
+        let key = StatKey::new(req.object.as_deref(), req.model.as_deref());
+        let metrics = self.stat.get(&key).await;
+        Ok(Response::new(Metrics {
+            hits: metrics.hits,
+            cached: metrics.cached,
+            bytes: metrics.bytes,
+        }))
+    }
+}
+
+/// Check admin bearer token in request metadata
+fn check_token(token: &str, request: &Request<()>) -> Result<(), Status> {
+    let bearer = request
+        .metadata()
+        .get("authorization")
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| x.strip_prefix("Bearer "));
+    match bearer {
+        Some(x) if x.trim() == token => Ok(()),
+        _ => Err(Status::unauthenticated("invalid admin token")),
+    }
+}
+
+/// Start gRPC admin server on liftoff if configured
+pub fn fairing() -> AdHoc {
+    AdHoc::on_liftoff("gRPC admin server", |rocket| {
+        Box::pin(async move {
+            let config = rocket.state::<Config<'_>>().unwrap();
+            let addr: SocketAddr = match config.admin.grpc {
+                Some(addr) => addr,
+                None => return,
+            };
+            let token = match config.admin.token {
+                Some(ref token) => token.clone(),
+                None => {
+                    error!("gRPC admin server requires admin token, not started");
+                    return;
+                }
+            };
+            let service = AdminService {
+                config: config.clone(),
+                cache: rocket.state::<FileCache>().unwrap().clone(),
+                stat: rocket.state::<Stat>().unwrap().clone(),
+            };
+            let service = InterceptedService::new(AdminServer::new(service), move |req| {
+                check_token(&token, &req).map(|_| req)
+            });
+            let shutdown = rocket.shutdown();
+
+            info!("gRPC admin server listening on {}", addr);
+            rocket::tokio::spawn(async move {
+                if let Err(err) = Server::builder()
+                    .add_service(service)
+                    .serve_with_shutdown(addr, shutdown)
+                    .await
+                {
+                    error!("gRPC admin server error: {}", err);
+                }
+            });
+        })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cache::FileCacheConfig;
+    use crate::events::Events;
+    use crate::usage::Usage;
+
+    #[test]
+    fn token() {
+        let mut req = Request::new(());
+        assert!(check_token("secret", &req).is_err());
+        req.metadata_mut()
+            .insert("authorization", "Bearer secret".parse().unwrap());
+        assert!(check_token("secret", &req).is_ok());
+        assert!(check_token("other", &req).is_err());
+    }
+
+    #[tokio::test]
+    async fn get_stat() {
+        let service = AdminService {
+            config: Config::default(),
+            cache: FileCache::new(FileCacheConfig::default(), Events::default()),
+            stat: Stat::new(&Default::default(), Events::default(), Usage::default()),
+        };
+        let key = StatKey::new(Some("city"), Some("block"));
+        let metrics = crate::stat::Metrics {
+            hits: 1,
+            cached: 0,
+            bytes: 10,
+            ..Default::default()
+        };
+        service.stat.insert(key, None, metrics).await;
+
+        let req = Request::new(GetStatRequest {
+            object: Some("city".to_owned()),
+            model: None,
+        });
+        let res = service.get_stat(req).await.unwrap().into_inner();
+        assert_eq!(res.hits, 1);
+        assert_eq!(res.bytes, 10);
+
+        let purge = |prefix: &str, host: Option<&str>| {
+            Request::new(PurgeCacheRequest {
+                prefix: prefix.to_owned(),
+                host: host.map(str::to_owned),
+            })
+        };
+        assert!(service.purge_cache(purge("../etc", None)).await.is_err());
+        assert!(service.purge_cache(purge("/etc", None)).await.is_err());
+        assert!(service.purge_cache(purge("tver", Some("x"))).await.is_err());
+        assert!(service.purge_cache(purge("tver/panorama", None)).await.is_ok());
+        assert!(service.purge_cache(purge("", None)).await.is_ok());
+    }
+}
@@ -0,0 +1,65 @@
+use rocket::fairing::AdHoc;
+use rocket::request::Outcome;
+use std::collections::HashMap;
+
+use crate::model::Model;
+use crate::stat::is_model_route;
+use crate::Config;
+
+/// Headers for the model, model specific values replace global ones
+pub fn model_headers<'a>(
+    global: &'a HashMap<String, String>,
+    model: &'a HashMap<String, String>,
+) -> Vec<(&'a str, &'a str)> {
+    let mut headers: Vec<(&str, &str)> = global
+        .iter()
+        .filter(|(name, _)| !model.keys().any(|x| x.eq_ignore_ascii_case(name)))
+        .chain(model.iter())
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect();
+    headers.sort();
+    headers
+}
+
+/// Add configured headers to model responses, attached after `Shield`
+/// so security headers like `X-Frame-Options` can be replaced
+pub fn fairing() -> AdHoc {
+    AdHoc::on_response("custom headers", |req, res| {
+        Box::pin(async move {
+            let config = match req.rocket().state::<Config<'_>>() {
+                Some(config) if is_model_route(req) => config,
+                _ => return,
+            };
+            let model = match req.guard::<Model>().await {
+                Outcome::Success(model) => model,
+                _ => return,
+            };
+            for (name, value) in model_headers(&config.headers, &config.model(&model).headers) {
+                res.set_raw_header(name.to_owned(), value.to_owned());
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn merged_headers() {
+        let global: HashMap<String, String> = [
+            ("X-Frame-Options".to_owned(), "DENY".to_owned()),
+            ("Timing-Allow-Origin".to_owned(), "*".to_owned()),
+        ]
+        .into();
+        let model: HashMap<String, String> =
+            [("x-frame-options".to_owned(), "SAMEORIGIN".to_owned())].into();
+        assert_eq!(
+            model_headers(&global, &model),
+            [
+                ("Timing-Allow-Origin", "*"),
+                ("x-frame-options", "SAMEORIGIN")
+            ]
+        );
+    }
+}
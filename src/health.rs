@@ -0,0 +1,34 @@
+use rocket::serde::json::Json;
+use rocket::serde::Serialize;
+use rocket::State;
+
+use crate::manifest::{ManifestCheck, ManifestSummary};
+use crate::mount::StorageHealth;
+
+/// Server health summary
+#[derive(Debug, Serialize)]
+pub struct Health {
+    pub status: &'static str,  // `ok` or `degraded`
+    pub storage: &'static str, // `ok` or `degraded`, cached content only
+    pub manifest: ManifestSummary, // paths at `/admin/manifest`
+}
+
+/// Health status for load balancers and monitoring
+#[get("/health")]
+pub fn health(manifest: &State<ManifestCheck>, storage: &State<StorageHealth>) -> Json<Health> {
+    let manifest = ManifestSummary::from(&manifest.report());
+    let storage = match storage.is_degraded() {
+        true => "degraded",
+        false => "ok",
+    };
+    let status = if manifest.mismatches == 0 && storage == "ok" {
+        "ok"
+    } else {
+        "degraded"
+    };
+    Json(Health {
+        status,
+        storage,
+        manifest,
+    })
+}
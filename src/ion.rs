@@ -0,0 +1,112 @@
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::State;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::access::{AccessKey, AccessMode, ModelAccess, SessionId};
+use crate::config::BaseUrl;
+use crate::tenant::Tenant;
+use crate::{Config, Model};
+
+/// Cesium ion asset mapped to the model
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct IonAsset {
+    pub object: String,
+    pub model: String,
+    #[serde(rename = "type", default = "default_asset_type")]
+    pub asset_type: String, // ion asset type, "3DTILES" by default
+}
+
+fn default_asset_type() -> String {
+    "3DTILES".to_owned()
+}
+
+/// Ion assets configuration, asset id to model mapping
+pub type IonConfig = HashMap<String, IonAsset>;
+
+/// Ion asset endpoint response
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct IonEndpoint {
+    #[serde(rename = "type")]
+    pub asset_type: String,
+    pub url: String,
+    pub access_token: String,
+    pub attributions: Vec<String>,
+}
+
+impl IonEndpoint {
+    pub fn new(base_url: &str, asset: &IonAsset, token: &str) -> Self {
+        // token is encoded, it can't add query params
+        let mut query = reqwest::Url::parse("http://localhost/").expect("valid url");
+        query.query_pairs_mut().append_pair("access_token", token);
+        IonEndpoint {
+            asset_type: asset.asset_type.clone(),
+            url: format!(
+                "{}/models/{}/{}/tileset.json?{}",
+                base_url,
+                asset.object,
+                asset.model,
+                query.query().unwrap_or_default()
+            ),
+            access_token: token.to_owned(),
+            attributions: Vec::new(),
+        }
+    }
+}
+
+/// Cesium ion compatible asset endpoint, the access token is used
+/// as the user session identifier for the model access check
+#[get("/v1/assets/<id>/endpoint")]
+pub async fn ion_endpoint(
+    id: &str,
+    session_id: SessionId,
+    base_url: BaseUrl,
+    tenant: &Tenant,
+    config: &State<Config<'_>>,
+    access: &State<ModelAccess>,
+) -> Result<Json<IonEndpoint>, Status> {
+    let asset = config.ion.get(id).ok_or(Status::NotFound)?;
+    let token = session_id.value().ok_or(Status::Unauthorized)?.to_owned();
+
+    let model = Model::new(Some(&asset.object), Some(&asset.model));
+    let key = AccessKey::new(Arc::new(model), session_id).for_tenant(tenant);
+    match access.check(&key).await {
+        AccessMode::Granted => Ok(Json(IonEndpoint::new(&base_url.0, asset, &token))),
+        AccessMode::Denied => Err(Status::Forbidden),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn endpoint() {
+        let asset = IonAsset {
+            object: "tver".to_owned(),
+            model: "panorama".to_owned(),
+            asset_type: default_asset_type(),
+        };
+        let res = IonEndpoint::new("http://localhost/3d", &asset, "secret");
+        assert_eq!(
+            res.url,
+            "http://localhost/3d/models/tver/panorama/tileset.json?access_token=secret"
+        );
+        assert_eq!(
+            rocket::serde::json::to_string(&res).unwrap(),
+            concat!(
+                r#"{"type":"3DTILES","url":"http://localhost/3d/models/tver/panorama/"#,
+                r#"tileset.json?access_token=secret","accessToken":"secret","attributions":[]}"#
+            )
+        );
+        let res = IonEndpoint::new("http://localhost/3d", &asset, "a&b=c#d");
+        assert_eq!(
+            res.url,
+            "http://localhost/3d/models/tver/panorama/tileset.json?access_token=a%26b%3Dc%23d"
+        );
+        assert_eq!(res.access_token, "a&b=c#d");
+    }
+}
@@ -0,0 +1,829 @@
+#[macro_use]
+extern crate rocket;
+
+use rocket::http::Accept;
+use rocket::shield::Shield;
+use rocket::request::Request;
+use rocket::serde::json::Json;
+use rocket::{Build, Rocket, Route, State};
+use rocket::{
+    figment::{
+        providers::{Env, Format, Serialized, Toml},
+        Figment, Profile,
+    },
+    http::Status,
+};
+use std::{
+    env,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    process,
+    sync::Arc,
+    time::Duration,
+};
+
+mod model;
+
+mod meta;
+use crate::meta::{Meta, MetaCache, MetaCacheConfig};
+
+mod config;
+pub use crate::config::Config;
+use crate::config::{SERVER_NAME, SERVER_VERSION};
+
+mod access;
+use crate::access::{AccessConfig, AccessKey, AuthMode, ModelAccess, StatAccess};
+
+mod cache;
+use crate::cache::{
+    CacheBypass, CachedNamedFile, Content, FileCache, FileCacheConfig, Validators,
+};
+
+mod cache_control;
+use crate::cache_control::CacheControl;
+
+mod headers;
+
+mod preload;
+use crate::preload::{PreloadCache, Preloaded};
+
+mod timeout;
+
+mod mount;
+use crate::mount::StorageHealth;
+
+mod runtime;
+pub use crate::runtime::RuntimeConfig;
+
+mod download;
+use crate::download::Attachment;
+
+#[cfg(any(test, feature = "dev-auth"))]
+pub mod mock_auth;
+use crate::model::Model;
+
+mod attribution;
+
+mod b3dm;
+
+mod variant;
+
+// route modules are public, rocket exports a uri macro per route
+// that is reported as an unused import in private modules
+pub mod admin;
+use crate::admin::Admin;
+
+pub mod extent;
+
+mod registry;
+use crate::registry::ModelRegistry;
+
+mod bloom;
+
+pub mod search;
+
+pub mod preview;
+
+pub mod dashboard;
+
+pub mod openapi;
+
+mod grpc;
+
+pub mod events;
+use crate::events::Events;
+
+mod webhook;
+
+mod logger;
+use crate::logger::AccessLog;
+
+mod notify;
+
+mod check;
+
+mod cli;
+use crate::cli::Command;
+
+mod warm;
+
+mod bench;
+
+mod replay;
+
+mod query;
+
+mod tilestats;
+use crate::tilestats::TilesetStatsCache;
+
+pub mod listing;
+
+mod stat;
+use stat::{Metrics, Report, Stat, StatKey, Timer};
+
+mod usage;
+use crate::usage::Usage;
+
+mod quota;
+use crate::quota::ApiClient;
+
+mod tenant;
+use crate::tenant::Tenant;
+
+mod proxy;
+
+mod dns;
+
+mod referer;
+
+mod shared;
+
+mod digest;
+
+pub mod manifest;
+use crate::manifest::ManifestCheck;
+
+pub mod health;
+use crate::digest::{DigestCache, Digested};
+
+pub mod upload;
+
+pub mod publish;
+
+pub mod pin;
+
+pub mod freeze;
+
+pub mod peers;
+use crate::peers::Peers;
+
+pub mod token;
+
+pub mod report;
+
+pub mod metrics;
+use crate::metrics::ServerMetrics;
+
+mod error;
+use crate::error::Error;
+
+mod mime;
+use crate::mime::ContentTypes;
+
+pub mod raster;
+use crate::raster::{MbTiles, RasterConfig};
+
+pub mod wmts;
+
+pub mod ion;
+
+pub mod validate;
+
+mod placeholder;
+use crate::placeholder::{Placeholders, TileError};
+
+#[catch(default)]
+fn default_catcher(status: Status, _: &Request) -> String {
+    format!("{}", status)
+}
+
+#[allow(clippy::too_many_arguments)]
+#[get("/models/<_>/<_>/<path..>?<verify>&<download>", rank = 10)]
+async fn tileset(
+    timer: Timer,
+    key: AccessKey,
+    client: ApiClient,
+    tenant: &Tenant,
+    path: PathBuf,
+    verify: Option<&str>,
+    download: Option<&str>,
+    admin: Option<Admin>,
+    accept: Option<&Accept>,
+    bypass: CacheBypass,
+    validators: Validators<'_>,
+    config: &State<Config<'_>>,
+    cache: &State<FileCache>,
+    metacache: &State<MetaCache>,
+    digests: &State<DigestCache>,
+    peers: &State<Peers>,
+    preloads: &State<PreloadCache>,
+    registry: &State<ModelRegistry>,
+    storage: &State<StorageHealth>,
+    stat: &State<Stat>,
+) -> Result<Attachment<Preloaded<Digested<CacheControl<CachedNamedFile>>>>, TileError> {
+    config.storage.check_path(&path).map_err(Error::BadRequest)?;
+
+    // build path to served file
+    let mut dir = tenant.root.clone();
+    dir.push(key.model.object.as_ref().unwrap());
+    let mut file = dir.join(key.model.name.as_ref().unwrap()).join(&path);
+
+    // use model variant compatible with client capabilities if exists
+    let variants = &config.model(&key.model).variants;
+    let candidates = match storage.is_degraded() {
+        true => Vec::new(),
+        false => variant::variant_dirs(accept, variants),
+    };
+    for variant in candidates {
+        let f = dir.join(variant).join(&path);
+        if metacache.metadata(&f).await.is_ok() {
+            file = f;
+            break;
+        }
+    }
+
+    // paths missing in the last storage scan are rejected without filesystem calls
+    let glb = config.storage.extract_glb && b3dm::is_glb(&file);
+    let cached = (cache.has_missing_grace() || cache.is_frozen(&file)) && cache.contains(&file);
+    if !glb && !cached && !registry.may_exist(&tenant.root, &file) {
+        let path = file.to_string_lossy();
+        return Err(Error::NotFound(format!("{} not in storage", path)).into());
+    }
+
+    // get path metadata and serve file from disk or cache
+    let (mut digest, mut verified) = (None, None);
+    let mut uris = Arc::default();
+    // frozen model files are served from the snapshot only, never from disk
+    let frozen = match cache.is_frozen(&file) {
+        true => Some(
+            cached_only(cache, &file, config.index(&key.model))
+                .await
+                .ok_or_else(|| {
+                    let path = file.to_string_lossy();
+                    Error::Unavailable(format!("model frozen, {} not cached", path))
+                })?,
+        ),
+        false => None,
+    };
+    let res = if storage.is_degraded() {
+        // storage is unavailable, cached content only
+        let (f, content) = cached_only(cache, &file, config.index(&key.model))
+            .await
+            .ok_or_else(|| {
+                let path = file.to_string_lossy();
+                Error::Unavailable(format!("storage degraded, {} not cached", path))
+            })?;
+        file = f;
+        CachedNamedFile::Cached(Box::new(content))
+    } else if let Some((f, content)) = frozen {
+        file = f;
+        CachedNamedFile::Cached(Box::new(content))
+    } else {
+        match metacache.metadata(&file).await {
+            Err(err) if config.storage.extract_glb && b3dm::is_glb(&file) => {
+                // try to extract glb payload from b3dm tile with the same name
+                let b3dm = file.with_extension("b3dm");
+                let meta = metacache.metadata(&b3dm).await.map_err(|_| err)?;
+                debug!("serving glb from b3dm: {:?}", &b3dm);
+                b3dm::open_glb(&file, &b3dm, &meta, cache).await?
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound && cache.has_missing_grace() => {
+                // deleted file is served from the cache during the grace period
+                let content = cache.get_missing(&file).await.ok_or(err)?;
+                CachedNamedFile::Missing(Box::new(content))
+            }
+            meta => {
+                let mut meta = meta?;
+                if meta.is_dir() {
+                    // if path is dir -- use first existing index file
+                    let dir = file;
+                    let mut found = None;
+                    for name in config.index(&key.model) {
+                        let f = dir.join(name);
+                        if let Ok(m) = metacache.metadata(&f).await {
+                            found = Some((f, m));
+                            break;
+                        }
+                    }
+                    (file, meta) = found.ok_or_else(|| {
+                        Error::NotFound(format!("no index file in {}", dir.to_string_lossy()))
+                    })?;
+                }
+                if bypass.0 {
+                    // drop cached content and metadata, the file is read from disk
+                    cache.invalidate(&file).await;
+                    meta = metacache.metadata(&file).await?;
+                }
+                debug!("serving file: {:?}", &file);
+                if config.storage.preload > 0 && is_index(&file, config.index(&key.model)) {
+                    uris = preloads.get(&file, &meta, config.storage.preload).await;
+                }
+                match config.model(&key.model).attribution {
+                    Some(ref text) if is_index(&file, config.index(&key.model)) => {
+                        attribution::open_attributed(&file, &meta, text, cache).await?
+                    }
+                    _ => {
+                        // digest of the stored file, gzipped payloads may be served decoded
+                        let detect_gzip = config.model(&key.model).detect_gzip;
+                        if config.storage.digest && !mime::is_vector_tile(&file) && !detect_gzip {
+                            if matches!(verify, Some("1" | "true")) && admin.is_some() {
+                                let (d, valid) = digests.verify(&file, &meta).await?;
+                                if !valid {
+                                    error!("file digest mismatch: {:?}", &file);
+                                    cache.invalidate(&file).await;
+                                }
+                                (digest, verified) = (Some(d), Some(valid));
+                            } else {
+                                digest = Some(digests.get(&file, &meta).await?);
+                            }
+                        }
+                        if bypass.0 {
+                            CachedNamedFile::open_with_detection(&file, &meta, cache, detect_gzip)
+                                .await?
+                                .refreshed()
+                        } else {
+                            peers
+                                .open(tenant, &file, &meta, cache, detect_gzip)
+                                .await?
+                        }
+                    }
+                }
+            }
+        }
+    };
+
+    let filename = download::is_attachment(&file, download, &config.storage.attachments)
+        .then(|| file.file_name().map(|x| x.to_string_lossy().into_owned()))
+        .flatten();
+
+    // prepare and insert stat
+    let model_config = config.model(&key.model);
+    let max_age = model_config.max_age_for(&config.storage, &file);
+    insert_stat(stat, key.model, client, &res, validators, timer).await;
+
+    // add cache, digest, preload and download headers to response
+    Ok(Attachment {
+        inner: Preloaded {
+            inner: Digested {
+                inner: CacheControl::new(res, config.storage.max_age)
+                    .for_model(model_config)
+                    .with_max_age(max_age)
+                    .for_file(config.storage.immutable.as_ref(), &file),
+                digest,
+                verified,
+            },
+            uris,
+        },
+        filename,
+    })
+}
+
+/// Cached content of the file or its directory index
+async fn cached_only(
+    cache: &FileCache,
+    file: &Path,
+    index: &[String],
+) -> Option<(PathBuf, Content)> {
+    let paths = std::iter::once(file.to_path_buf()).chain(index.iter().map(|x| file.join(x)));
+    for path in paths {
+        if let Some(content) = cache.get(&path).await {
+            return Some((path, content));
+        }
+    }
+    None
+}
+
+/// Is the file one of the directory index files?
+fn is_index(file: &Path, index: &[String]) -> bool {
+    file.file_name()
+        .is_some_and(|name| index.iter().any(|x| name == x.as_str()))
+}
+
+/// Prepare and insert stat for the served content,
+/// not modified and HEAD responses are counted without bytes
+async fn insert_stat(
+    stat: &Stat,
+    model: Arc<Model>,
+    client: ApiClient,
+    res: &CachedNamedFile,
+    validators: Validators<'_>,
+    timer: Timer,
+) {
+    let key = StatKey { model, class: None };
+    let not_modified = validators.not_modified(res.meta());
+    let head = validators.is_head();
+    let metrics = Metrics {
+        hits: 1,
+        cached: res.is_cached() as u64,
+        bytes: if not_modified || head { 0 } else { res.meta().len() },
+        time_us: timer.elapsed().as_micros() as u64,
+        timed: 1,
+        hits_304: not_modified as u64,
+        hits_head: head as u64,
+    };
+    stat.insert(key, client.0, metrics).await;
+}
+
+#[get("/stat/<_..>?<class>&<children>")]
+async fn get_stat(
+    access: StatAccess,
+    class: Option<&str>,
+    children: Option<&str>,
+    stat: &State<Stat>,
+) -> Result<Json<Report>, Status> {
+    let class = match class {
+        Some(class) => Some(stat::parse_class(class).ok_or(Status::BadRequest)?),
+        None => None,
+    };
+    let key = StatKey {
+        model: access.model,
+        class,
+    };
+    let mut report = Report::from(stat.get(&key).await);
+    // per-model breakdown of the object
+    let object = key.model.object.is_some() && key.model.name.is_none();
+    if object && matches!(children, Some("1" | "true")) {
+        let children = stat.children(&key).await;
+        report.children = Some(children.into_iter().map(|(k, v)| (k, v.into())).collect());
+    }
+    Ok(Json(report))
+}
+
+#[get("/ping")]
+async fn ping() -> &'static str {
+    "pong"
+}
+
+/// Local url of the server base path for command line clients
+fn server_url(figment: &Figment, config: &Config) -> String {
+    let rocket: rocket::Config = figment.extract().unwrap_or_default();
+    format!(
+        "http://{}:{}{}",
+        rocket.address,
+        rocket.port,
+        config.base_path.path().as_str().trim_end_matches('/')
+    )
+}
+
+/// Local url of the admin listener, public server if not configured
+fn admin_url(figment: &Figment, config: &Config) -> String {
+    match config.admin.address {
+        Some(addr) => format!(
+            "http://{}{}",
+            addr,
+            config.base_path.path().as_str().trim_end_matches('/')
+        ),
+        None => server_url(figment, config),
+    }
+}
+
+/// Tile serving routes
+fn public_routes() -> Vec<Route> {
+    routes![
+        tileset,
+        raster::raster_tile,
+        wmts::get_capabilities,
+        ion::ion_endpoint,
+        validate::validate,
+        listing::listing,
+        extent::extent,
+        search::search,
+        search::models,
+        preview::preview,
+        openapi::openapi,
+        health::health,
+        token::issue,
+        peers::peer_file,
+        ping
+    ]
+}
+
+/// Operational routes, served by the separate admin listener if configured
+fn admin_routes(read_only: bool) -> Vec<Route> {
+    let mut routes = routes![
+        admin::model_stats,
+        admin::revoke_session,
+        upload::storage,
+        manifest::verify,
+        manifest::report,
+        pin::pin,
+        freeze::freeze,
+        freeze::unfreeze,
+        dashboard::dashboard,
+        dashboard::summary,
+        events::live,
+        report::usage,
+        token::share,
+        metrics::metrics,
+        get_stat
+    ];
+    // storage mutating routes are not mounted on read-only replicas
+    if !read_only {
+        routes.extend(routes![upload::upload, upload::delete, publish::activate]);
+    }
+    routes
+}
+
+/// Start mock auth server with the policy and use it for access checks
+#[cfg(feature = "dev-auth")]
+async fn dev_auth<'a>(mut config: Config<'a>, policy: &str) -> Config<'a> {
+    let policy = mock_auth::MockPolicy::parse(policy).unwrap_or_else(|err| {
+        eprintln!("{err}");
+        process::exit(2)
+    });
+    let addr = mock_auth::spawn(policy, ([127, 0, 0, 1], 0).into())
+        .await
+        .unwrap_or_else(|err| {
+            eprintln!("Problem start dev auth server: {err}");
+            process::exit(1)
+        });
+    println!("Dev auth server at http://{addr}");
+    config.access.server = rocket::http::uri::Absolute::parse_owned(format!("http://{addr}"))
+        .expect("valid server url");
+    config
+}
+
+/// Configuration sources: defaults, `rtiles.toml` and `RTILES_` env vars
+fn figment() -> Figment {
+    Figment::from(rocket::Config::default())
+        .merge(Serialized::defaults(Config::default()))
+        .merge(Toml::file("rtiles.toml").nested())
+        .merge(Env::prefixed("RTILES").global())
+        .select(Profile::from_env_or("RTILES_PROFILE", "default"))
+}
+
+/// Runtime for [`run()`] with the configured thread pools, defaults
+/// if the config is broken so that `run()` reports the error
+pub fn runtime() -> std::io::Result<tokio::runtime::Runtime> {
+    figment()
+        .extract_inner::<RuntimeConfig>("runtime")
+        .unwrap_or_default()
+        .build()
+}
+
+/// Parse command line and config, run the command
+pub async fn run() {
+    // parse command line, exit if error
+    let command = Command::parse(env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{err}\n\n{}", cli::USAGE);
+        process::exit(2)
+    });
+    if command == Command::Help {
+        println!("{}", cli::USAGE);
+        return;
+    }
+
+    // extract the config, exit if error
+    let figment = figment();
+    let config: Config = figment.extract().unwrap_or_else(|err| {
+        eprintln!("Problem parsing config: {err}");
+        process::exit(1)
+    });
+    // rocket reports the worker count of its own config
+    let figment = figment.merge(("workers", config.runtime.workers));
+
+    // embedded mock auth server replaces the configured one
+    #[cfg(feature = "dev-auth")]
+    let config = match command {
+        Command::DevServe(ref policy) => dev_auth(config, policy).await,
+        _ => config,
+    };
+
+    match command {
+        Command::DevServe(_) if !cfg!(feature = "dev-auth") => {
+            eprintln!("--dev-auth requires a build with the `dev-auth` feature");
+            process::exit(2)
+        }
+        Command::Check => {
+            let passed = check::run(&config).await;
+            process::exit(if passed { 0 } else { 1 })
+        }
+        Command::Warm(ref args) => {
+            let url = args.url.clone().unwrap_or_else(|| server_url(&figment, &config));
+            let passed = warm::run(args, &url).await;
+            process::exit(if passed { 0 } else { 1 })
+        }
+        Command::Bench(ref args) => {
+            let url = args.url.clone().unwrap_or_else(|| server_url(&figment, &config));
+            let passed = bench::run(args, &url).await;
+            process::exit(if passed { 0 } else { 1 })
+        }
+        Command::Replay(ref args) => {
+            let url = args.url.clone().unwrap_or_else(|| server_url(&figment, &config));
+            let passed = replay::run(args, &url).await;
+            process::exit(if passed { 0 } else { 1 })
+        }
+        Command::Stat(ref args) => {
+            let url = args.url.clone().unwrap_or_else(|| admin_url(&figment, &config));
+            let token = args.token.as_deref().or(config.admin.token.as_deref());
+            let passed = query::run(args, &url, token).await;
+            process::exit(if passed { 0 } else { 1 })
+        }
+        _ => (),
+    }
+
+    // write logs to files if configured, exit if error
+    let level = figment
+        .extract_inner::<rocket::config::LogLevel>("log_level")
+        .map(Into::into)
+        .unwrap_or(log::LevelFilter::Info);
+    logger::init(&config.logging, level).unwrap_or_else(|err| {
+        eprintln!("Problem open log file: {err}");
+        process::exit(1)
+    });
+
+    // span timings are flushed when the server stops
+    #[cfg(feature = "flame")]
+    let _flame = logger::flame(&config.logging).unwrap_or_else(|err| {
+        eprintln!("Problem open flame file: {err}");
+        process::exit(1)
+    });
+    #[cfg(not(feature = "flame"))]
+    if config.logging.flame.is_some() {
+        eprintln!("WARNING: logging.flame requires a build with the `flame` feature");
+    }
+
+    let no_auth = config.access.mode == AuthMode::Disabled;
+    let (public, admin) = build(config, figment).unwrap_or_else(|err| {
+        eprintln!("{err}");
+        process::exit(1)
+    });
+
+    println!(
+        "Starting 3D tiles rocket server, {}/{}",
+        SERVER_NAME, SERVER_VERSION
+    );
+    if no_auth {
+        eprintln!("WARNING: access checks are disabled, every model is served to everyone. Do not use in production!");
+    }
+
+    let res = match admin {
+        Some(admin) => tokio::try_join!(public.launch(), admin.launch()).map(|_| ()),
+        None => public.launch().await.map(|_| ()),
+    };
+    if let Err(err) = res {
+        eprintln!("Server error: {err}");
+        process::exit(1)
+    }
+}
+
+/// Build public and optional admin servers sharing state, admin routes
+/// are served by the public server if no admin address is configured
+pub fn build(
+    config: Config<'static>,
+    figment: Figment,
+) -> Result<(Rocket<Build>, Option<Rocket<Build>>), String> {
+    // open access log file if configured
+    let access_log = AccessLog::new(&config.logging)
+        .map_err(|err| format!("Problem open log file: {err}"))?;
+
+    // create server events bus
+    let events = Events::default();
+
+    // send notable events to webhooks
+    webhook::start(&config.webhooks, &events);
+
+    // create model access cached resolver, exit if error
+    let access = ModelAccess::new(&config.access, events.clone())
+        .map_err(|err| format!("Problem create model access client: {err}"))?;
+
+    // watch storage IO errors, degraded storage serves cached content only
+    let storage = StorageHealth::new(config.storage.degrade_errors, events.clone());
+    storage.start(
+        config.storage.root.clone(),
+        Duration::from_secs(config.storage.recover_probe.max(1)),
+    );
+
+    // create metadata cache
+    let metacache = MetaCache::new(MetaCacheConfig::default()).with_health(&storage);
+
+    // create file cache, invalidated along with the metadata
+    let cache = FileCache::new(
+        FileCacheConfig {
+            size: config.storage.cache_size,
+            mmap_max: config.storage.mmap_max,
+            read_buffer: config.storage.read_buffer,
+            stream_threshold: config.storage.stream_threshold,
+            dedup: config.storage.dedup,
+            pin_budget: config.storage.pin_budget,
+            load_concurrency: config.storage.load_concurrency,
+            missing_grace: config.storage.missing_grace,
+        },
+        events.clone(),
+    )
+    .with_meta(&metacache);
+
+    // create stat server with usage counters
+    let usage = Usage::new(&config.usage);
+    let stat = Stat::new(&config.stat, events.clone(), usage);
+
+    // create tileset statistics cache, 5 minutes ttl
+    let tilestats = TilesetStatsCache::new(5 * 60);
+
+    // create response histograms for Prometheus
+    let server_metrics = ServerMetrics::new(&config.metrics);
+
+    // create storage inventory, rescanned in background
+    let registry = ModelRegistry::new().with_path_filter(config.storage.path_filter);
+    registry.start(tenant::roots(&config), config.storage.scan_interval);
+
+    // verify model manifests in background if configured
+    let manifests = ManifestCheck::new();
+    if config.storage.verify_manifests {
+        manifests.start(config.storage.root.clone(), registry.clone());
+    }
+
+    // reject legacy object paths clashing with the routes
+    tenant::check_object_paths(&config)?;
+
+    // create cluster peers client, exit if error
+    config.peers.check()?;
+    let peers =
+        Peers::new(&config.peers).map_err(|err| format!("Problem create peers client: {err}"))?;
+
+    // create MBTiles connections pool
+    let mbtiles = MbTiles::new();
+
+    // create content types table
+    let content_types = ContentTypes::new(&config.content_types);
+
+    // load tile placeholders served on storage failures, exit if error
+    let placeholders = Placeholders::load(&config.storage.placeholders)
+        .map_err(|err| format!("Problem load placeholder tiles: {err}"))?;
+
+    // set server base path from config
+    let base_path = config.base_path.to_owned();
+    let timeouts = config.timeouts.clone();
+    let read_only = config.read_only;
+
+    // rocket adds default security headers, an empty shield disables them
+    let shield = match config.security_headers {
+        true => Shield::default(),
+        false => Shield::new(),
+    };
+
+    // admin listener shares state with the public server
+    let tenant_paths = tenant::base_paths(&config);
+    let admin = config.admin.address.map(|addr| {
+        let figment = figment
+            .clone()
+            .merge(("address", addr.ip()))
+            .merge(("port", addr.port()));
+        let mut admin = rocket::custom(figment)
+            .manage(config.clone())
+            .manage(access.clone())
+            .manage(cache.clone())
+            .manage(stat.clone())
+            .manage(tilestats.clone())
+            .manage(registry.clone())
+            .manage(metacache.clone())
+            .manage(manifests.clone())
+            .manage(storage.clone())
+            .manage(server_metrics.clone())
+            .manage(events.clone())
+            .mount(base_path.clone(), routes![ping, health::health])
+            .register("/", catchers![default_catcher])
+            .attach(access_log.clone());
+        // tenant routes like pin resolve the storage root by the base path
+        for path in &tenant_paths {
+            admin = admin.mount(
+                path.clone(),
+                timeout::with_timeout(admin_routes(read_only), timeouts.admin),
+            );
+        }
+        admin
+    });
+
+    let mut public = rocket::custom(figment)
+        .manage(config)
+        .manage(access)
+        .manage(cache)
+        .manage(metacache)
+        .manage(DigestCache::new())
+        .manage(PreloadCache::new())
+        .manage(manifests)
+        .manage(storage)
+        .manage(peers)
+        .manage(stat)
+        .manage(content_types)
+        .manage(placeholders)
+        .manage(mbtiles)
+        .manage(tilestats)
+        .manage(registry)
+        .manage(server_metrics.clone())
+        .manage(events)
+        .register("/", catchers![default_catcher])
+        .attach(shield)
+        .attach(headers::fairing())
+        .attach(cache::method_fairing())
+        .attach(stat::fairing())
+        .attach(metrics::fairing(server_metrics))
+        .attach(grpc::fairing())
+        .attach(notify::fairing())
+        .attach(tenant::SubdomainObject)
+        .attach(tenant::LegacyObjectPaths)
+        .attach(access_log);
+
+    // mount public routes for every virtual host base path
+    for path in tenant_paths {
+        if admin.is_none() {
+            // no separate listener, serve operational routes on the public port
+            public = public.mount(
+                path.clone(),
+                timeout::with_timeout(admin_routes(read_only), timeouts.admin),
+            );
+        }
+        public = public.mount(path, timeout::with_timeout(public_routes(), timeouts.tile));
+    }
+    Ok((public, admin))
+}
@@ -0,0 +1,84 @@
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::serde::Serialize;
+use rocket::State;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::access::AccessKey;
+use crate::admin::Admin;
+use crate::tenant::Tenant;
+use crate::Config;
+
+/// Directory listing entry
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Entry {
+    pub name: String,
+    pub dir: bool,
+    pub size: u64,
+    pub modified: Option<u64>, // unix time in seconds
+}
+
+/// List directory entries sorted by name
+pub async fn list_dir(path: &Path) -> io::Result<Vec<Entry>> {
+    let mut dir = tokio::fs::read_dir(path).await?;
+    let mut entries = Vec::new();
+    while let Some(entry) = dir.next_entry().await? {
+        let meta = entry.metadata().await?;
+        entries.push(Entry {
+            name: entry.file_name().to_string_lossy().into_owned(),
+            dir: meta.is_dir(),
+            size: meta.len(),
+            modified: meta
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs()),
+        });
+    }
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(entries)
+}
+
+/// Directory listing of the model path, allowed for admin
+/// or for users with model access if enabled in storage config
+#[get("/list/<_>/<_>/<path..>")]
+pub async fn listing(
+    key: Result<AccessKey, ()>,
+    admin: Option<Admin>,
+    path: PathBuf,
+    tenant: &Tenant,
+    config: &State<Config<'_>>,
+) -> Result<Json<Vec<Entry>>, Status> {
+    if admin.is_none() && !config.storage.listing {
+        return Err(Status::NotFound);
+    }
+    let key = key.map_err(|_| Status::Forbidden)?;
+
+    let mut dir = tenant.root.clone();
+    dir.push(key.model.object.as_ref().unwrap());
+    dir.push(key.model.name.as_ref().unwrap());
+    dir.push(path);
+
+    list_dir(&dir).await.map(Json).map_err(|err| {
+        debug!("listing error: {}", err);
+        Status::NotFound
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn list() {
+        let entries = list_dir(Path::new(".")).await.unwrap();
+        let src = entries.iter().find(|e| e.name == "src").unwrap();
+        assert!(src.dir);
+        let readme = entries.iter().find(|e| e.name == "README.md").unwrap();
+        assert!(!readme.dir);
+        assert!(readme.size > 0);
+        assert!(readme.modified.is_some());
+    }
+}
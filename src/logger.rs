@@ -0,0 +1,523 @@
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::uri::Origin;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::{Request, Response};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::net::UdpSocket;
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use time::OffsetDateTime;
+
+use crate::access::secret_tag;
+use crate::config::{SERVER_NAME, SERVER_VERSION};
+use crate::proxy::Forwarded;
+
+/// Log files params
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct LogConfig {
+    pub file: Option<PathBuf>,        // application log, stdout only if not set
+    pub access_file: Option<PathBuf>, // access log of served requests, disabled if not set
+    pub max_size: u64,                // rotate file when exceeds size in MB, 0 to disable
+    pub daily: bool,                  // rotate file at midnight UTC
+    pub keep: usize,                  // count of rotated files to retain
+    pub syslog: Option<SyslogConfig>, // send records to syslog, disabled if not set
+    pub journald: bool,               // send records to systemd journal
+    pub stdout: bool,                 // also print records to stdout
+    pub flame: Option<PathBuf>,       // folded span timings for flamegraphs, `flame` feature builds
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            file: None,
+            access_file: None,
+            max_size: 100, // 100 MB
+            daily: false,
+            keep: 7,
+            syslog: None,
+            journald: false,
+            stdout: true,
+            flame: None,
+        }
+    }
+}
+
+/// Timestamp in RFC 3339 format, UTC
+fn timestamp(t: OffsetDateTime) -> String {
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        t.year(),
+        t.month() as u8,
+        t.day(),
+        t.hour(),
+        t.minute(),
+        t.second()
+    )
+}
+
+/// Log file with size and time based rotation
+pub struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    day: u16, // ordinal day of the year when file was opened
+    max_size: u64,
+    daily: bool,
+    keep: usize,
+}
+
+/// Path of the rotated file with the index
+fn rotated(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+impl RotatingFile {
+    pub fn open(path: &Path, config: &LogConfig) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile {
+            path: path.to_path_buf(),
+            file,
+            size,
+            day: OffsetDateTime::now_utc().ordinal(),
+            max_size: config.max_size * 1024 * 1024,
+            daily: config.daily,
+            keep: config.keep,
+        })
+    }
+
+    /// Shift rotated files, drop the oldest and reopen the log
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let _ = fs::remove_file(rotated(&self.path, self.keep));
+            for n in (1..self.keep).rev() {
+                let _ = fs::rename(rotated(&self.path, n), rotated(&self.path, n + 1));
+            }
+            fs::rename(&self.path, rotated(&self.path, 1))?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+
+    /// Write the line, rotate file before if needed
+    pub fn write_line(&mut self, now: OffsetDateTime, line: &str) -> io::Result<()> {
+        let by_size = self.max_size > 0 && self.size + line.len() as u64 + 1 > self.max_size;
+        let by_time = self.daily && now.ordinal() != self.day;
+        if (by_size && self.size > 0) || by_time {
+            self.rotate()?;
+        }
+        self.day = now.ordinal();
+        writeln!(self.file, "{}", line)?;
+        self.size += line.len() as u64 + 1;
+        Ok(())
+    }
+}
+
+/// Syslog sink params
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct SyslogConfig {
+    pub address: String, // `udp://host:port` or `unix:/dev/log`
+    pub facility: u8,    // syslog facility code, 3 is daemon
+}
+
+impl Default for SyslogConfig {
+    fn default() -> Self {
+        SyslogConfig {
+            address: "unix:/dev/log".to_owned(),
+            facility: 3,
+        }
+    }
+}
+
+/// Syslog severity of the log level
+fn severity(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+/// Format RFC 5424 syslog message
+fn syslog_message(pri: u8, ts: &str, host: &str, target: &str, msg: &str) -> String {
+    format!(
+        "<{}>1 {} {} {} {} - [origin software=\"{}\" swVersion=\"{}\"][rtiles@32473 target=\"{}\"] {}",
+        pri,
+        ts,
+        host,
+        SERVER_NAME,
+        std::process::id(),
+        SERVER_NAME,
+        SERVER_VERSION,
+        target.replace(['"', '\\', ']'], "_"),
+        msg
+    )
+}
+
+/// Syslog datagram socket
+enum SyslogSocket {
+    Udp(UdpSocket),
+    Unix(UnixDatagram),
+}
+
+/// Syslog sink, RFC 5424 over UDP or unix socket
+struct Syslog {
+    socket: SyslogSocket,
+    facility: u8,
+    host: String,
+}
+
+impl Syslog {
+    fn connect(config: &SyslogConfig) -> io::Result<Self> {
+        let socket = if let Some(addr) = config.address.strip_prefix("udp://") {
+            let socket = UdpSocket::bind("0.0.0.0:0")?;
+            socket.connect(addr)?;
+            SyslogSocket::Udp(socket)
+        } else if let Some(path) = config.address.strip_prefix("unix:") {
+            let socket = UnixDatagram::unbound()?;
+            socket.connect(path)?;
+            SyslogSocket::Unix(socket)
+        } else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("illegal syslog address: {}", config.address),
+            ));
+        };
+        let host = fs::read_to_string("/proc/sys/kernel/hostname")
+            .map(|x| x.trim().to_owned())
+            .unwrap_or_else(|_| "-".to_owned());
+        Ok(Syslog {
+            socket,
+            facility: config.facility,
+            host,
+        })
+    }
+
+    fn send(&self, record: &Record, ts: &str) -> io::Result<()> {
+        let pri = self.facility * 8 + severity(record.level());
+        let msg = syslog_message(pri, ts, &self.host, record.target(), &record.args().to_string());
+        match self.socket {
+            SyslogSocket::Udp(ref socket) => socket.send(msg.as_bytes()),
+            SyslogSocket::Unix(ref socket) => socket.send(msg.as_bytes()),
+        }
+        .map(|_| ())
+    }
+}
+
+/// Append journal native protocol field
+fn journal_field(buf: &mut Vec<u8>, key: &str, value: &str) {
+    buf.extend_from_slice(key.as_bytes());
+    if value.contains('\n') {
+        // binary safe encoding with explicit length
+        buf.push(b'\n');
+        buf.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        buf.push(b'=');
+    }
+    buf.extend_from_slice(value.as_bytes());
+    buf.push(b'\n');
+}
+
+/// systemd-journald sink with structured fields
+struct Journald(UnixDatagram);
+
+impl Journald {
+    const SOCKET: &'static str = "/run/systemd/journal/socket";
+
+    fn connect() -> io::Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(Self::SOCKET)?;
+        Ok(Journald(socket))
+    }
+
+    fn send(&self, record: &Record) -> io::Result<()> {
+        let mut buf = Vec::new();
+        journal_field(&mut buf, "MESSAGE", &record.args().to_string());
+        journal_field(&mut buf, "PRIORITY", &severity(record.level()).to_string());
+        journal_field(&mut buf, "SYSLOG_IDENTIFIER", SERVER_NAME);
+        journal_field(&mut buf, "TARGET", record.target());
+        if let Some(module) = record.module_path() {
+            journal_field(&mut buf, "CODE_MODULE", module);
+        }
+        if let Some(file) = record.file() {
+            journal_field(&mut buf, "CODE_FILE", file);
+        }
+        if let Some(line) = record.line() {
+            journal_field(&mut buf, "CODE_LINE", &line.to_string());
+        }
+        self.0.send(&buf).map(|_| ())
+    }
+}
+
+/// Log output
+enum Sink {
+    File(Mutex<RotatingFile>),
+    Syslog(Syslog),
+    Journald(Journald),
+}
+
+/// Logger writing records to stdout and configured sinks
+struct Logger {
+    level: LevelFilter,
+    stdout: bool,
+    sinks: Vec<Sink>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        // skip noisy http internals unless debug, same as rocket logger
+        let from = |path| record.module_path().is_some_and(|m| m.starts_with(path));
+        if self.level < LevelFilter::Trace && (from("hyper") || from("rustls") || from("h2")) {
+            return;
+        }
+        // rocket uses targets with suffix "_" for indented records
+        let indent = if record.target().ends_with('_') { "   >> " } else { "" };
+        if self.stdout {
+            let prefix = match record.level() {
+                Level::Error if indent.is_empty() => "Error: ",
+                Level::Warn if indent.is_empty() => "Warning: ",
+                _ => "",
+            };
+            println!("{}{}{}", indent, prefix, record.args());
+        }
+
+        let now = OffsetDateTime::now_utc();
+        let ts = timestamp(now);
+        for sink in &self.sinks {
+            let res = match sink {
+                Sink::File(file) => {
+                    let line = format!("{} {:<5} {}{}", ts, record.level(), indent, record.args());
+                    match file.lock() {
+                        Ok(mut file) => file.write_line(now, &line),
+                        Err(_) => Ok(()),
+                    }
+                }
+                Sink::Syslog(syslog) => syslog.send(record, &ts),
+                Sink::Journald(journald) => journald.send(record),
+            };
+            res.unwrap_or_else(|err| eprintln!("error write log: {}", err));
+        }
+    }
+
+    fn flush(&self) {
+        for sink in &self.sinks {
+            if let Sink::File(file) = sink {
+                if let Ok(mut file) = file.lock() {
+                    let _ = file.file.flush();
+                }
+            }
+        }
+    }
+}
+
+/// Install logger if any sink is configured, rocket logs to stdout otherwise
+pub fn init(config: &LogConfig, level: LevelFilter) -> io::Result<()> {
+    let mut sinks = Vec::new();
+    if let Some(ref path) = config.file {
+        sinks.push(Sink::File(Mutex::new(RotatingFile::open(path, config)?)));
+    }
+    if let Some(ref syslog) = config.syslog {
+        sinks.push(Sink::Syslog(Syslog::connect(syslog)?));
+    }
+    if config.journald {
+        sinks.push(Sink::Journald(Journald::connect()?));
+    }
+    if sinks.is_empty() {
+        return Ok(());
+    }
+    let logger = Logger {
+        level,
+        stdout: config.stdout,
+        sinks,
+    };
+    log::set_boxed_logger(Box::new(logger)).map_err(io::Error::other)?;
+    log::set_max_level(level);
+    Ok(())
+}
+
+/// Span timings writer, flushed when the guard is dropped
+#[cfg(feature = "flame")]
+pub type FlameGuard = tracing_flame::FlushGuard<io::BufWriter<File>>;
+
+/// Record span timings in folded stacks format if configured
+#[cfg(feature = "flame")]
+pub fn flame(config: &LogConfig) -> io::Result<Option<FlameGuard>> {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    let Some(ref path) = config.flame else {
+        return Ok(None);
+    };
+    let (layer, guard) = tracing_flame::FlameLayer::with_file(path).map_err(io::Error::other)?;
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::set_global_default(subscriber).map_err(io::Error::other)?;
+    Ok(Some(guard))
+}
+
+/// Access log fairing, writes a line per response in common log format
+#[derive(Clone)]
+pub struct AccessLog(Option<Arc<Mutex<RotatingFile>>>);
+
+impl AccessLog {
+    /// Open access log file if configured, disabled otherwise
+    pub fn new(config: &LogConfig) -> io::Result<Self> {
+        let file = match config.access_file {
+            Some(ref path) => Some(Arc::new(Mutex::new(RotatingFile::open(path, config)?))),
+            None => None,
+        };
+        Ok(AccessLog(file))
+    }
+}
+
+/// Query params carrying session secrets, `access_token` of ion endpoints and share links
+pub const SECRET_PARAMS: [&str; 2] = ["access_token", "share"];
+
+/// Request uri with secret query values replaced by their short hashes
+fn redacted(uri: &Origin<'_>) -> String {
+    let query = match uri.query() {
+        Some(query) => query.as_str(),
+        None => return uri.path().to_string(),
+    };
+    let params: Vec<String> = query
+        .split('&')
+        .map(|param| match param.split_once('=') {
+            Some((name, value)) if SECRET_PARAMS.contains(&name) => {
+                format!("{}=<{}>", name, secret_tag(value))
+            }
+            _ => param.to_owned(),
+        })
+        .collect();
+    format!("{}?{}", uri.path(), params.join("&"))
+}
+
+#[rocket::async_trait]
+impl Fairing for AccessLog {
+    fn info(&self) -> Info {
+        Info {
+            name: "Access log",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let file = match self.0 {
+            Some(ref file) => file,
+            None => return,
+        };
+        let now = OffsetDateTime::now_utc();
+        let ip = Forwarded::of(req)
+            .ip
+            .map(|x| x.to_string())
+            .unwrap_or_else(|| "-".to_owned());
+        let bytes = res
+            .body()
+            .preset_size()
+            .map(|x| x.to_string())
+            .unwrap_or_else(|| "-".to_owned());
+        let line = format!(
+            "{} - - [{}] \"{} {}\" {} {}",
+            ip,
+            timestamp(now),
+            req.method(),
+            redacted(req.uri()),
+            res.status().code,
+            bytes
+        );
+        if let Ok(mut file) = file.lock() {
+            file.write_line(now, &line)
+                .unwrap_or_else(|err| error!("error write access log: {}", err));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn redacted_secrets() {
+        let uri = Origin::parse("/3d/ion/tver/pano?access_token=secret&v=1&share=x.y.z").unwrap();
+        let line = redacted(&uri);
+        assert!(line.starts_with("/3d/ion/tver/pano?access_token=<"));
+        assert!(line.contains("&v=1&share=<"));
+        assert!(!line.contains("secret") && !line.contains("x.y.z"));
+        let uri = Origin::parse("/3d/models/tver/pano/tileset.json").unwrap();
+        assert_eq!(redacted(&uri), "/3d/models/tver/pano/tileset.json");
+    }
+
+    #[test]
+    fn rotation() {
+        let dir = std::env::temp_dir().join(format!("rtiles-log-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("rtiles.log");
+        let config = LogConfig {
+            keep: 2,
+            ..Default::default()
+        };
+        let mut file = RotatingFile::open(&path, &config).unwrap();
+        // rotate after every line
+        file.max_size = 10;
+
+        let now = OffsetDateTime::now_utc();
+        for line in ["first line", "second line", "third line", "fourth line"] {
+            file.write_line(now, line).unwrap();
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fourth line\n");
+        assert_eq!(fs::read_to_string(rotated(&path, 1)).unwrap(), "third line\n");
+        assert_eq!(fs::read_to_string(rotated(&path, 2)).unwrap(), "second line\n");
+        assert!(!rotated(&path, 3).exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn syslog_format() {
+        let msg = syslog_message(30, "2024-05-07T23:59:00Z", "host", "rtiles::cache", "hello");
+        assert_eq!(
+            msg,
+            format!(
+                "<30>1 2024-05-07T23:59:00Z host rtiles {} - [origin software=\"rtiles\" swVersion=\"{}\"][rtiles@32473 target=\"rtiles::cache\"] hello",
+                std::process::id(),
+                SERVER_VERSION
+            )
+        );
+        assert_eq!(3 * 8 + severity(Level::Info), 30);
+    }
+
+    #[test]
+    fn journal_fields() {
+        let mut buf = Vec::new();
+        journal_field(&mut buf, "MESSAGE", "hello");
+        journal_field(&mut buf, "MESSAGE", "a\nb");
+        let mut expected = b"MESSAGE=hello\nMESSAGE\n".to_vec();
+        expected.extend_from_slice(&3u64.to_le_bytes());
+        expected.extend_from_slice(b"a\nb\n");
+        assert_eq!(buf, expected);
+    }
+
+    #[test]
+    fn time_format() {
+        // 2024-05-07 23:59 UTC
+        let t = OffsetDateTime::from_unix_timestamp(1715126340).unwrap();
+        assert_eq!(timestamp(t), "2024-05-07T23:59:00Z");
+    }
+}
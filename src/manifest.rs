@@ -0,0 +1,240 @@
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::serde::Serialize;
+use rocket::State;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use crate::admin::Admin;
+use crate::digest::sha256;
+use crate::registry::ModelRegistry;
+use crate::token::now;
+use crate::Config;
+
+/// Per-model checksums file in `sha256sum` format
+pub const MANIFEST: &str = "manifest.sha256";
+
+/// Parse `<hex digest>  <path>` lines, binary mode `*` marks are allowed
+pub fn parse(text: &str) -> Vec<(String, String)> {
+    text.lines()
+        .filter_map(|line| {
+            let (digest, path) = line.trim_end().split_once(' ')?;
+            let path = path.trim_start_matches([' ', '*']);
+            let valid = digest.len() == 64 && digest.bytes().all(|x| x.is_ascii_hexdigit());
+            (valid && !path.is_empty()).then(|| (digest.to_lowercase(), path.to_owned()))
+        })
+        .collect()
+}
+
+/// Relative path inside the model directory, no root, prefix or `..` components
+fn is_contained(path: &str) -> bool {
+    Path::new(path)
+        .components()
+        .all(|x| matches!(x, Component::Normal(_) | Component::CurDir))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|x| format!("{:02x}", x)).collect()
+}
+
+/// File failed verification
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Mismatch {
+    pub model: String, // `object/model`
+    pub path: String,
+    pub error: String, // `checksum mismatch` or io error
+}
+
+/// Verify model files listed in the manifest, none if there is no manifest
+async fn verify_model(dir: &Path, model: &str) -> io::Result<Option<(u64, Vec<Mismatch>)>> {
+    let text = match tokio::fs::read_to_string(dir.join(MANIFEST)).await {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    let entries = parse(&text);
+    let mut mismatches = Vec::new();
+    for (digest, path) in &entries {
+        // manifest paths must stay inside the model directory
+        let error = if !is_contained(path) {
+            Some("illegal path".to_owned())
+        } else {
+            match sha256(&dir.join(path)).await {
+                Ok(x) if &to_hex(&x) == digest => None,
+                Ok(_) => Some("checksum mismatch".to_owned()),
+                Err(err) => Some(err.to_string()),
+            }
+        };
+        if let Some(error) = error {
+            error!(
+                "manifest verification failed for {}/{}: {}",
+                model, path, error
+            );
+            mismatches.push(Mismatch {
+                model: model.to_owned(),
+                path: path.clone(),
+                error,
+            });
+        }
+    }
+    Ok(Some((entries.len() as u64, mismatches)))
+}
+
+/// Result of the last manifests verification
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ManifestReport {
+    pub running: bool,
+    pub finished: Option<i64>, // unix time
+    pub models: u64,           // models with manifest
+    pub files: u64,
+    pub mismatches: Vec<Mismatch>,
+}
+
+/// Counts of the last verification, without storage paths
+#[derive(Debug, Clone, Serialize)]
+pub struct ManifestSummary {
+    pub running: bool,
+    pub finished: Option<i64>, // unix time
+    pub models: u64,
+    pub files: u64,
+    pub mismatches: u64,
+}
+
+impl From<&ManifestReport> for ManifestSummary {
+    fn from(report: &ManifestReport) -> Self {
+        ManifestSummary {
+            running: report.running,
+            finished: report.finished,
+            models: report.models,
+            files: report.files,
+            mismatches: report.mismatches.len() as u64,
+        }
+    }
+}
+
+/// Background verification of model manifests
+#[derive(Clone, Default)]
+pub struct ManifestCheck {
+    report: Arc<RwLock<ManifestReport>>,
+}
+
+impl ManifestCheck {
+    pub fn new() -> Self {
+        ManifestCheck::default()
+    }
+
+    pub fn report(&self) -> ManifestReport {
+        self.report.read().unwrap().clone()
+    }
+
+    /// Start verification task, false if already running
+    pub fn start(&self, root: PathBuf, registry: ModelRegistry) -> bool {
+        {
+            let mut report = self.report.write().unwrap();
+            if report.running {
+                return false;
+            }
+            report.running = true;
+        }
+        let check = self.clone();
+        tokio::spawn(async move {
+            let mut res = ManifestReport::default();
+            match registry.models(&root).await {
+                Ok(models) => {
+                    for entry in models.iter() {
+                        let dir = root.join(&entry.object).join(&entry.model);
+                        let model = format!("{}/{}", entry.object, entry.model);
+                        match verify_model(&dir, &model).await {
+                            Ok(Some((files, mismatches))) => {
+                                res.models += 1;
+                                res.files += files;
+                                res.mismatches.extend(mismatches);
+                            }
+                            Ok(None) => (),
+                            Err(err) => error!("manifest read error for {}: {}", model, err),
+                        }
+                    }
+                }
+                Err(err) => error!("manifest verification error: {}", err),
+            }
+            info!(
+                "manifests verified: {} models, {} files, {} mismatches",
+                res.models,
+                res.files,
+                res.mismatches.len()
+            );
+            res.finished = Some(now());
+            *check.report.write().unwrap() = res;
+        });
+        true
+    }
+}
+
+/// Start manifests verification in background
+#[post("/admin/manifest/verify")]
+pub fn verify(
+    _admin: Admin,
+    config: &State<Config<'_>>,
+    check: &State<ManifestCheck>,
+    registry: &State<ModelRegistry>,
+) -> Status {
+    if check.start(config.storage.root.clone(), registry.inner().clone()) {
+        Status::Accepted
+    } else {
+        Status::Conflict
+    }
+}
+
+/// Last verification report with mismatching paths
+#[get("/admin/manifest")]
+pub fn report(_admin: Admin, check: &State<ManifestCheck>) -> Json<ManifestReport> {
+    Json(check.report())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn manifest() {
+        let digest = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        let text = format!(
+            "{}  tileset.json\n{} *0/0.b3dm\nbad line\n",
+            digest,
+            digest.to_uppercase()
+        );
+        assert_eq!(
+            parse(&text),
+            vec![
+                (digest.to_owned(), "tileset.json".to_owned()),
+                (digest.to_owned(), "0/0.b3dm".to_owned())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn verify_files() {
+        let dir = std::env::temp_dir().join("rtiles-manifest-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.json"), "abc").unwrap();
+        std::fs::write(dir.join("b.json"), "abd").unwrap();
+        let digest = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        let text = format!(
+            "{d}  a.json\n{d}  b.json\n{d}  c.json\n{d}  /etc/passwd\n{d}  ../a.json\n",
+            d = digest
+        );
+        std::fs::write(dir.join(MANIFEST), text).unwrap();
+
+        let (files, mismatches) = verify_model(&dir, "tver/test").await.unwrap().unwrap();
+        assert_eq!(files, 5);
+        assert_eq!(mismatches.len(), 4);
+        assert_eq!(mismatches[0].path, "b.json");
+        assert_eq!(mismatches[0].error, "checksum mismatch");
+        assert_eq!(mismatches[1].path, "c.json");
+        assert_eq!(mismatches[2].error, "illegal path");
+        assert_eq!(mismatches[3].error, "illegal path");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
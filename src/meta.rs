@@ -3,8 +3,11 @@ use std::{
     fs::Metadata,
     io,
     path::{Path, PathBuf},
-    time::{Duration, SystemTime},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
+use time::OffsetDateTime;
+
+use crate::mount::StorageHealth;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Meta {
@@ -24,6 +27,14 @@ impl From<Metadata> for Meta {
 }
 
 impl Meta {
+    pub fn new(len: u64, modified: Option<SystemTime>, is_dir: bool) -> Self {
+        Meta {
+            len,
+            modified,
+            is_dir,
+        }
+    }
+
     pub async fn from_path(path: &Path) -> io::Result<Meta> {
         Ok(Meta::from(tokio::fs::metadata(path).await?))
     }
@@ -35,6 +46,45 @@ impl Meta {
     pub fn is_dir(&self) -> bool {
         self.is_dir
     }
+
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.modified
+    }
+
+    /// Weak validator built from the length and second-granularity modification time,
+    /// shared by gzipped and decoded representations, never matched by `If-Range`
+    pub fn etag(&self) -> String {
+        let mtime = self
+            .modified
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        format!("W/\"{:x}-{:x}\"", self.len, mtime)
+    }
+
+    /// Modification time in HTTP-date format
+    pub fn last_modified(&self) -> Option<String> {
+        self.modified.map(|t| http_date(t.into()))
+    }
+}
+
+/// Format time as IMF-fixdate, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`
+pub fn http_date(t: OffsetDateTime) -> String {
+    const DAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let t = t.to_offset(time::UtcOffset::UTC);
+    format!(
+        "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+        DAYS[t.weekday().number_days_from_monday() as usize],
+        t.day(),
+        MONTHS[t.month() as usize - 1],
+        t.year(),
+        t.hour(),
+        t.minute(),
+        t.second()
+    )
 }
 
 
@@ -51,8 +101,10 @@ impl Default for MetaCacheConfig {
         }
     }
 }
+#[derive(Clone)]
 pub struct MetaCache {
     cache: Cache<PathBuf, Meta>,
+    health: Option<StorageHealth>, // counts storage IO errors of lookups
 }
 
 impl MetaCache {
@@ -61,20 +113,52 @@ impl MetaCache {
             // Max 100,000 entries
             .max_capacity(100_000)
             .time_to_live(Duration::from_secs(config.ttl))
+            // Allow to purge directories
+            .support_invalidation_closures()
             .build();
-        MetaCache { cache }
+        MetaCache {
+            cache,
+            health: None,
+        }
+    }
+
+    /// Report lookup errors to the storage health
+    pub fn with_health(mut self, health: &StorageHealth) -> Self {
+        self.health = Some(health.clone());
+        self
     }
 
+    #[tracing::instrument(name = "meta", skip_all)]
     pub async fn metadata(&self, path: &PathBuf) -> io::Result<Meta> {
-        match self.cache.get(path) {
+        match self.cache.get(path).await {
             Some(meta) => Ok(meta),
             None => {
-                let meta = Meta::from_path(path).await?;
+                let res = Meta::from_path(path).await;
+                if let Some(ref health) = self.health {
+                    health.record(&res);
+                }
+                let meta = res?;
                 self.cache.insert(path.clone(), meta.clone()).await;
                 Ok(meta)
             }
         }
     }
+
+    /// Invalidate metadata of the path
+    pub async fn invalidate(&self, path: &PathBuf) {
+        self.cache.invalidate(path).await
+    }
+
+    /// Invalidate metadata of paths with the prefix
+    pub fn purge(&self, prefix: &Path) {
+        let prefix = prefix.to_path_buf();
+        let res = self
+            .cache
+            .invalidate_entries_if(move |path, _| path.starts_with(&prefix));
+        if let Err(err) = res {
+            error!("failed to purge metadata cache: {}", err);
+        }
+    }
 }
 
 #[cfg(test)]
@@ -97,4 +181,18 @@ mod test {
 
         assert_eq!(meta2, meta3);
     }
+
+    #[test]
+    fn validators() {
+        let t = UNIX_EPOCH + Duration::from_secs(784111777);
+        assert_eq!(http_date(t.into()), "Sun, 06 Nov 1994 08:49:37 GMT");
+
+        let meta = Meta::new(255, Some(t), false);
+        assert_eq!(meta.etag(), "W/\"ff-2ebc98a1\"");
+        assert_eq!(
+            meta.last_modified().as_deref(),
+            Some("Sun, 06 Nov 1994 08:49:37 GMT")
+        );
+        assert_eq!(Meta::new(1, None, false).last_modified(), None);
+    }
 }
@@ -0,0 +1,382 @@
+use rocket::fairing::AdHoc;
+use rocket::http::ContentType;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::State;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::access::ModelAccess;
+use crate::admin::Admin;
+use crate::cache::FileCache;
+use crate::error::Error;
+use crate::stat::{is_model_route, Stat, Timer};
+
+/// Prometheus metrics configuration
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct MetricsConfig {
+    pub latency_buckets: Vec<f64>, // response time histogram bounds, seconds
+    pub size_buckets: Vec<f64>,    // response size histogram bounds, bytes
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        MetricsConfig {
+            latency_buckets: vec![
+                0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+            ],
+            size_buckets: vec![
+                1024.0, 4096.0, 16384.0, 65536.0, 262144.0, 1048576.0, 4194304.0, 16777216.0,
+            ],
+        }
+    }
+}
+
+/// Histogram with fixed bucket bounds
+pub struct Histogram {
+    bounds: Vec<f64>,
+    counts: Vec<AtomicU64>, // per bucket, the last one is `+Inf`
+    sum: AtomicU64,         // f64 bits
+}
+
+impl Histogram {
+    /// Make histogram, bounds are sorted and deduplicated
+    pub fn new(bounds: &[f64]) -> Self {
+        let mut bounds: Vec<f64> = bounds.iter().copied().filter(|x| x.is_finite()).collect();
+        bounds.sort_by(f64::total_cmp);
+        bounds.dedup();
+        let counts = (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect();
+        Histogram {
+            bounds,
+            counts,
+            sum: AtomicU64::new(0f64.to_bits()),
+        }
+    }
+
+    /// Record observed value
+    pub fn observe(&self, value: f64) {
+        let i = self.bounds.partition_point(|x| *x < value);
+        self.counts[i].fetch_add(1, Ordering::Relaxed);
+        self.sum
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |x| {
+                Some((f64::from_bits(x) + value).to_bits())
+            })
+            .ok();
+    }
+
+    /// Write in the Prometheus text format
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        let mut count = 0;
+        for (i, bucket) in self.counts.iter().enumerate() {
+            count += bucket.load(Ordering::Relaxed);
+            let le = match self.bounds.get(i) {
+                Some(bound) => bound.to_string(),
+                None => "+Inf".to_owned(),
+            };
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, le, count);
+        }
+        let sum = f64::from_bits(self.sum.load(Ordering::Relaxed));
+        let _ = writeln!(out, "{}_sum {}", name, sum);
+        let _ = writeln!(out, "{}_count {}", name, count);
+    }
+}
+
+/// Write counter or gauge in the Prometheus text format
+fn single(out: &mut String, name: &str, kind: &str, help: &str, value: u64) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+/// Tokio runtime gauges and per worker counters,
+/// poll time histograms need `tokio_unstable` and are not exported
+#[cfg(feature = "runtime-metrics")]
+fn runtime(out: &mut String) {
+    let m = tokio::runtime::Handle::current().metrics();
+    for (name, help, value) in [
+        (
+            "rtiles_runtime_workers",
+            "Runtime worker threads",
+            m.num_workers(),
+        ),
+        (
+            "rtiles_runtime_alive_tasks",
+            "Runtime tasks not yet completed",
+            m.num_alive_tasks(),
+        ),
+        (
+            "rtiles_runtime_global_queue_depth",
+            "Tasks waiting in the runtime global queue",
+            m.global_queue_depth(),
+        ),
+    ] {
+        single(out, name, "gauge", help, value as u64);
+    }
+    let workers = 0..m.num_workers();
+    let busy = "rtiles_runtime_worker_busy_seconds_total";
+    let _ = writeln!(out, "# HELP {} Worker busy time", busy);
+    let _ = writeln!(out, "# TYPE {} counter", busy);
+    for n in workers.clone() {
+        let value = m.worker_total_busy_duration(n).as_secs_f64();
+        let _ = writeln!(out, "{}{{worker=\"{}\"}} {}", busy, n, value);
+    }
+    let parks = "rtiles_runtime_worker_parks_total";
+    let _ = writeln!(out, "# HELP {} Worker parks for no work", parks);
+    let _ = writeln!(out, "# TYPE {} counter", parks);
+    for n in workers {
+        let _ = writeln!(
+            out,
+            "{}{{worker=\"{}\"}} {}",
+            parks,
+            n,
+            m.worker_park_count(n)
+        );
+    }
+}
+
+/// Handler errors by variant
+#[derive(Default)]
+struct ErrorCounters {
+    bad_request: AtomicU64,
+    not_found: AtomicU64,
+    forbidden: AtomicU64,
+    unavailable: AtomicU64,
+    internal: AtomicU64,
+    timeout: AtomicU64,
+}
+
+impl ErrorCounters {
+    fn counter(&self, err: &Error) -> &AtomicU64 {
+        match err {
+            Error::BadRequest(_) => &self.bad_request,
+            Error::NotFound(_) => &self.not_found,
+            Error::Forbidden(_) => &self.forbidden,
+            Error::Unavailable(_) => &self.unavailable,
+            Error::Internal(_) => &self.internal,
+            Error::Timeout(_) => &self.timeout,
+        }
+    }
+
+    fn render(&self, out: &mut String) {
+        let name = "rtiles_errors_total";
+        let _ = writeln!(out, "# HELP {} Request handler errors", name);
+        let _ = writeln!(out, "# TYPE {} counter", name);
+        for (kind, counter) in [
+            ("bad_request", &self.bad_request),
+            ("not_found", &self.not_found),
+            ("forbidden", &self.forbidden),
+            ("unavailable", &self.unavailable),
+            ("internal", &self.internal),
+            ("timeout", &self.timeout),
+        ] {
+            let value = counter.load(Ordering::Relaxed);
+            let _ = writeln!(out, "{}{{kind=\"{}\"}} {}", name, kind, value);
+        }
+    }
+}
+
+/// Model response histograms and error counters
+#[derive(Clone)]
+pub struct ServerMetrics {
+    latency: Arc<Histogram>,
+    size: Arc<Histogram>,
+    errors: Arc<ErrorCounters>,
+}
+
+impl ServerMetrics {
+    pub fn new(config: &MetricsConfig) -> Self {
+        ServerMetrics {
+            latency: Arc::new(Histogram::new(&config.latency_buckets)),
+            size: Arc::new(Histogram::new(&config.size_buckets)),
+            errors: Arc::default(),
+        }
+    }
+
+    /// Count handler error
+    pub fn error(&self, err: &Error) {
+        self.errors.counter(err).fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Metrics in the Prometheus text format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        self.latency
+            .render(&mut out, "rtiles_response_seconds", "Model response time");
+        self.size.render(
+            &mut out,
+            "rtiles_response_bytes",
+            "Model response body size",
+        );
+        self.errors.render(&mut out);
+        out
+    }
+}
+
+/// Observe model responses
+pub fn fairing(metrics: ServerMetrics) -> AdHoc {
+    AdHoc::on_response("prometheus metrics", move |req, res| {
+        let metrics = metrics.clone();
+        Box::pin(async move {
+            if !is_model_route(req) {
+                return;
+            }
+            // timer is set by the handler guard
+            let timer = *req.local_cache(Timer::now);
+            metrics.latency.observe(timer.elapsed().as_secs_f64());
+            if let Some(size) = res.body_mut().size().await {
+                metrics.size.observe(size as f64);
+            }
+        })
+    })
+}
+
+/// Prometheus scrape endpoint
+#[get("/metrics")]
+pub async fn metrics(
+    _admin: Admin,
+    metrics: &State<ServerMetrics>,
+    access: &State<ModelAccess>,
+    stat: &State<Stat>,
+    cache: &State<FileCache>,
+) -> (ContentType, String) {
+    let mut out = metrics.render();
+    let a = access.stats().await;
+    for (name, kind, help, value) in [
+        (
+            "rtiles_access_cache_hits_total",
+            "counter",
+            "Access decisions from cache",
+            a.hits,
+        ),
+        (
+            "rtiles_access_cache_misses_total",
+            "counter",
+            "Access decisions not cached",
+            a.misses,
+        ),
+        (
+            "rtiles_access_cache_inserts_total",
+            "counter",
+            "Access decisions cached",
+            a.inserts,
+        ),
+        (
+            "rtiles_access_cache_evictions_total",
+            "counter",
+            "Access decisions expired or evicted",
+            a.evictions,
+        ),
+        (
+            "rtiles_access_cache_entries",
+            "gauge",
+            "Cached access decisions",
+            a.entries,
+        ),
+        (
+            "rtiles_auth_requests_total",
+            "counter",
+            "Auth server requests",
+            a.auth_requests,
+        ),
+        (
+            "rtiles_auth_errors_total",
+            "counter",
+            "Auth server transport errors",
+            a.auth_errors,
+        ),
+        (
+            "rtiles_auth_time_microseconds_total",
+            "counter",
+            "Auth server response time",
+            a.auth_time_us,
+        ),
+        (
+            "rtiles_auth_soft_grants_total",
+            "counter",
+            "Access granted on auth server errors",
+            a.soft_grants,
+        ),
+        (
+            "rtiles_auth_over_budget_total",
+            "counter",
+            "Access answered by budget policy before the auth server",
+            a.over_budget,
+        ),
+        (
+            "rtiles_stat_dropped_total",
+            "counter",
+            "Stat records dropped on the queue overflow",
+            stat.dropped(),
+        ),
+        (
+            "rtiles_stat_queue_depth",
+            "gauge",
+            "Stat records waiting for the stat task",
+            stat.queue_depth() as u64,
+        ),
+        (
+            "rtiles_cache_load_queue_depth",
+            "gauge",
+            "Files waiting for the cache loader",
+            cache.queue_depth() as u64,
+        ),
+        (
+            "rtiles_cache_missing_served_total",
+            "counter",
+            "Cached files served while missing on disk",
+            cache.missing_served(),
+        ),
+    ] {
+        single(&mut out, name, kind, help, value);
+    }
+    #[cfg(feature = "runtime-metrics")]
+    runtime(&mut out);
+    let ct = ContentType::new("text", "plain").with_params(("version", "0.0.4"));
+    (ct, out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn error_counters() {
+        let metrics = ServerMetrics::new(&MetricsConfig::default());
+        metrics.error(&Error::NotFound("a".into()));
+        metrics.error(&Error::NotFound("b".into()));
+        metrics.error(&Error::Internal("c".into()));
+        let out = metrics.render();
+        assert!(out.contains("rtiles_errors_total{kind=\"not_found\"} 2\n"));
+        assert!(out.contains("rtiles_errors_total{kind=\"forbidden\"} 0\n"));
+        assert!(out.contains("rtiles_errors_total{kind=\"internal\"} 1\n"));
+    }
+
+    #[cfg(feature = "runtime-metrics")]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn runtime_metrics() {
+        let mut out = String::new();
+        runtime(&mut out);
+        assert!(out.contains("rtiles_runtime_workers 2\n"));
+        assert!(out.contains("rtiles_runtime_worker_parks_total{worker=\"1\"}"));
+    }
+
+    #[test]
+    fn histogram() {
+        let h = Histogram::new(&[10.0, 1.0, 5.0, 5.0]);
+        for x in [0.5, 1.0, 3.0, 7.0, 100.0] {
+            h.observe(x);
+        }
+        let mut out = String::new();
+        h.render(&mut out, "size", "Size");
+        assert_eq!(
+            out,
+            "# HELP size Size\n# TYPE size histogram\n\
+             size_bucket{le=\"1\"} 2\nsize_bucket{le=\"5\"} 3\n\
+             size_bucket{le=\"10\"} 4\nsize_bucket{le=\"+Inf\"} 5\n\
+             size_sum 111.5\nsize_count 5\n"
+        );
+    }
+}
@@ -0,0 +1,109 @@
+use rocket::http::ContentType;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Default content types for 3D Tiles and related formats,
+/// not recognized by [`ContentType::from_extension()`]
+pub fn default_content_types() -> HashMap<String, String> {
+    [
+        ("b3dm", "application/octet-stream"),
+        ("i3dm", "application/octet-stream"),
+        ("pnts", "application/octet-stream"),
+        ("cmpt", "application/octet-stream"),
+        ("subtree", "application/octet-stream"),
+        ("glb", "model/gltf-binary"),
+        ("gltf", "model/gltf+json"),
+        ("terrain", "application/vnd.quantized-mesh"),
+        ("pbf", "application/x-protobuf"),
+        ("mvt", "application/x-protobuf"),
+    ]
+    .into_iter()
+    .map(|(ext, mime)| (ext.to_owned(), mime.to_owned()))
+    .collect()
+}
+
+/// Vector tile extensions, payloads are often stored gzipped
+const VECTOR_TILES: [&str; 2] = ["pbf", "mvt"];
+
+/// Is the path a Mapbox vector tile?
+pub fn is_vector_tile(path: &Path) -> bool {
+    match path.extension() {
+        Some(ext) => VECTOR_TILES.iter().any(|x| ext.eq_ignore_ascii_case(x)),
+        None => false,
+    }
+}
+
+/// Extension to content type mapping for served files
+#[derive(Debug, Clone)]
+pub struct ContentTypes(HashMap<String, ContentType>);
+
+impl ContentTypes {
+    /// Build mapping from config table, skip unparsable types
+    pub fn new(table: &HashMap<String, String>) -> Self {
+        let map = table
+            .iter()
+            .filter_map(|(ext, mime)| match ContentType::parse_flexible(mime) {
+                Some(ct) => Some((ext.to_lowercase(), ct)),
+                None => {
+                    error!("illegal content type '{}' for '.{}', ignored", mime, ext);
+                    None
+                }
+            })
+            .collect();
+        ContentTypes(map)
+    }
+
+    /// Get content type for the path, fallback to octet-stream
+    pub fn get(&self, path: &Path) -> ContentType {
+        let ext = match path.extension() {
+            Some(ext) => ext.to_string_lossy().to_lowercase(),
+            None => return ContentType::Binary,
+        };
+        self.0
+            .get(&ext)
+            .cloned()
+            .or_else(|| ContentType::from_extension(&ext))
+            .unwrap_or(ContentType::Binary)
+    }
+}
+
+impl Default for ContentTypes {
+    fn default() -> Self {
+        ContentTypes::new(&default_content_types())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn content_types() {
+        let mut table = default_content_types();
+        table.insert("b3dm".into(), "application/x-b3dm".into());
+        let types = ContentTypes::new(&table);
+
+        assert_eq!(
+            types.get(Path::new("a/tile.glb")),
+            ContentType::new("model", "gltf-binary")
+        );
+        assert_eq!(
+            types.get(Path::new("a/tile.B3DM")),
+            ContentType::new("application", "x-b3dm")
+        );
+        assert_eq!(types.get(Path::new("tileset.json")), ContentType::JSON);
+        assert_eq!(types.get(Path::new("data.unknown")), ContentType::Binary);
+        assert_eq!(types.get(Path::new("noext")), ContentType::Binary);
+        assert_eq!(
+            types.get(Path::new("1/0/0.pbf")),
+            ContentType::new("application", "x-protobuf")
+        );
+    }
+
+    #[test]
+    fn vector_tile() {
+        assert!(is_vector_tile(Path::new("1/0/0.pbf")));
+        assert!(is_vector_tile(Path::new("1/0/0.MVT")));
+        assert!(!is_vector_tile(Path::new("1/0/0.png")));
+    }
+}
@@ -0,0 +1,129 @@
+use std::io;
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Mock server decision policy
+#[derive(Debug, Clone, PartialEq)]
+pub enum MockPolicy {
+    Allow,
+    Deny,
+    Pattern(Vec<String>), // granted paths like `tver/*` or `tver/panorama`
+}
+
+impl MockPolicy {
+    /// Parse `allow`, `deny` or comma separated path patterns
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "allow" => Ok(MockPolicy::Allow),
+            "deny" => Ok(MockPolicy::Deny),
+            "" => Err("empty dev auth policy".to_owned()),
+            _ => Ok(MockPolicy::Pattern(
+                s.split(',').map(|x| x.trim().to_owned()).collect(),
+            )),
+        }
+    }
+
+    /// Is the model path `object/model` granted?
+    pub fn granted(&self, path: &str) -> bool {
+        match self {
+            MockPolicy::Allow => true,
+            MockPolicy::Deny => false,
+            MockPolicy::Pattern(patterns) => patterns.iter().any(|p| match p.strip_suffix('*') {
+                Some(prefix) => path.starts_with(prefix),
+                None => path == p,
+            }),
+        }
+    }
+}
+
+/// Start embedded access server answering `200 OK` to granted
+/// `/<object>/<model>` paths and `403` otherwise, returns its address
+pub async fn spawn(policy: MockPolicy, addr: SocketAddr) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr).await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    let policy = policy.clone();
+                    tokio::spawn(async move {
+                        if let Err(err) = answer(stream, &policy).await {
+                            debug!("dev auth connection error: {}", err);
+                        }
+                    });
+                }
+                Err(err) => error!("dev auth accept error: {}", err),
+            }
+        }
+    });
+    Ok(addr)
+}
+
+/// Answer single request and close the connection
+async fn answer(mut stream: TcpStream, policy: &MockPolicy) -> io::Result<()> {
+    let mut buf = vec![0; 8192];
+    let mut len = 0;
+    // read up to the end of request headers
+    while !buf[..len].windows(4).any(|x| x == b"\r\n\r\n") {
+        if len == buf.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "headers too long",
+            ));
+        }
+        match stream.read(&mut buf[len..]).await? {
+            0 => return Ok(()),
+            n => len += n,
+        }
+    }
+    let head = String::from_utf8_lossy(&buf[..len]);
+    let target = head.split(' ').nth(1).unwrap_or_default();
+    let path = target
+        .split('?')
+        .next()
+        .unwrap_or_default()
+        .trim_matches('/');
+    let status = if policy.granted(path) {
+        "200 OK"
+    } else {
+        "403 Forbidden"
+    };
+    debug!("dev auth {}: {}", path, status);
+    let res = format!(
+        "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        status
+    );
+    stream.write_all(res.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn policy() {
+        assert_eq!(MockPolicy::parse("allow"), Ok(MockPolicy::Allow));
+        assert!(MockPolicy::parse("").is_err());
+        let policy = MockPolicy::parse("tver/*, lake/center").unwrap();
+        assert!(policy.granted("tver/panorama"));
+        assert!(policy.granted("lake/center"));
+        assert!(!policy.granted("lake/north"));
+        assert!(!policy.granted("tverskaya/a"));
+    }
+
+    #[tokio::test]
+    async fn responses() {
+        let policy = MockPolicy::parse("tver/*").unwrap();
+        let addr = spawn(policy, "127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let client = reqwest::Client::new();
+        let status = |path: &'static str| {
+            let req = client.get(format!("http://{}{}", addr, path));
+            async move { req.send().await.unwrap().status().as_u16() }
+        };
+        assert_eq!(status("/tver/panorama").await, 200);
+        assert_eq!(status("/tver/panorama?scope=stat").await, 200);
+        assert_eq!(status("/lake/center").await, 403);
+    }
+}
@@ -1,10 +1,12 @@
-use std::convert::Infallible;
-
 use rocket::{
+    http::Status,
     request::{FromRequest, Outcome},
     Request,
 };
 
+/// Maximum object or model name length, bytes
+const MAX_NAME_LEN: usize = 128;
+
 /// Model identity
 #[derive(Default, Debug, Hash, PartialEq, Eq, Clone)]
 pub struct Model {
@@ -21,16 +23,72 @@ impl Model {
     }
 }
 
+/// Check object or model name: non-empty, limited length,
+/// letters, digits, `-`, `_` and `.` not in the first position
+pub fn validate_name(name: &str) -> Result<(), &'static str> {
+    if name.is_empty() {
+        return Err("empty name");
+    }
+    if name.len() > MAX_NAME_LEN {
+        return Err("name too long");
+    }
+    if name.starts_with('.') {
+        return Err("name starts with a dot");
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    {
+        return Err("illegal character in name");
+    }
+    Ok(())
+}
+
+/// Decode and validate path segment
+fn segment(req: &Request<'_>, n: usize) -> Result<Option<String>, &'static str> {
+    match req.param::<String>(n) {
+        Some(Ok(name)) => validate_name(&name).map(|_| Some(name)),
+        Some(Err(_)) => Err("illegal name encoding"),
+        None => Ok(None),
+    }
+}
+
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for Model {
-    type Error = Infallible;
+    type Error = &'static str;
 
     async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
-        let model = Model {
-            object: req.param(1).map(|x| x.unwrap_or_default()),
-            name: req.param(2).map(|x| x.unwrap_or_default()),
-        };
+        let model = segment(req, 1).and_then(|object| {
+            Ok(Model {
+                object,
+                name: segment(req, 2)?,
+            })
+        });
+        match model {
+            Ok(model) => Outcome::Success(model),
+            Err(reason) => {
+                debug!("malformed model path {}: {}", req.uri(), reason);
+                Outcome::Failure((Status::BadRequest, reason))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
 
-        Outcome::Success(model)
+    #[test]
+    fn names() {
+        assert!(validate_name("tver").is_ok());
+        assert!(validate_name("pano_2023-v1.2").is_ok());
+        assert!(validate_name("тверь").is_ok());
+        assert!(validate_name("").is_err());
+        assert!(validate_name(" ").is_err());
+        assert!(validate_name("..").is_err());
+        assert!(validate_name(".hidden").is_err());
+        assert!(validate_name("a b").is_err());
+        assert!(validate_name("a/b").is_err());
+        assert!(validate_name(&"x".repeat(MAX_NAME_LEN + 1)).is_err());
     }
 }
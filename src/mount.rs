@@ -0,0 +1,140 @@
+use std::io::{self, ErrorKind};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::events::{Event, Events};
+
+/// Storage root health, degraded after repeated IO errors
+/// like an NFS outage and recovered by the root probe
+#[derive(Clone)]
+pub struct StorageHealth {
+    errors: Arc<AtomicU32>, // consecutive storage IO errors
+    degraded: Arc<AtomicBool>,
+    threshold: u32, // 0 disables
+    events: Events,
+}
+
+/// Is the error caused by the storage itself, not by the requested path?
+fn is_storage_error(err: &io::Error) -> bool {
+    !matches!(
+        err.kind(),
+        ErrorKind::NotFound
+            | ErrorKind::NotADirectory
+            | ErrorKind::IsADirectory
+            | ErrorKind::PermissionDenied
+            | ErrorKind::InvalidInput
+            | ErrorKind::InvalidFilename
+    )
+}
+
+/// Can the storage root be listed?
+async fn probe(root: &PathBuf) -> io::Result<()> {
+    let mut entries = tokio::fs::read_dir(root).await?;
+    entries.next_entry().await?;
+    Ok(())
+}
+
+impl StorageHealth {
+    pub fn new(threshold: u32, events: Events) -> Self {
+        StorageHealth {
+            errors: Arc::default(),
+            degraded: Arc::default(),
+            threshold,
+            events,
+        }
+    }
+
+    /// Count the storage call result, degrade on the threshold of errors in a row
+    pub fn record<T>(&self, res: &io::Result<T>) {
+        match res {
+            Err(err) if self.threshold > 0 && is_storage_error(err) => {
+                let errors = self.errors.fetch_add(1, Ordering::Relaxed) + 1;
+                if errors >= self.threshold && !self.degraded.swap(true, Ordering::Relaxed) {
+                    error!(
+                        "storage degraded after {} IO errors, serving cache only: {}",
+                        errors, err
+                    );
+                    self.events.send(Event::StorageDegraded {
+                        error: err.to_string(),
+                    });
+                }
+            }
+            Err(_) => (),
+            Ok(_) => self.errors.store(0, Ordering::Relaxed),
+        }
+    }
+
+    /// Is the storage unavailable, misses answered with 503?
+    pub fn is_degraded(&self) -> bool {
+        self.degraded.load(Ordering::Relaxed)
+    }
+
+    /// Check degraded storage root with the interval, recover when it is back
+    pub fn start(&self, root: PathBuf, interval: Duration) {
+        if self.threshold == 0 {
+            return;
+        }
+        let health = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                if health.is_degraded() {
+                    health.check(&root, interval).await;
+                }
+            }
+        });
+    }
+
+    // probe the root, hung mounts count as failed
+    async fn check(&self, root: &PathBuf, timeout: Duration) {
+        match tokio::time::timeout(timeout, probe(root)).await {
+            Ok(Ok(())) => {
+                self.errors.store(0, Ordering::Relaxed);
+                self.degraded.store(false, Ordering::Relaxed);
+                info!("storage {:?} recovered", root);
+                self.events.send(Event::StorageRecovered);
+            }
+            Ok(Err(err)) => debug!("storage {:?} still unavailable: {}", root, err),
+            Err(_) => debug!("storage {:?} probe timed out", root),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn degrade_and_recover() {
+        let events = Events::default();
+        let mut rx = events.subscribe();
+        let health = StorageHealth::new(3, events);
+        let eio = || Err::<(), _>(io::Error::from_raw_os_error(5));
+
+        // missing files and interrupted series do not degrade
+        health.record(&Err::<(), _>(io::Error::from(ErrorKind::NotFound)));
+        health.record(&eio());
+        health.record(&eio());
+        health.record(&Ok(()));
+        health.record(&eio());
+        health.record(&eio());
+        assert!(!health.is_degraded());
+        health.record(&eio());
+        assert!(health.is_degraded());
+        assert_eq!(rx.recv().await.unwrap().kind(), "storage_degraded");
+
+        // still unavailable root keeps the state
+        let root = std::env::temp_dir().join("rtiles-mount-test");
+        let _ = std::fs::remove_dir_all(&root);
+        health.check(&root, Duration::from_secs(1)).await;
+        assert!(health.is_degraded());
+
+        std::fs::create_dir_all(&root).unwrap();
+        health.check(&root, Duration::from_secs(1)).await;
+        assert!(!health.is_degraded());
+        assert_eq!(rx.recv().await.unwrap(), Event::StorageRecovered);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
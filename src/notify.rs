@@ -0,0 +1,78 @@
+use rocket::fairing::AdHoc;
+use std::env;
+use std::io;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+
+/// Send state to systemd service manager, returns false if not supervised
+pub fn sd_notify(state: &str) -> io::Result<bool> {
+    let path = match env::var_os("NOTIFY_SOCKET") {
+        Some(path) => path,
+        None => return Ok(false),
+    };
+    let path = path.to_string_lossy();
+    let addr = match path.strip_prefix('@') {
+        // abstract namespace socket
+        Some(name) => abstract_addr(name)?,
+        None => SocketAddr::from_pathname(path.as_ref())?,
+    };
+    let socket = UnixDatagram::unbound()?;
+    socket.send_to_addr(state.as_bytes(), &addr)?;
+    Ok(true)
+}
+
+#[cfg(target_os = "linux")]
+fn abstract_addr(name: &str) -> io::Result<SocketAddr> {
+    use std::os::linux::net::SocketAddrExt;
+    SocketAddr::from_abstract_name(name)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn abstract_addr(_name: &str) -> io::Result<SocketAddr> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "abstract notify socket is not supported",
+    ))
+}
+
+fn notify(state: &str) {
+    match sd_notify(state) {
+        Ok(true) => debug!("systemd notified: {}", state),
+        Ok(false) => (),
+        Err(err) => error!("systemd notify error: {}", err),
+    }
+}
+
+/// Notify systemd when the server is ready and when it is stopping
+pub fn fairing() -> AdHoc {
+    AdHoc::on_liftoff("systemd notify", |rocket| {
+        Box::pin(async move {
+            // state is built and the server is listening
+            notify("READY=1");
+            let shutdown = rocket.shutdown();
+            rocket::tokio::spawn(async move {
+                shutdown.await;
+                notify("STOPPING=1");
+            });
+        })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn notify_socket() {
+        let path = env::temp_dir().join(format!("rtiles-notify-{}", std::process::id()));
+        let socket = UnixDatagram::bind(&path).unwrap();
+        env::set_var("NOTIFY_SOCKET", &path);
+        assert!(sd_notify("READY=1").unwrap());
+        env::remove_var("NOTIFY_SOCKET");
+        assert!(!sd_notify("READY=1").unwrap());
+
+        let mut buf = [0u8; 16];
+        let n = socket.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"READY=1");
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,40 @@
+use rocket::serde::json::{self, Json, Value};
+use rocket::State;
+
+use crate::config::SERVER_VERSION;
+use crate::Config;
+
+const SPEC: &str = include_str!("../static/openapi.json");
+
+/// Build OpenAPI document for the configured server
+pub fn spec(config: &Config<'_>) -> Value {
+    let mut spec: Value = json::from_str(SPEC).expect("valid openapi.json");
+    spec["info"]["version"] = Value::from(SERVER_VERSION);
+    spec["servers"] = json::json!([{ "url": config.base_path.path().as_str() }]);
+    spec["components"]["securitySchemes"]["session"]["name"] =
+        Value::from(config.access.cookie_name.as_ref());
+    spec
+}
+
+#[get("/openapi.json")]
+pub fn openapi(config: &State<Config<'_>>) -> Json<Value> {
+    Json(spec(config))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn document() {
+        let spec = spec(&Config::default());
+        assert_eq!(spec["openapi"], "3.0.3");
+        assert_eq!(spec["info"]["version"], SERVER_VERSION);
+        assert_eq!(spec["servers"][0]["url"], "/3d");
+        assert_eq!(
+            spec["components"]["securitySchemes"]["session"]["name"],
+            "PHPSESSID"
+        );
+        assert!(spec["paths"]["/models/{object}/{model}/{path}"]["get"].is_object());
+    }
+}
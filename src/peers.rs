@@ -0,0 +1,242 @@
+use reqwest::{Client, StatusCode, Url};
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::serde::{Deserialize, Serialize};
+use rocket::State;
+use sha2::{Digest, Sha256};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::cache::{CachedNamedFile, Content, FileCache, Forward};
+use crate::meta::MetaCache;
+use crate::tenant::Tenant;
+use crate::{Config, Meta};
+
+/// Header with the shared token of cluster peers
+const PEER_TOKEN_HEADER: &str = "X-Peer-Token";
+
+/// Header with the virtual host of the requested file, the global storage if not set
+const PEER_HOST_HEADER: &str = "X-Peer-Host";
+
+/// Distributed cache cluster configuration
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq)]
+pub struct PeersConfig {
+    pub url: Option<String>, // own url as listed in peers, e.g. `http://10.0.0.1:8000/3d`
+    pub peers: Vec<String>,  // base urls of all replicas, disabled if empty
+    pub token: Option<String>, // shared token required by the peer route
+}
+
+impl PeersConfig {
+    /// Check the cluster config, the peer route serves storage without access checks
+    pub fn check(&self) -> Result<(), String> {
+        if !self.peers.is_empty() && self.token.is_none() {
+            return Err("peers.token is required if peers are configured".to_owned());
+        }
+        Ok(())
+    }
+}
+
+/// Weight of the peer for the key, rendezvous hashing
+fn weight(peer: &str, key: &str) -> u64 {
+    let hash = Sha256::new()
+        .chain_update(peer)
+        .chain_update([0])
+        .chain_update(key)
+        .finalize();
+    u64::from_be_bytes(hash[..8].try_into().unwrap())
+}
+
+/// Peer route url with the percent-encoded storage path
+fn peer_url(owner: &str, key: &str) -> Option<Url> {
+    let mut url = Url::parse(&format!("{}/peer", owner.trim_end_matches('/'))).ok()?;
+    url.path_segments_mut().ok()?.extend(key.split('/'));
+    Some(url)
+}
+
+/// Replicas sharing their file caches, each path is owned by one peer
+#[derive(Clone)]
+pub struct Peers {
+    config: PeersConfig,
+    client: Client,
+}
+
+impl Peers {
+    pub fn new(config: &PeersConfig) -> Result<Self, reqwest::Error> {
+        let client = Client::builder()
+            // peer should answer faster than slow storage
+            .timeout(Duration::from_secs(2))
+            .build()?;
+        Ok(Peers {
+            config: config.clone(),
+            client,
+        })
+    }
+
+    /// Remote peer owning the path, none if owned by this replica
+    pub fn owner(&self, key: &str) -> Option<&str> {
+        let own = self.config.url.as_deref()?;
+        let owner = self.config.peers.iter().max_by_key(|x| weight(x, key))?;
+        (owner != own).then_some(owner.as_str())
+    }
+
+    /// Fetch the file of the virtual host from the peer cache
+    async fn fetch(
+        &self,
+        owner: &str,
+        key: &str,
+        host: Option<&str>,
+    ) -> reqwest::Result<Option<bytes::Bytes>> {
+        let url = match peer_url(owner, key) {
+            Some(url) => url,
+            None => {
+                warn!("illegal peer url {}", owner);
+                return Ok(None);
+            }
+        };
+        // get gzipped payloads as stored
+        let mut rq = self.client.get(url).header("Accept-Encoding", "gzip");
+        if let Some(ref token) = self.config.token {
+            rq = rq.header(PEER_TOKEN_HEADER, token);
+        }
+        if let Some(host) = host {
+            rq = rq.header(PEER_HOST_HEADER, host);
+        }
+        let res = rq.send().await?;
+        match res.status() {
+            StatusCode::OK => Ok(Some(res.bytes().await?)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Serve the file from the local cache, the owner peer cache or the storage
+    pub async fn open(
+        &self,
+        tenant: &Tenant,
+        path: &PathBuf,
+        meta: &Meta,
+        cache: &FileCache,
+        detect_gzip: bool,
+    ) -> io::Result<CachedNamedFile> {
+        let owner = match path.strip_prefix(&tenant.root).ok().and_then(Path::to_str) {
+            Some(key) if !cache.contains(path) => self.owner(key).map(|x| (x, key)),
+            _ => None,
+        };
+        if let Some((owner, key)) = owner {
+            match self.fetch(owner, key, tenant.host.as_deref()).await {
+                // check length, the file may be changed in the storage
+                Ok(Some(body)) if body.len() as u64 == meta.len() => {
+                    debug!("serving file from peer {}: {:?}", owner, path);
+                    let cnt =
+                        Content::new(path.clone(), meta.clone(), body).detect_gzip(detect_gzip);
+                    return Ok(CachedNamedFile::Loaded(Box::new(cnt), Forward::Bypass("peer")));
+                }
+                Ok(_) => debug!("peer {} has no valid file: {:?}", owner, path),
+                Err(err) => warn!("peer {} request error: {}", owner, err),
+            }
+        }
+        CachedNamedFile::open_with_detection(path, meta, cache, detect_gzip).await
+    }
+}
+
+/// Cluster peer guard, checks the shared token and resolves the requested tenant
+pub struct Peer(Tenant);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Peer {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let config = req.rocket().state::<Config<'_>>().unwrap();
+        if config.peers.peers.is_empty() {
+            return Outcome::Failure((Status::NotFound, ()));
+        }
+        // token is required by the config check, refuse if missing anyway
+        match config.peers.token {
+            Some(ref token) if req.headers().get_one(PEER_TOKEN_HEADER) == Some(token) => (),
+            _ => return Outcome::Failure((Status::Forbidden, ())),
+        }
+        // the peer host is not the tenant, the requesting replica names it
+        let host = req.headers().get_one(PEER_HOST_HEADER);
+        let tenant = Tenant::resolve(config, host);
+        if host.is_some() && tenant.host.is_none() {
+            return Outcome::Failure((Status::NotFound, ()));
+        }
+        Outcome::Success(Peer(tenant))
+    }
+}
+
+/// Serve storage file to the peer, cached by the owner
+#[get("/peer/<path..>")]
+pub async fn peer_file(
+    peer: Peer,
+    path: PathBuf,
+    cache: &State<FileCache>,
+    metacache: &State<MetaCache>,
+) -> Result<CachedNamedFile, Status> {
+    let file = peer.0.root.join(path);
+    let meta = metacache
+        .metadata(&file)
+        .await
+        .map_err(|_| Status::NotFound)?;
+    if meta.is_dir() {
+        return Err(Status::NotFound);
+    }
+    CachedNamedFile::open_with_cache(&file, &meta, cache)
+        .await
+        .map_err(|_| Status::NotFound)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn peers(own: &str) -> Peers {
+        Peers::new(&PeersConfig {
+            url: Some(own.to_owned()),
+            peers: vec!["http://a".into(), "http://b".into(), "http://c".into()],
+            token: None,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn rendezvous() {
+        let keys: Vec<String> = (0..300)
+            .map(|i| format!("tver/panorama/{}.b3dm", i))
+            .collect();
+        let a = peers("http://a");
+        let b = peers("http://b");
+
+        // every replica agrees on owners, each one owns a fair share
+        let mut owned = 0;
+        for key in &keys {
+            let owner = |p: &Peers, own| p.owner(key).unwrap_or(own).to_owned();
+            assert_eq!(owner(&a, "http://a"), owner(&b, "http://b"));
+            owned += a.owner(key).is_none() as usize;
+        }
+        assert!(owned > 50 && owned < 150, "owned {}", owned);
+
+        // disabled without own url
+        let p = Peers::new(&PeersConfig::default()).unwrap();
+        assert_eq!(p.owner("tver/panorama/0.b3dm"), None);
+    }
+
+    #[test]
+    fn encoded_url() {
+        let url = peer_url("http://a/3d/", "tver/pano 1/a?b#c.b3dm").unwrap();
+        assert_eq!(url.as_str(), "http://a/3d/peer/tver/pano%201/a%3Fb%23c.b3dm");
+    }
+
+    #[test]
+    fn token_required() {
+        assert!(PeersConfig::default().check().is_ok());
+        let mut config = PeersConfig {
+            peers: vec!["http://a".into()],
+            ..Default::default()
+        };
+        assert!(config.check().is_err());
+        config.token = Some("secret".into());
+        assert!(config.check().is_ok());
+    }
+}
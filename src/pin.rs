@@ -0,0 +1,142 @@
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::State;
+use std::collections::VecDeque;
+use std::path::{Component, Path, PathBuf};
+
+use crate::admin::Admin;
+use crate::cache::FileCache;
+use crate::model::validate_name;
+use crate::tenant::Tenant;
+use crate::Config;
+
+/// Paths to pin in the cache, relative to the tenant storage root
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PinRequest {
+    pub paths: Vec<PathBuf>,
+    pub prefix: Option<PathBuf>, // model directory, files are pinned shallow first
+}
+
+/// Pinning result
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct Pinned {
+    pub pinned: u64,  // pinned files
+    pub skipped: u64, // missing or over the pin budget
+    pub bytes: u64,   // total size of pinned entries
+}
+
+/// Check that the path is relative with valid names only
+pub fn is_plain_path(path: &Path) -> bool {
+    path.components().next().is_some()
+        && path.components().all(|x| match x {
+            Component::Normal(name) => name.to_str().is_some_and(|x| validate_name(x).is_ok()),
+            _ => false,
+        })
+}
+
+/// Files under the directory, breadth-first, index files first in each directory
+async fn files(dir: &Path, index: &[String], max_depth: usize) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    let mut queue = VecDeque::from([(dir.to_path_buf(), 0)]);
+    while let Some((dir, depth)) = queue.pop_front() {
+        let mut entries = match tokio::fs::read_dir(&dir).await {
+            Ok(entries) => entries,
+            Err(err) => {
+                debug!("pin read dir {:?}: {}", dir, err);
+                continue;
+            }
+        };
+        let mut names = Vec::new();
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if validate_name(&name).is_ok() {
+                names.push(name);
+            }
+        }
+        names.sort_by_key(|x| (!index.contains(x), x.clone()));
+        for name in names {
+            let path = dir.join(name);
+            match tokio::fs::metadata(&path).await {
+                Ok(meta) if meta.is_dir() && depth < max_depth => {
+                    queue.push_back((path, depth + 1))
+                }
+                Ok(meta) if meta.is_file() => found.push(path),
+                _ => (),
+            }
+        }
+    }
+    found
+}
+
+/// Keep the files in memory until restart, up to the pin budget
+#[post("/admin/cache/pin", data = "<req>")]
+pub async fn pin(
+    _admin: Admin,
+    req: Json<PinRequest>,
+    tenant: &Tenant,
+    config: &State<Config<'_>>,
+    cache: &State<FileCache>,
+) -> Result<Json<Pinned>, Status> {
+    let req = req.into_inner();
+    if !req.paths.iter().chain(&req.prefix).all(|x| is_plain_path(x)) {
+        return Err(Status::BadRequest);
+    }
+    let root = &tenant.root;
+    let mut paths: Vec<PathBuf> = req.paths.iter().map(|x| root.join(x)).collect();
+    if let Some(ref prefix) = req.prefix {
+        let index = &config.storage.index;
+        paths.extend(files(&root.join(prefix), index, config.storage.max_depth).await);
+    }
+
+    let mut res = Pinned::default();
+    for path in paths {
+        match cache.pin(path.clone()).await {
+            Ok(true) => res.pinned += 1,
+            Ok(false) => res.skipped += 1,
+            Err(err) => {
+                debug!("pin {:?}: {}", path, err);
+                res.skipped += 1
+            }
+        }
+    }
+    res.bytes = cache.pinned_size();
+    info!("pinned {} files, {} skipped", res.pinned, res.skipped);
+    Ok(Json(res))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn plain_paths() {
+        assert!(is_plain_path(Path::new("tver/panorama/tileset.json")));
+        assert!(!is_plain_path(Path::new("")));
+        assert!(!is_plain_path(Path::new("/etc/passwd")));
+        assert!(!is_plain_path(Path::new("tver/../secret")));
+        assert!(!is_plain_path(Path::new("tver/.versions/panorama")));
+    }
+
+    #[tokio::test]
+    async fn shallow_first() {
+        let dir = std::env::temp_dir().join("rtiles-pin-files-test");
+        std::fs::create_dir_all(dir.join("0/1")).unwrap();
+        for name in ["a.b3dm", "tileset.json", "0/b.b3dm", "0/1/c.b3dm"] {
+            std::fs::write(dir.join(name), "x").unwrap();
+        }
+        let index = ["tileset.json".to_owned()];
+        let found: Vec<_> = files(&dir, &index, 12)
+            .await
+            .into_iter()
+            .map(|x| x.strip_prefix(&dir).unwrap().to_owned())
+            .collect();
+        assert_eq!(
+            found,
+            ["tileset.json", "a.b3dm", "0/b.b3dm", "0/1/c.b3dm"].map(PathBuf::from)
+        );
+        assert_eq!(files(&dir, &index, 0).await.len(), 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,127 @@
+use bytes::Bytes;
+use rocket::http::{Header, StatusClass};
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use std::collections::HashMap;
+use std::io::{self, Cursor};
+use std::path::{Path, PathBuf};
+
+use crate::error::Error;
+use crate::metrics::ServerMetrics;
+use crate::mime::ContentTypes;
+
+/// Payloads by file extension served instead of server errors, loaded on startup
+#[derive(Debug, Clone, Default)]
+pub struct Placeholders(HashMap<String, Bytes>);
+
+impl Placeholders {
+    /// Read payload files, a placeholder on the failing storage is useless
+    pub fn load(files: &HashMap<String, PathBuf>) -> io::Result<Self> {
+        let mut map = HashMap::new();
+        for (ext, path) in files {
+            let body = std::fs::read(path)
+                .map_err(|err| io::Error::new(err.kind(), format!("{:?}: {}", path, err)))?;
+            map.insert(ext.to_lowercase(), Bytes::from(body));
+        }
+        Ok(Placeholders(map))
+    }
+
+    /// Placeholder for the requested file extension
+    pub fn get(&self, path: &Path) -> Option<Bytes> {
+        let ext = path.extension()?.to_string_lossy().to_lowercase();
+        self.0.get(&ext).cloned()
+    }
+}
+
+/// Tile handler error, server errors are answered with a placeholder if configured
+#[derive(Debug)]
+pub struct TileError(pub Error);
+
+impl From<Error> for TileError {
+    fn from(e: Error) -> Self {
+        TileError(e)
+    }
+}
+
+impl From<io::Error> for TileError {
+    fn from(e: io::Error) -> Self {
+        TileError(e.into())
+    }
+}
+
+impl<'r> Responder<'r, 'static> for TileError {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let path = Path::new(req.uri().path().as_str());
+        let body = match req.rocket().state::<Placeholders>() {
+            Some(placeholders) if self.0.status().class() == StatusClass::ServerError => {
+                placeholders.get(path)
+            }
+            _ => None,
+        };
+        let Some(body) = body else {
+            return self.0.respond_to(req);
+        };
+
+        warn!(
+            "{} {}: {:?}, placeholder served",
+            self.0.status(),
+            req.uri(),
+            self.0
+        );
+        if let Some(metrics) = req.rocket().state::<ServerMetrics>() {
+            metrics.error(&self.0);
+        }
+        let content_type = match req.rocket().state::<ContentTypes>() {
+            Some(types) => types.get(path),
+            None => ContentTypes::default().get(path),
+        };
+        // placeholders must not replace the tile in client and shared caches
+        Response::build()
+            .header(content_type)
+            .header(Header::new("Cache-Control", "no-store"))
+            .header(Header::new("Cache-Status", "rtiles; detail=placeholder"))
+            .sized_body(body.len(), Cursor::new(body))
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rocket::http::Status;
+    use rocket::local::asynchronous::Client;
+
+    // rocket declares an unused uri macro reexport per route
+    #[allow(unused_imports)]
+    mod handlers {
+        use super::*;
+
+        #[get("/<_>")]
+        pub fn failing() -> Result<&'static str, TileError> {
+            Err(io::Error::other("disk failure").into())
+        }
+
+        #[get("/missing/<_>")]
+        pub fn missing() -> Result<&'static str, TileError> {
+            Err(Error::NotFound("missing".to_owned()).into())
+        }
+    }
+
+    #[rocket::async_test]
+    async fn served_on_failure() {
+        let placeholders = Placeholders([("b3dm".to_owned(), Bytes::from("empty"))].into());
+        let rocket = rocket::build()
+            .manage(placeholders)
+            .mount("/", routes![handlers::failing, handlers::missing]);
+        let client = Client::tracked(rocket).await.unwrap();
+
+        let res = client.get("/tile.B3DM").dispatch().await;
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.headers().get_one("Cache-Control"), Some("no-store"));
+        assert_eq!(res.into_string().await.unwrap(), "empty");
+        let res = client.get("/tile.png").dispatch().await;
+        assert_eq!(res.status(), Status::InternalServerError);
+        let res = client.get("/missing/tile.b3dm").dispatch().await;
+        assert_eq!(res.status(), Status::NotFound);
+    }
+}
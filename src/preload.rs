@@ -0,0 +1,136 @@
+use moka::future::Cache;
+use rocket::http::Header;
+use rocket::request::Request;
+use rocket::response::{self, Responder};
+use rocket::serde::json::{self, Value};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::Meta;
+
+/// Content uri of the tile, `url` is used by pre 1.0 tilesets
+fn content_uri(tile: &Value) -> Option<&str> {
+    let content = tile.get("content")?;
+    content
+        .get("uri")
+        .or_else(|| content.get("url"))
+        .and_then(Value::as_str)
+}
+
+/// Relative content uris of the root tile and its first-level children
+pub fn root_uris(tileset: &[u8], max: usize) -> Vec<String> {
+    let root = match json::from_slice::<Value>(tileset) {
+        Ok(value) => value.get("root").cloned().unwrap_or_default(),
+        Err(_) => return Vec::new(),
+    };
+    let children = root
+        .get("children")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten();
+    std::iter::once(&root)
+        .chain(children)
+        .filter_map(content_uri)
+        // other origins would need own credentials mode
+        .filter(|uri| !uri.contains("://"))
+        .take(max)
+        .map(str::to_owned)
+        .collect()
+}
+
+/// `Link` header value, the tileset query (e.g. access token) is passed
+/// to the children the same way as viewers do it
+pub fn link(uri: &str, query: Option<&str>) -> String {
+    let uri = match query {
+        Some(query) if !uri.contains('?') => format!("{}?{}", uri, query),
+        Some(query) => format!("{}&{}", uri, query),
+        None => uri.to_owned(),
+    };
+    format!("<{}>; rel=preload; as=fetch; crossorigin", uri)
+}
+
+/// Cache of tileset root uris, validated by file metadata
+#[derive(Clone)]
+pub struct PreloadCache {
+    cache: Cache<PathBuf, (Meta, Arc<Vec<String>>)>,
+}
+
+impl PreloadCache {
+    pub fn new() -> Self {
+        let cache = Cache::builder()
+            // Max 10,000 tilesets
+            .max_capacity(10_000)
+            .build();
+        PreloadCache { cache }
+    }
+
+    /// Get cached or parse root uris of the tileset file
+    pub async fn get(&self, path: &Path, meta: &Meta, max: usize) -> Arc<Vec<String>> {
+        if let Some((m, uris)) = self.cache.get(path).await {
+            if &m == meta {
+                return uris;
+            }
+        }
+        let uris = match tokio::fs::read(path).await {
+            Ok(data) => Arc::new(root_uris(&data, max)),
+            Err(err) => {
+                debug!("preload links of {:?}: {}", path, err);
+                return Arc::default();
+            }
+        };
+        self.cache
+            .insert(path.to_path_buf(), (meta.clone(), uris.clone()))
+            .await;
+        uris
+    }
+}
+
+/// Response with `Link` preload headers
+pub struct Preloaded<R> {
+    pub inner: R,
+    pub uris: Arc<Vec<String>>,
+}
+
+impl<'r, R: Responder<'r, 'static>> Responder<'r, 'static> for Preloaded<R> {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let mut res = self.inner.respond_to(req)?;
+        let query = req.uri().query().map(|x| x.as_str());
+        for uri in self.uris.iter() {
+            res.adjoin_header(Header::new("Link", link(uri, query)));
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn uris() {
+        let tileset = br#"{ "root": {
+            "content": { "uri": "root.b3dm" },
+            "children": [
+                { "content": { "url": "a/0.b3dm" } },
+                { "children": [] },
+                { "content": { "uri": "https://cdn.example.com/b.b3dm" } },
+                { "content": { "uri": "c/0.b3dm" } }
+            ]
+        }}"#;
+        assert_eq!(
+            root_uris(tileset, 10),
+            ["root.b3dm", "a/0.b3dm", "c/0.b3dm"]
+        );
+        assert_eq!(root_uris(tileset, 2), ["root.b3dm", "a/0.b3dm"]);
+        assert!(root_uris(b"not json", 10).is_empty());
+
+        assert_eq!(
+            link("a/0.b3dm", Some("share=x")),
+            "<a/0.b3dm?share=x>; rel=preload; as=fetch; crossorigin"
+        );
+        assert_eq!(
+            link("a/0.b3dm?v=1", None),
+            "<a/0.b3dm?v=1>; rel=preload; as=fetch; crossorigin"
+        );
+    }
+}
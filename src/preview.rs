@@ -0,0 +1,66 @@
+use rocket::response::content::RawHtml;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::State;
+
+use crate::access::AccessKey;
+use crate::Config;
+
+const PAGE: &str = include_str!("../static/preview.html");
+
+/// Model preview page configuration
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct PreviewConfig {
+    pub cesium_url: String, // CesiumJS build base url
+}
+
+impl Default for PreviewConfig {
+    fn default() -> Self {
+        PreviewConfig {
+            cesium_url: "https://cesium.com/downloads/cesiumjs/releases/1.110/Build/Cesium"
+                .to_owned(),
+        }
+    }
+}
+
+/// Escape html special chars
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Render preview page
+pub fn render(config: &PreviewConfig, title: &str) -> String {
+    PAGE.replace("{cesium}", &escape(config.cesium_url.trim_end_matches('/')))
+        .replace("{title}", &escape(title))
+}
+
+/// Cesium viewer page, loads model tileset from the same path
+#[get("/models/<_>/<_>/preview")]
+pub async fn preview(key: AccessKey, config: &State<Config<'_>>) -> RawHtml<String> {
+    let title = match config.model(&key.model).title {
+        Some(ref title) => title.clone(),
+        None => format!(
+            "{}/{}",
+            key.model.object.as_ref().unwrap(),
+            key.model.name.as_ref().unwrap()
+        ),
+    };
+    RawHtml(render(&config.preview, &title))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn page() {
+        let config = PreviewConfig {
+            cesium_url: "https://cdn/cesium/".to_owned(),
+        };
+        let page = render(&config, "<city>");
+        assert!(page.contains(r#"<script src="https://cdn/cesium/Cesium.js"></script>"#));
+        assert!(page.contains("<title>&lt;city&gt; - rtiles preview</title>"));
+    }
+}
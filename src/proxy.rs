@@ -0,0 +1,190 @@
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use crate::Config;
+
+/// IP network in CIDR notation, single address if no prefix
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    /// Does the network contain the address?
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // compare ipv4 mapped ipv6 peers as ipv4
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+            ip => ip,
+        };
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr
+            .trim()
+            .parse()
+            .map_err(|_| format!("illegal address: {}", s))?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(x) => x
+                .trim()
+                .parse()
+                .ok()
+                .filter(|x| *x <= max)
+                .ok_or_else(|| format!("illegal prefix: {}", s))?,
+            None => max,
+        };
+        Ok(Cidr { addr, prefix })
+    }
+}
+
+impl TryFrom<String> for Cidr {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Cidr> for String {
+    fn from(x: Cidr) -> Self {
+        x.to_string()
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+fn is_trusted(proxies: &[Cidr], ip: IpAddr) -> bool {
+    proxies.iter().any(|x| x.contains(ip))
+}
+
+/// Client address behind the trusted proxies chain
+fn client_ip(proxies: &[Cidr], peer: IpAddr, forwarded_for: Option<&str>) -> IpAddr {
+    if !is_trusted(proxies, peer) {
+        return peer;
+    }
+    // walk the chain from the nearest proxy, first untrusted hop is the client
+    let mut client = peer;
+    if let Some(chain) = forwarded_for {
+        for hop in chain.rsplit(',') {
+            match hop.trim().parse() {
+                Ok(ip) => {
+                    client = ip;
+                    if !is_trusted(proxies, ip) {
+                        break;
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+    }
+    client
+}
+
+/// Client address and scheme, taken from forwarded headers of trusted proxies
+#[derive(Debug, Clone, PartialEq)]
+pub struct Forwarded {
+    pub ip: Option<IpAddr>,
+    pub proto: &'static str,
+}
+
+impl Forwarded {
+    /// Resolve forwarded params of the request, cached per request
+    pub fn of<'r>(req: &'r Request<'_>) -> &'r Forwarded {
+        req.local_cache(|| {
+            let config = req.rocket().state::<Config<'_>>().unwrap();
+            let proxies = &config.trusted_proxies;
+            let peer = req.remote().map(|x| x.ip());
+            let trusted = peer.is_some_and(|x| is_trusted(proxies, x));
+            let ip = peer.map(|x| client_ip(proxies, x, req.headers().get_one("X-Forwarded-For")));
+            let proto = match req.headers().get_one("X-Forwarded-Proto") {
+                Some(x) if trusted && x.eq_ignore_ascii_case("https") => "https",
+                _ => "http",
+            };
+            Forwarded { ip, proto }
+        })
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for &'r Forwarded {
+    type Error = Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(Forwarded::of(req))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn cidr() {
+        let net: Cidr = "10.0.0.0/8".parse().unwrap();
+        assert!(net.contains(ip("10.1.2.3")));
+        assert!(!net.contains(ip("11.0.0.1")));
+        assert!(net.contains(ip("::ffff:10.0.0.1")));
+        let host: Cidr = "192.168.1.1".parse().unwrap();
+        assert!(host.contains(ip("192.168.1.1")));
+        assert!(!host.contains(ip("192.168.1.2")));
+        let v6: Cidr = "fd00::/8".parse().unwrap();
+        assert!(v6.contains(ip("fd12::1")));
+        assert!(!v6.contains(ip("10.0.0.1")));
+        let all: Cidr = "0.0.0.0/0".parse().unwrap();
+        assert!(all.contains(ip("8.8.8.8")));
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("proxy".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn forwarded_for() {
+        let proxies: Vec<Cidr> = vec!["10.0.0.0/8".parse().unwrap()];
+        let xff = Some("203.0.113.7, 198.51.100.1, 10.0.0.2");
+        // untrusted peer, headers ignored
+        assert_eq!(
+            client_ip(&proxies, ip("198.51.100.9"), xff),
+            ip("198.51.100.9")
+        );
+        // trusted peer, first untrusted hop from the right
+        assert_eq!(client_ip(&proxies, ip("10.0.0.1"), xff), ip("198.51.100.1"));
+        assert_eq!(client_ip(&proxies, ip("10.0.0.1"), None), ip("10.0.0.1"));
+        assert_eq!(
+            client_ip(&proxies, ip("10.0.0.1"), Some("garbage")),
+            ip("10.0.0.1")
+        );
+    }
+}
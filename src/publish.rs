@@ -0,0 +1,111 @@
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::serde::Serialize;
+use rocket::State;
+use std::path::{Path, PathBuf};
+
+use crate::admin::{model_dir, Admin};
+use crate::cache::FileCache;
+use crate::events::{Event, Events};
+use crate::model::validate_name;
+use crate::registry::ModelRegistry;
+use crate::tenant::Tenant;
+
+/// Object subdirectory with staged model versions, hidden from the inventory
+pub const VERSIONS_DIR: &str = ".versions";
+
+/// Path to the staged model version directory
+pub fn version_dir(
+    root: &Path,
+    object: &str,
+    model: &str,
+    version: &str,
+) -> Result<PathBuf, Status> {
+    if validate_name(version).is_err() {
+        return Err(Status::BadRequest);
+    }
+    let dir = model_dir(root, object, model)?;
+    Ok(dir.with_file_name(VERSIONS_DIR).join(model).join(version))
+}
+
+/// Activated model version
+#[derive(Debug, Serialize)]
+pub struct Activated {
+    pub version: String,
+    pub purged: u64, // invalidated cache entries
+}
+
+/// Atomically switch the live model to the staged version
+#[post("/admin/models/<object>/<model>/activate?<version>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn activate(
+    _admin: Admin,
+    object: &str,
+    model: &str,
+    version: &str,
+    tenant: &Tenant,
+    cache: &State<FileCache>,
+    registry: &State<ModelRegistry>,
+    events: &State<Events>,
+) -> Result<Json<Activated>, Status> {
+    let target = version_dir(&tenant.root, object, model, version)?;
+    if !tokio::fs::metadata(&target).await.is_ok_and(|x| x.is_dir()) {
+        return Err(Status::NotFound);
+    }
+
+    // live model must be a link to the version, not a plain directory
+    let live = model_dir(&tenant.root, object, model)?;
+    match tokio::fs::symlink_metadata(&live).await {
+        Ok(meta) if !meta.file_type().is_symlink() => return Err(Status::Conflict),
+        _ => (),
+    }
+
+    // make new link aside and rename it over the live one, rename is atomic
+    let link = live.with_file_name(format!(".{}.activate", model));
+    let _ = tokio::fs::remove_file(&link).await;
+    let relative = PathBuf::from(VERSIONS_DIR).join(model).join(version);
+    let res = async {
+        tokio::fs::symlink(&relative, &link).await?;
+        tokio::fs::rename(&link, &live).await
+    }
+    .await;
+    if let Err(err) = res {
+        error!("model activation error: {}", err);
+        let _ = tokio::fs::remove_file(&link).await;
+        return Err(Status::InternalServerError);
+    }
+    info!("model {}/{} activated: {}", object, model, version);
+
+    // drop content and metadata of the previous version
+    let purged = cache.purge(&live).await;
+    if let Err(err) = registry.rescan(&tenant.root).await {
+        error!("storage scan error: {}", err);
+    }
+    events.send(Event::ModelActivated {
+        object: object.to_owned(),
+        model: model.to_owned(),
+        version: version.to_owned(),
+    });
+    Ok(Json(Activated {
+        version: version.to_owned(),
+        purged,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn version_path() {
+        let root = Path::new("data");
+        assert_eq!(
+            version_dir(root, "tver", "panorama", "v2"),
+            Ok(PathBuf::from("data/tver/.versions/panorama/v2"))
+        );
+        assert_eq!(
+            version_dir(root, "tver", "panorama", ".."),
+            Err(Status::BadRequest)
+        );
+    }
+}
@@ -0,0 +1,119 @@
+use reqwest::Client;
+use rocket::serde::json;
+use std::time::Duration;
+
+use crate::cli::StatArgs;
+use crate::stat::Metrics;
+
+/// Human readable bytes count
+pub fn human_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Format metrics as aligned lines
+pub fn format_metrics(args: &StatArgs, m: &Metrics) -> String {
+    let ratio = if m.hits > 0 {
+        m.cached as f64 * 100.0 / m.hits as f64
+    } else {
+        0.0
+    };
+    format!(
+        "object  {}\nmodel   {}\nhits    {}\ncached  {} ({:.1}%)\nbytes   {}\nlatency {:.1} ms",
+        args.object,
+        args.model.as_deref().unwrap_or("*"),
+        m.hits,
+        m.cached,
+        ratio,
+        human_bytes(m.bytes),
+        m.latency_ms()
+    )
+}
+
+/// Query model metrics from the server and print, returns true on success
+pub async fn run(args: &StatArgs, server: &str, token: Option<&str>) -> bool {
+    let mut url = format!("{}/stat/{}", server.trim_end_matches('/'), args.object);
+    if let Some(ref model) = args.model {
+        url.push('/');
+        url.push_str(model);
+    }
+    let client = Client::builder().timeout(Duration::from_secs(10)).build();
+    let mut req = match client {
+        Ok(client) => client.get(&url),
+        Err(err) => {
+            eprintln!("Problem create http client: {err}");
+            return false;
+        }
+    };
+    if let Some(token) = token {
+        req = req.bearer_auth(token);
+    }
+    let res = match req.send().await {
+        Ok(res) if res.status().is_success() => res,
+        Ok(res) => {
+            eprintln!("{}: {}", url, res.status());
+            return false;
+        }
+        Err(err) => {
+            eprintln!("{}: {}", url, err);
+            return false;
+        }
+    };
+    let metrics = match res.text().await.map(|x| json::from_str::<Metrics>(&x)) {
+        Ok(Ok(metrics)) => metrics,
+        Ok(Err(err)) => {
+            eprintln!("Illegal response: {err}");
+            return false;
+        }
+        Err(err) => {
+            eprintln!("{}: {}", url, err);
+            return false;
+        }
+    };
+    println!("{}", format_metrics(args, &metrics));
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bytes() {
+        assert_eq!(human_bytes(512), "512 B");
+        assert_eq!(human_bytes(1536), "1.5 KB");
+        assert_eq!(human_bytes(5 * 1024 * 1024 * 1024), "5.0 GB");
+    }
+
+    #[test]
+    fn format() {
+        let args = StatArgs {
+            object: "city".to_owned(),
+            model: None,
+            url: None,
+            token: None,
+        };
+        let m = Metrics {
+            hits: 4,
+            cached: 3,
+            bytes: 2048,
+            time_us: 5000,
+            timed: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            format_metrics(&args, &m),
+            "object  city\nmodel   *\nhits    4\ncached  3 (75.0%)\nbytes   2.0 KB\nlatency 2.5 ms"
+        );
+    }
+}
@@ -0,0 +1,134 @@
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::access::SessionId;
+use crate::events::{Event, Events};
+use crate::proxy::Forwarded;
+use crate::stat::{Metrics, Stat};
+use crate::usage::{this_month, today, UsageKey};
+use crate::Config;
+
+/// Request and byte limits of the client, not limited if not set
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct Quota {
+    pub daily_requests: Option<u64>,
+    pub daily_bytes: Option<u64>,
+    pub monthly_requests: Option<u64>,
+    pub monthly_bytes: Option<u64>,
+}
+
+impl Quota {
+    /// Check the client usage, too many requests or forbidden if bytes exceeded
+    pub fn check(&self, daily: &Metrics, monthly: &Metrics) -> Result<(), Status> {
+        let over = |used: u64, limit: Option<u64>| limit.is_some_and(|x| used >= x);
+        if over(daily.hits, self.daily_requests) || over(monthly.hits, self.monthly_requests) {
+            return Err(Status::TooManyRequests);
+        }
+        if over(daily.bytes, self.daily_bytes) || over(monthly.bytes, self.monthly_bytes) {
+            return Err(Status::Forbidden);
+        }
+        Ok(())
+    }
+}
+
+/// Client quotas configuration
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct QuotaConfig {
+    pub default: Option<Quota>,       // applied to every client without own quota
+    pub keys: HashMap<String, Quota>, // keyed by API key or session id
+}
+
+impl QuotaConfig {
+    /// Get quota of the client
+    pub fn get(&self, client: &str) -> Option<&Quota> {
+        self.keys.get(client).or(self.default.as_ref())
+    }
+}
+
+/// Quota checked client, usage is counted only for clients with quota
+#[derive(Debug)]
+pub struct ApiClient(pub Option<String>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ApiClient {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let config = req.rocket().state::<Config<'_>>().unwrap();
+        let session_id = req.guard::<SessionId>().await.unwrap();
+        // anonymous clients are limited by the address behind trusted proxies
+        let id = match session_id.value() {
+            Some(id) => id.to_owned(),
+            None => match Forwarded::of(req).ip {
+                Some(ip) => ip.to_string(),
+                None => return Outcome::Success(ApiClient(None)),
+            },
+        };
+        let (id, quota) = match config.quota.get(&id) {
+            Some(quota) => (id.as_str(), quota),
+            None => return Outcome::Success(ApiClient(None)),
+        };
+
+        let usage = req.rocket().state::<Stat>().unwrap().usage();
+        let key = |period| UsageKey {
+            period,
+            client: Some(id.to_owned()),
+            object: None,
+        };
+        let daily = usage.get(&key(today())).await;
+        let monthly = usage.get(&key(this_month())).await;
+        match quota.check(&daily, &monthly) {
+            Ok(()) => Outcome::Success(ApiClient(Some(id.to_owned()))),
+            Err(status) => {
+                debug!("quota exceeded for client {}: {}", id, status);
+                if let Some(events) = req.rocket().state::<Events>() {
+                    events.send(Event::QuotaExceeded {
+                        client: id.to_owned(),
+                        status: status.code,
+                    });
+                }
+                Outcome::Failure((status, ()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn check() {
+        let quota = Quota {
+            daily_requests: Some(10),
+            monthly_bytes: Some(1000),
+            ..Default::default()
+        };
+        let usage = |hits, bytes| Metrics {
+            hits,
+            cached: 0,
+            bytes,
+            ..Default::default()
+        };
+        assert_eq!(quota.check(&usage(9, 0), &usage(100, 999)), Ok(()));
+        assert_eq!(
+            quota.check(&usage(10, 0), &usage(10, 0)),
+            Err(Status::TooManyRequests)
+        );
+        assert_eq!(
+            quota.check(&usage(1, 0), &usage(1, 1000)),
+            Err(Status::Forbidden)
+        );
+
+        let mut config = QuotaConfig::default();
+        assert_eq!(config.get("key"), None);
+        config.default = Some(Quota::default());
+        config.keys.insert("key".to_owned(), quota.clone());
+        assert_eq!(config.get("key"), Some(&quota));
+        assert_eq!(config.get("other"), Some(&Quota::default()));
+    }
+}
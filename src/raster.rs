@@ -0,0 +1,410 @@
+use bytes::Bytes;
+use rocket::serde::json::{self, Value};
+use rocket::serde::{Deserialize, Serialize};
+use rocket::State;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tokio::task;
+
+use crate::access::AccessKey;
+use crate::cache::{CachedNamedFile, Content, FileCache, Forward, Validators};
+use crate::cache_control::CacheControl;
+use crate::meta::{Meta, MetaCache};
+use crate::stat::{Stat, Timer};
+use crate::quota::ApiClient;
+use crate::tenant::Tenant;
+use crate::{insert_stat, Config, Error};
+
+/// Tile row numbering scheme
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TileScheme {
+    Xyz, // row 0 at the north (OSM, Google)
+    Tms, // row 0 at the south (TMS, MBTiles)
+}
+
+/// Raster tiles configuration
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct RasterConfig {
+    pub scheme: TileScheme, // scheme of the row number in request url
+    pub max_zoom: u8,       // max allowed zoom level
+}
+
+impl Default for RasterConfig {
+    fn default() -> Self {
+        RasterConfig {
+            scheme: TileScheme::Xyz,
+            max_zoom: 24,
+        }
+    }
+}
+
+/// Tile address in the pyramid
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileCoord {
+    pub z: u8,
+    pub x: u32,
+    pub y: u32, // row in XYZ scheme
+    pub ext: String,
+}
+
+impl TileCoord {
+    /// Parse tile address from url params, `tile` is a `<y>.<ext>` segment
+    pub fn parse(z: u8, x: u32, tile: &str, config: &RasterConfig) -> Option<Self> {
+        let (y, ext) = tile.split_once('.')?;
+        let y: u32 = y.parse().ok()?;
+        if z > config.max_zoom || z > 31 || ext.is_empty() {
+            return None;
+        }
+        let max = 1u32 << z;
+        if x >= max || y >= max {
+            return None;
+        }
+        let y = match config.scheme {
+            TileScheme::Xyz => y,
+            TileScheme::Tms => max - 1 - y,
+        };
+        Some(TileCoord {
+            z,
+            x,
+            y,
+            ext: ext.to_lowercase(),
+        })
+    }
+
+    /// Row number in TMS scheme
+    pub fn tms_y(&self) -> u32 {
+        (1u32 << self.z) - 1 - self.y
+    }
+
+    /// Relative tile path `z/x/y.ext` in XYZ scheme
+    pub fn path(&self) -> PathBuf {
+        [
+            self.z.to_string(),
+            self.x.to_string(),
+            format!("{}.{}", self.y, self.ext),
+        ]
+        .iter()
+        .collect()
+    }
+}
+
+/// Raster layer metadata, MBTiles metadata table keys
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerInfo {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub format: String,   // tile file extension
+    pub bounds: [f64; 4], // WGS84 west, south, east, north
+    pub minzoom: u8,
+    pub maxzoom: u8,
+}
+
+impl Default for LayerInfo {
+    fn default() -> Self {
+        LayerInfo {
+            name: None,
+            description: None,
+            format: "png".to_owned(),
+            bounds: [-180.0, -85.051129, 180.0, 85.051129],
+            minzoom: 0,
+            maxzoom: 18,
+        }
+    }
+}
+
+impl LayerInfo {
+    /// Make layer info from the key-value map, ignore invalid values
+    pub fn from_map(map: &HashMap<String, String>) -> Self {
+        let mut info = LayerInfo {
+            name: map.get("name").cloned(),
+            description: map.get("description").cloned(),
+            ..Default::default()
+        };
+        if let Some(format) = map.get("format") {
+            info.format = format.to_lowercase();
+        }
+        if let Some(bounds) = map.get("bounds") {
+            let v: Vec<f64> = bounds
+                .split(',')
+                .filter_map(|x| x.trim().parse().ok())
+                .collect();
+            if let Ok(b) = v.try_into() {
+                info.bounds = b;
+            }
+        }
+        if let Some(z) = map.get("minzoom").and_then(|x| x.parse().ok()) {
+            info.minzoom = z;
+        }
+        if let Some(z) = map.get("maxzoom").and_then(|x| x.parse().ok()) {
+            info.maxzoom = z;
+        }
+        info
+    }
+
+    /// Load layer info from `metadata.json` in the layer directory
+    /// or from the MBTiles metadata table
+    pub async fn load(layer: &Path, mbtiles: &MbTiles) -> io::Result<Self> {
+        match tokio::fs::metadata(layer).await {
+            Ok(m) if m.is_dir() => Self::from_json(&layer.join("metadata.json")).await,
+            _ => {
+                let db = mbtiles_path(layer);
+                let meta = Meta::from_path(&db).await?;
+                Ok(LayerInfo::from_map(&mbtiles.metadata(&db, &meta).await?))
+            }
+        }
+    }
+
+    // read layer info from json file, defaults if file not exists
+    async fn from_json(path: &Path) -> io::Result<Self> {
+        let s = match tokio::fs::read_to_string(path).await {
+            Ok(s) => s,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(LayerInfo::default()),
+            Err(e) => return Err(e),
+        };
+        let map: HashMap<String, Value> =
+            json::from_str(&s).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let map = map
+            .into_iter()
+            .map(|(k, v)| match v {
+                Value::String(s) => (k, s),
+                v => (k, v.to_string()),
+            })
+            .collect();
+        Ok(LayerInfo::from_map(&map))
+    }
+}
+
+/// Path to MBTiles file of the layer: `<layer>.mbtiles`
+pub fn mbtiles_path(layer: &Path) -> PathBuf {
+    let mut name = layer.file_name().unwrap_or_default().to_os_string();
+    name.push(".mbtiles");
+    layer.with_file_name(name)
+}
+
+type Connections = HashMap<PathBuf, (Option<SystemTime>, Arc<Mutex<Connection>>)>;
+
+/// Read-only MBTiles connections pool
+#[derive(Default)]
+pub struct MbTiles {
+    conns: Arc<Mutex<Connections>>,
+}
+
+impl MbTiles {
+    pub fn new() -> Self {
+        MbTiles::default()
+    }
+
+    /// Read tile data from MBTiles file, `None` if tile not exists
+    pub async fn tile(
+        &self,
+        path: &Path,
+        meta: &Meta,
+        coord: &TileCoord,
+    ) -> io::Result<Option<Vec<u8>>> {
+        let conns = Arc::clone(&self.conns);
+        let path = path.to_path_buf();
+        let modified = meta.modified();
+        let (z, x, y) = (coord.z, coord.x, coord.tms_y());
+
+        task::spawn_blocking(move || {
+            let conn = Self::connection(&conns, &path, modified).map_err(io::Error::other)?;
+            let conn = conn.lock().unwrap();
+            conn.query_row(
+                "SELECT tile_data FROM tiles \
+                 WHERE zoom_level = ?1 AND tile_column = ?2 AND tile_row = ?3",
+                params![z, x, y],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(io::Error::other)
+        })
+        .await?
+    }
+
+    /// Read metadata table from MBTiles file
+    pub async fn metadata(&self, path: &Path, meta: &Meta) -> io::Result<HashMap<String, String>> {
+        let conns = Arc::clone(&self.conns);
+        let path = path.to_path_buf();
+        let modified = meta.modified();
+
+        task::spawn_blocking(move || {
+            let conn = Self::connection(&conns, &path, modified).map_err(io::Error::other)?;
+            let conn = conn.lock().unwrap();
+            let mut stmt = conn
+                .prepare("SELECT name, value FROM metadata")
+                .map_err(io::Error::other)?;
+            let rows = stmt
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+                .map_err(io::Error::other)?;
+            rows.collect::<rusqlite::Result<_>>()
+                .map_err(io::Error::other)
+        })
+        .await?
+    }
+
+    // get opened connection, reopen if the file was modified
+    fn connection(
+        conns: &Mutex<Connections>,
+        path: &Path,
+        modified: Option<SystemTime>,
+    ) -> rusqlite::Result<Arc<Mutex<Connection>>> {
+        let mut map = conns.lock().unwrap();
+        if let Some((m, conn)) = map.get(path) {
+            if *m == modified {
+                return Ok(Arc::clone(conn));
+            }
+        }
+        debug!("open mbtiles: {:?}", path);
+        let conn = Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        let conn = Arc::new(Mutex::new(conn));
+        map.insert(path.to_path_buf(), (modified, Arc::clone(&conn)));
+        Ok(conn)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+#[get("/raster/<_>/<_>/<z>/<x>/<tile>")]
+pub async fn raster_tile(
+    timer: Timer,
+    key: AccessKey,
+    client: ApiClient,
+    tenant: &Tenant,
+    z: u8,
+    x: u32,
+    tile: &str,
+    validators: Validators<'_>,
+    config: &State<Config<'_>>,
+    cache: &State<FileCache>,
+    metacache: &State<MetaCache>,
+    mbtiles: &State<MbTiles>,
+    stat: &State<Stat>,
+) -> Result<CacheControl<CachedNamedFile>, Error> {
+    let coord = TileCoord::parse(z, x, tile, &config.raster)
+        .ok_or_else(|| Error::BadRequest(format!("illegal tile address: {z}/{x}/{tile}")))?;
+
+    // build path to the layer
+    let mut layer = tenant.root.clone();
+    layer.push(key.model.object.as_ref().unwrap());
+    layer.push(key.model.name.as_ref().unwrap());
+
+    // serve tile from directory tree if exists, otherwise from MBTiles file
+    let file = layer.join(coord.path());
+    let res = match metacache.metadata(&file).await {
+        Ok(meta) => {
+            debug!("serving file: {:?}", &file);
+            CachedNamedFile::open_with_cache(&file, &meta, cache).await?
+        }
+        Err(_) => {
+            let db = mbtiles_path(&layer);
+            let meta = metacache.metadata(&db).await?;
+            let path = db.join(coord.path());
+            debug!("serving mbtiles tile: {:?}", &path);
+            match cache.get(&path).await {
+                Some(cnt) if cnt.meta().modified() == meta.modified() => {
+                    CachedNamedFile::Cached(Box::new(cnt))
+                }
+                _ => {
+                    let data = mbtiles
+                        .tile(&db, &meta, &coord)
+                        .await?
+                        .ok_or_else(|| Error::NotFound(format!("tile not found: {:?}", &path)))?;
+                    let meta = Meta::new(data.len() as u64, meta.modified(), false);
+                    let cnt = Content::new(path.clone(), meta, Bytes::from(data));
+                    if cache.fits(cnt.meta().len()) {
+                        cache.put(path, cnt.clone()).await;
+                        CachedNamedFile::Loaded(Box::new(cnt), Forward::Miss)
+                    } else {
+                        CachedNamedFile::Loaded(Box::new(cnt), Forward::Bypass("too-large"))
+                    }
+                }
+            }
+        }
+    };
+
+    let model_config = config.model(&key.model);
+    insert_stat(stat, key.model, client, &res, validators, timer).await;
+
+    Ok(CacheControl::new(res, config.storage.max_age).for_model(model_config))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tile_coord() {
+        let mut config = RasterConfig::default();
+        let coord = TileCoord::parse(2, 1, "0.png", &config).unwrap();
+        assert_eq!(coord.y, 0);
+        assert_eq!(coord.tms_y(), 3);
+        assert_eq!(coord.path(), PathBuf::from("2/1/0.png"));
+
+        config.scheme = TileScheme::Tms;
+        let coord = TileCoord::parse(2, 1, "0.PNG", &config).unwrap();
+        assert_eq!(coord.y, 3);
+        assert_eq!(coord.path(), PathBuf::from("2/1/3.png"));
+
+        assert_eq!(TileCoord::parse(2, 4, "0.png", &config), None);
+        assert_eq!(TileCoord::parse(2, 1, "4.png", &config), None);
+        assert_eq!(TileCoord::parse(2, 1, "0", &config), None);
+        assert_eq!(TileCoord::parse(25, 1, "0.png", &config), None);
+    }
+
+    #[tokio::test]
+    async fn mbtiles_tile() {
+        let path = std::env::temp_dir().join(format!("rtiles-{}.mbtiles", std::process::id()));
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE tiles (zoom_level integer, tile_column integer, \
+             tile_row integer, tile_data blob); \
+             INSERT INTO tiles VALUES (1, 0, 1, x'0102');",
+        )
+        .unwrap();
+        drop(conn);
+
+        let meta = Meta::from_path(&path).await.unwrap();
+        let mbtiles = MbTiles::new();
+        let config = RasterConfig::default();
+
+        // XYZ row 0 is TMS row 1 at zoom 1
+        let coord = TileCoord::parse(1, 0, "0.png", &config).unwrap();
+        let data = mbtiles.tile(&path, &meta, &coord).await.unwrap();
+        assert_eq!(data, Some(vec![1, 2]));
+
+        let coord = TileCoord::parse(1, 0, "1.png", &config).unwrap();
+        let data = mbtiles.tile(&path, &meta, &coord).await.unwrap();
+        assert_eq!(data, None);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn layer_info() {
+        let map = [
+            ("name", "Ortho"),
+            ("format", "JPG"),
+            ("bounds", "30.1,56.5,36.8,58.9"),
+            ("maxzoom", "19"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_owned(), v.to_owned()))
+        .collect();
+        let info = LayerInfo::from_map(&map);
+        assert_eq!(info.name.as_deref(), Some("Ortho"));
+        assert_eq!(info.format, "jpg");
+        assert_eq!(info.bounds, [30.1, 56.5, 36.8, 58.9]);
+        assert_eq!((info.minzoom, info.maxzoom), (0, 19));
+        assert_eq!(
+            mbtiles_path(Path::new("data/city/ortho")),
+            PathBuf::from("data/city/ortho.mbtiles")
+        );
+    }
+}
@@ -0,0 +1,75 @@
+use crate::config::ModelConfig;
+
+/// Host part of the `Referer` header url
+fn referer_host(referer: &str) -> Option<&str> {
+    let rest = referer.split_once("://")?.1;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit('@').next()?;
+    let host = match host.strip_prefix('[') {
+        // ipv6 literal
+        Some(x) => x.split(']').next()?,
+        None => host.split(':').next()?,
+    };
+    (!host.is_empty()).then_some(host)
+}
+
+/// Does the host match pattern like `example.com` or `*.example.com`?
+fn host_matches(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host.len().checked_sub(domain.len() + 1).is_some_and(|i| {
+            host.as_bytes()[i] == b'.' && host[i + 1..].eq_ignore_ascii_case(domain)
+        }),
+        None => host.eq_ignore_ascii_case(pattern),
+    }
+}
+
+/// Is the request with the referer allowed to embed the model?
+pub fn allowed(config: &ModelConfig, referer: Option<&str>) -> bool {
+    let patterns = match config.referers {
+        Some(ref x) => x,
+        None => return true,
+    };
+    match referer.map(str::trim).filter(|x| !x.is_empty()) {
+        Some(referer) => match referer_host(referer) {
+            Some(host) => patterns.iter().any(|x| host_matches(x, host)),
+            None => false,
+        },
+        None => config.allow_empty_referer,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn host() {
+        assert_eq!(
+            referer_host("https://maps.example.com/view?x=1"),
+            Some("maps.example.com")
+        );
+        assert_eq!(
+            referer_host("http://user@example.com:8080"),
+            Some("example.com")
+        );
+        assert_eq!(referer_host("http://[::1]:8000/"), Some("::1"));
+        assert_eq!(referer_host("example.com"), None);
+    }
+
+    #[test]
+    fn referers() {
+        let mut config = ModelConfig {
+            referers: Some(vec!["example.com".into(), "*.example.org".into()]),
+            ..Default::default()
+        };
+        assert!(allowed(&config, Some("https://example.com/map")));
+        assert!(allowed(&config, Some("https://a.b.EXAMPLE.org/")));
+        assert!(!allowed(&config, Some("https://example.org/")));
+        assert!(!allowed(&config, Some("https://badexample.org/")));
+        assert!(!allowed(&config, Some("https://evil.com/?example.com")));
+        assert!(!allowed(&config, None));
+        config.allow_empty_referer = true;
+        assert!(allowed(&config, None));
+        assert!(allowed(&ModelConfig::default(), Some("https://evil.com/")));
+    }
+}
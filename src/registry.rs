@@ -0,0 +1,452 @@
+use rocket::serde::Serialize;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::bloom::{self, PathFilter};
+use crate::publish::VERSIONS_DIR;
+use crate::{Config, Model};
+
+/// Model in the storage inventory
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ModelEntry {
+    pub object: String,
+    pub model: String,
+    pub files: u64,
+    pub bytes: u64,
+    pub modified: Option<u64>, // newest file mtime, unix time
+}
+
+/// Registered model description
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ModelInfo {
+    pub object: String,
+    pub model: String,
+    pub title: Option<String>,
+    pub bytes: u64,
+    pub modified: Option<u64>,
+}
+
+impl ModelInfo {
+    /// Describe the inventory entry, title is taken from models config
+    pub fn new(entry: &ModelEntry, config: &Config<'_>) -> Self {
+        let title = config
+            .model(&Model::new(Some(&entry.object), Some(&entry.model)))
+            .title
+            .clone();
+        ModelInfo {
+            object: entry.object.clone(),
+            model: entry.model.clone(),
+            title,
+            bytes: entry.bytes,
+            modified: entry.modified,
+        }
+    }
+
+    /// Case insensitive match of names and title, `query` must be lowercase
+    pub fn matches(&self, query: &str) -> bool {
+        self.object.to_lowercase().contains(query)
+            || self.model.to_lowercase().contains(query)
+            || self
+                .title
+                .as_ref()
+                .is_some_and(|x| x.to_lowercase().contains(query))
+    }
+}
+
+type Inventory = HashMap<PathBuf, Arc<Vec<ModelEntry>>>;
+type Filters = HashMap<PathBuf, Arc<PathFilter>>;
+type Staged = HashMap<PathBuf, HashMap<String, u64>>;
+
+/// Inventory of models in the storage roots, rescanned in background
+#[derive(Clone, Default)]
+pub struct ModelRegistry {
+    roots: Arc<RwLock<Inventory>>,
+    filters: Option<Arc<RwLock<Filters>>>, // existing paths by root, if enabled
+    staged: Arc<RwLock<Staged>>, // bytes of staged versions by root and object
+}
+
+impl ModelRegistry {
+    pub fn new() -> Self {
+        ModelRegistry::default()
+    }
+
+    /// Keep filters of existing paths, rebuilt on every rescan
+    pub fn with_path_filter(mut self, enabled: bool) -> Self {
+        self.filters = enabled.then(Arc::default);
+        self
+    }
+
+    fn filter(&self, root: &Path) -> Option<Arc<PathFilter>> {
+        self.filters.as_ref()?.read().unwrap().get(root).cloned()
+    }
+
+    /// Can the path under the root exist? True until the root is scanned
+    pub fn may_exist(&self, root: &Path, path: &Path) -> bool {
+        self.filter(root).is_none_or(|x| x.may_exist(path))
+    }
+
+    /// Add the created file and its directories to the path filter
+    pub fn add_path(&self, root: &Path, path: &Path) {
+        if let Some(filter) = self.filter(root) {
+            for x in path.ancestors().take_while(|x| x.starts_with(root)) {
+                filter.insert(x);
+            }
+        }
+    }
+
+    /// Rescan storage roots periodically, interval in seconds
+    pub fn start(&self, roots: Vec<PathBuf>, interval: u64) {
+        let registry = self.clone();
+        tokio::spawn(async move {
+            let mut timer = tokio::time::interval(Duration::from_secs(interval));
+            loop {
+                timer.tick().await;
+                for root in &roots {
+                    if let Err(err) = registry.rescan(root).await {
+                        error!("storage scan error in {}: {}", root.to_string_lossy(), err);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Scan the storage root and replace its inventory
+    pub async fn rescan(&self, root: &Path) -> io::Result<Arc<Vec<ModelEntry>>> {
+        let mut paths = self.filters.as_ref().map(|_| ScannedPaths::default());
+        let models = Arc::new(scan(root, paths.as_mut()).await?);
+        let staged = scan_staged(root).await?;
+        if let (Some(filters), Some(paths)) = (&self.filters, paths) {
+            let filter = PathFilter::new(&paths.hashes, paths.links);
+            filters
+                .write()
+                .unwrap()
+                .insert(root.to_path_buf(), Arc::new(filter));
+        }
+        debug!(
+            "storage scanned: {}, {} models",
+            root.to_string_lossy(),
+            models.len()
+        );
+        self.staged
+            .write()
+            .unwrap()
+            .insert(root.to_path_buf(), staged);
+        self.roots
+            .write()
+            .unwrap()
+            .insert(root.to_path_buf(), models.clone());
+        Ok(models)
+    }
+
+    /// Update the inventory entries of the root, if scanned
+    fn modify(&self, root: &Path, f: impl FnOnce(&mut Vec<ModelEntry>)) {
+        let mut roots = self.roots.write().unwrap();
+        if let Some(models) = roots.get_mut(root) {
+            f(Arc::make_mut(models));
+        }
+    }
+
+    /// Account uploaded bytes before the next rescan
+    pub fn add(&self, root: &Path, object: &str, model: &str, bytes: i64) {
+        let modified = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|x| x.as_secs());
+        self.modify(root, |models| {
+            let pos = models
+                .binary_search_by(|x| (x.object.as_str(), x.model.as_str()).cmp(&(object, model)));
+            match pos {
+                Ok(i) => {
+                    let entry = &mut models[i];
+                    entry.bytes = entry.bytes.saturating_add_signed(bytes);
+                    entry.modified = modified;
+                }
+                Err(i) => models.insert(
+                    i,
+                    ModelEntry {
+                        object: object.to_owned(),
+                        model: model.to_owned(),
+                        files: 0,
+                        bytes: bytes.max(0) as u64,
+                        modified,
+                    },
+                ),
+            }
+        })
+    }
+
+    /// Account bytes uploaded to staged versions before the next rescan
+    pub fn add_staged(&self, root: &Path, object: &str, bytes: i64) {
+        let mut staged = self.staged.write().unwrap();
+        if let Some(objects) = staged.get_mut(root) {
+            let entry = objects.entry(object.to_owned()).or_default();
+            *entry = entry.saturating_add_signed(bytes);
+        }
+    }
+
+    /// Remove deleted model before the next rescan
+    pub fn remove(&self, root: &Path, object: &str, model: &str) {
+        self.modify(root, |models| {
+            models.retain(|x| x.object != object || x.model != model)
+        })
+    }
+
+    /// Total size of the object models and staged versions in bytes
+    pub async fn object_bytes(&self, root: &Path, object: &str) -> io::Result<u64> {
+        let models = self.models(root).await?;
+        let staged = self
+            .staged
+            .read()
+            .unwrap()
+            .get(root)
+            .and_then(|x| x.get(object).copied())
+            .unwrap_or(0);
+        Ok(models
+            .iter()
+            .filter(|x| x.object == object)
+            .map(|x| x.bytes)
+            .sum::<u64>()
+            + staged)
+    }
+
+    /// Get models in the storage root, scanned now if not yet in the inventory
+    pub async fn models(&self, root: &Path) -> io::Result<Arc<Vec<ModelEntry>>> {
+        let models = self.roots.read().unwrap().get(root).cloned();
+        match models {
+            Some(models) => Ok(models),
+            None => self.rescan(root).await,
+        }
+    }
+}
+
+// list subdirectories names
+async fn subdirs(path: &Path) -> io::Result<Vec<String>> {
+    let mut dir = tokio::fs::read_dir(path).await?;
+    let mut names = Vec::new();
+    while let Some(entry) = dir.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') {
+            continue;
+        }
+        // follow links of versioned models, broken ones are skipped
+        match tokio::fs::metadata(entry.path()).await {
+            Ok(meta) if meta.is_dir() => names.push(name),
+            Ok(_) => (),
+            Err(err) => warn!("storage scan skipped {:?}: {}", entry.path(), err),
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Paths found by the scan for the path filter
+#[derive(Default)]
+struct ScannedPaths {
+    hashes: Vec<u64>,
+    links: Vec<PathBuf>,
+}
+
+impl ScannedPaths {
+    fn add(&mut self, path: &Path) {
+        self.hashes.push(bloom::hash(path));
+    }
+}
+
+/// Walk the model directory, returns files count, size and newest mtime
+async fn walk(
+    root: &Path,
+    mut paths: Option<&mut ScannedPaths>,
+) -> io::Result<(u64, u64, Option<SystemTime>)> {
+    let (mut files, mut bytes, mut modified) = (0, 0, None);
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let mut entries = tokio::fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let meta = entry.metadata().await?;
+            if let Some(ref mut paths) = paths {
+                paths.add(&entry.path());
+                if meta.is_symlink() {
+                    paths.links.push(entry.path());
+                }
+            }
+            if meta.is_dir() {
+                dirs.push(entry.path());
+                continue;
+            }
+            files += 1;
+            bytes += meta.len();
+            modified = modified.max(meta.modified().ok());
+        }
+    }
+    Ok((files, bytes, modified))
+}
+
+/// Scan storage for `object/model` directories, unreadable objects
+/// and models are logged and skipped
+async fn scan(root: &Path, mut paths: Option<&mut ScannedPaths>) -> io::Result<Vec<ModelEntry>> {
+    let mut models = Vec::new();
+    for object in subdirs(root).await? {
+        if let Some(ref mut paths) = paths {
+            paths.add(&root.join(&object));
+        }
+        let names = match subdirs(&root.join(&object)).await {
+            Ok(names) => names,
+            Err(err) => {
+                warn!("storage scan skipped object {}: {}", object, err);
+                continue;
+            }
+        };
+        for model in names {
+            let dir = root.join(&object).join(&model);
+            if let Some(ref mut paths) = paths {
+                paths.add(&dir);
+            }
+            let (files, bytes, modified) = match walk(&dir, paths.as_deref_mut()).await {
+                Ok(res) => res,
+                Err(err) => {
+                    warn!("storage scan skipped model {}/{}: {}", object, model, err);
+                    continue;
+                }
+            };
+            models.push(ModelEntry {
+                object: object.clone(),
+                model,
+                files,
+                bytes,
+                modified: modified
+                    .and_then(|x| x.duration_since(UNIX_EPOCH).ok())
+                    .map(|x| x.as_secs()),
+            });
+        }
+    }
+    Ok(models)
+}
+
+/// Scan staged model versions, bytes by object, versions linked
+/// as live models are counted by the models scan
+async fn scan_staged(root: &Path) -> io::Result<HashMap<String, u64>> {
+    let mut staged = HashMap::new();
+    for object in subdirs(root).await? {
+        let versions = root.join(&object).join(VERSIONS_DIR);
+        let models = match subdirs(&versions).await {
+            Ok(models) => models,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err),
+        };
+        for model in models {
+            let live = tokio::fs::canonicalize(root.join(&object).join(&model))
+                .await
+                .ok();
+            for version in subdirs(&versions.join(&model)).await? {
+                let dir = versions.join(&model).join(version);
+                if live.is_some() && tokio::fs::canonicalize(&dir).await.ok() == live {
+                    continue;
+                }
+                match walk(&dir, None).await {
+                    Ok((_, bytes, _)) => *staged.entry(object.clone()).or_default() += bytes,
+                    Err(err) => warn!("storage scan skipped {:?}: {}", dir, err),
+                }
+            }
+        }
+    }
+    Ok(staged)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn matches() {
+        let info = ModelInfo {
+            object: "tver".to_owned(),
+            model: "panorama".to_owned(),
+            title: Some("Tver City Center".to_owned()),
+            bytes: 0,
+            modified: None,
+        };
+        assert!(info.matches("tver"));
+        assert!(info.matches("pano"));
+        assert!(info.matches("city center"));
+        assert!(!info.matches("lake"));
+    }
+
+    #[tokio::test]
+    async fn inventory() {
+        let root = std::env::temp_dir().join("rtiles-inventory-test");
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("tver/panorama/0")).unwrap();
+        std::fs::create_dir_all(root.join("tver/center")).unwrap();
+        std::fs::write(root.join("tver/panorama/tileset.json"), "{}").unwrap();
+        std::fs::write(root.join("tver/panorama/0/0.b3dm"), "b3dm").unwrap();
+
+        let registry = ModelRegistry::new();
+        let models = registry.models(&root).await.unwrap();
+        assert_eq!(models.len(), 2);
+        assert_eq!((models[0].model.as_str(), models[0].bytes), ("center", 0));
+        assert_eq!((models[1].files, models[1].bytes), (2, 6));
+        assert!(models[1].modified.is_some());
+
+        // served from inventory until rescan
+        std::fs::write(root.join("tver/center/tileset.json"), "{}").unwrap();
+        assert_eq!(registry.models(&root).await.unwrap()[0].files, 0);
+        registry.rescan(&root).await.unwrap();
+        assert_eq!(registry.models(&root).await.unwrap()[0].files, 1);
+
+        // uploads and deletes are accounted before rescan
+        registry.add(&root, "msk", "kremlin", 10);
+        registry.add(&root, "tver", "center", -2);
+        assert_eq!(registry.object_bytes(&root, "msk").await.unwrap(), 10);
+        assert_eq!(registry.object_bytes(&root, "tver").await.unwrap(), 6);
+        registry.remove(&root, "tver", "panorama");
+        assert_eq!(registry.object_bytes(&root, "tver").await.unwrap(), 0);
+        assert_eq!(registry.models(&root).await.unwrap()[0].object, "msk");
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn staged_versions() {
+        let root = std::env::temp_dir().join("rtiles-staged-test");
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("tver/.versions/panorama/v1")).unwrap();
+        std::fs::create_dir_all(root.join("tver/.versions/panorama/v2")).unwrap();
+        std::fs::write(root.join("tver/.versions/panorama/v1/tileset.json"), "{}").unwrap();
+        std::fs::write(root.join("tver/.versions/panorama/v2/tileset.json"), "{}{}").unwrap();
+        std::os::unix::fs::symlink(".versions/panorama/v1", root.join("tver/panorama")).unwrap();
+
+        // live version is counted once, staged versions are counted too
+        let registry = ModelRegistry::new();
+        assert_eq!(registry.object_bytes(&root, "tver").await.unwrap(), 6);
+        registry.add_staged(&root, "tver", 10);
+        assert_eq!(registry.object_bytes(&root, "tver").await.unwrap(), 16);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn path_filter() {
+        let root = std::env::temp_dir().join("rtiles-path-filter-test");
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("tver/panorama/0")).unwrap();
+        std::fs::write(root.join("tver/panorama/0/0.b3dm"), "b3dm").unwrap();
+
+        // everything passes until scanned
+        let registry = ModelRegistry::new().with_path_filter(true);
+        let missing = root.join("tver/panorama/0/1.b3dm");
+        assert!(registry.may_exist(&root, &missing));
+
+        registry.rescan(&root).await.unwrap();
+        assert!(registry.may_exist(&root, &root.join("tver/panorama")));
+        assert!(registry.may_exist(&root, &root.join("tver/panorama/0/0.b3dm")));
+        assert!(!registry.may_exist(&root, &missing));
+
+        // uploaded files and their directories pass before rescan
+        let uploaded = root.join("tver/panorama/1/0.b3dm");
+        registry.add_path(&root, &uploaded);
+        assert!(registry.may_exist(&root, &uploaded));
+        assert!(registry.may_exist(&root, &root.join("tver/panorama/1")));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,200 @@
+use reqwest::{Client, Url};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use time::{Date, Month, PrimitiveDateTime, Time};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use crate::bench::{self, Summary};
+use crate::cli::ReplayArgs;
+use crate::logger::SECRET_PARAMS;
+
+/// Logged request
+#[derive(Debug, PartialEq)]
+pub struct Entry {
+    pub time: Option<i64>, // unix time, seconds
+    pub uri: String,
+}
+
+/// Parse `2024-05-08T00:39:00Z` access log timestamp
+fn parse_time(s: &str) -> Option<i64> {
+    let (date, time) = s.strip_suffix('Z')?.split_once('T')?;
+    let num = |s: &str| s.parse::<u16>().ok();
+    let mut date = date.splitn(3, '-').map(num);
+    let mut time = time.splitn(3, ':').map(num);
+    let (y, m, d) = (date.next()??, date.next()??, date.next()??);
+    let (h, mi, s) = (time.next()??, time.next()??, time.next()??);
+    let date = Date::from_calendar_date(y as i32, Month::try_from(m as u8).ok()?, d as u8).ok()?;
+    let time = Time::from_hms(h as u8, mi as u8, s as u8).ok()?;
+    Some(
+        PrimitiveDateTime::new(date, time)
+            .assume_utc()
+            .unix_timestamp(),
+    )
+}
+
+/// Parse access log line, GET requests only, mutating requests are never replayed
+pub fn parse_line(line: &str) -> Option<Entry> {
+    // `ip - - [time] "GET /3d/models/..." 200 1234`
+    let time = line
+        .split_once('[')
+        .and_then(|(_, x)| x.split_once(']'))
+        .and_then(|(x, _)| parse_time(x));
+    let request = line.split('"').nth(1)?;
+    let (method, uri) = request.split_once(' ')?;
+    let uri = uri.split(' ').next()?; // optional protocol version
+    (method == "GET" && uri.starts_with('/')).then(|| Entry {
+        time,
+        uri: uri.to_owned(),
+    })
+}
+
+/// Drop logged session secrets, requests run with the `--token` session only
+fn without_secrets(url: &mut Url) {
+    let params: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(name, _)| !SECRET_PARAMS.contains(&name.as_ref()))
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect();
+    if params.is_empty() {
+        url.set_query(None);
+    } else {
+        url.query_pairs_mut().clear().extend_pairs(params);
+    }
+}
+
+/// Delay of the entry from the log start scaled by the speed
+fn delay(entry: &Entry, start: Option<i64>, speed: f64) -> Duration {
+    match (entry.time, start) {
+        (Some(t), Some(start)) if t > start => Duration::from_secs_f64((t - start) as f64 / speed),
+        _ => Duration::ZERO,
+    }
+}
+
+/// Replay logged requests in order, returns true if no errors
+pub async fn run(args: &ReplayArgs, server: &str) -> bool {
+    let log = match std::fs::read_to_string(&args.log) {
+        Ok(log) => log,
+        Err(err) => {
+            eprintln!("Problem read {}: {err}", args.log);
+            return false;
+        }
+    };
+    let origin = match Url::parse(server) {
+        Ok(url) => url,
+        Err(err) => {
+            eprintln!("Illegal server url: {err}");
+            return false;
+        }
+    };
+    let entries: Vec<Entry> = log.lines().filter_map(parse_line).collect();
+    if entries.is_empty() {
+        eprintln!("No GET requests in {}", args.log);
+        return false;
+    }
+    let client = match Client::builder().timeout(Duration::from_secs(30)).build() {
+        Ok(client) => client,
+        Err(err) => {
+            eprintln!("Problem create http client: {err}");
+            return false;
+        }
+    };
+    match args.speed {
+        Some(speed) => println!("replaying {} requests at {speed}x", entries.len()),
+        None => println!("replaying {} requests", entries.len()),
+    }
+
+    let log_start = entries.iter().find_map(|x| x.time);
+    let limit = Arc::new(Semaphore::new(args.concurrency));
+    let token: Option<Arc<str>> = args.token.as_deref().map(Arc::from);
+    let start = Instant::now();
+    let mut tasks = JoinSet::new();
+    for entry in entries {
+        let mut url = match origin.join(&entry.uri) {
+            Ok(url) => url,
+            Err(_) => continue,
+        };
+        without_secrets(&mut url);
+        // original timing if speed is set, as fast as possible otherwise
+        if let Some(speed) = args.speed {
+            let at = start + delay(&entry, log_start, speed);
+            tokio::time::sleep_until(at.into()).await;
+        }
+        let permit = match Arc::clone(&limit).acquire_owned().await {
+            Ok(permit) => permit,
+            Err(_) => break,
+        };
+        let (client, token) = (client.clone(), token.clone());
+        tasks.spawn(async move {
+            let sample = bench::sample(&client, url, token.as_deref()).await;
+            drop(permit);
+            sample
+        });
+    }
+    let mut samples = Vec::new();
+    while let Some(res) = tasks.join_next().await {
+        match res {
+            Ok(x) => samples.push(x),
+            Err(err) => eprintln!("error: {err}"),
+        }
+    }
+    let summary = Summary::new(samples, start.elapsed());
+    println!("{}", summary.report());
+    summary.errors == 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn log_lines() {
+        assert_eq!(
+            parse_line("127.0.0.1 - - [2024-05-08T00:39:00Z] \"GET /3d/models/a/b/t.b3dm\" 200 10"),
+            Some(Entry {
+                time: Some(1715128740),
+                uri: "/3d/models/a/b/t.b3dm".to_owned()
+            })
+        );
+        assert_eq!(
+            parse_line("- - - [bad] \"GET /3d/ping HTTP/1.1\" 200 4"),
+            Some(Entry {
+                time: None,
+                uri: "/3d/ping".to_owned()
+            })
+        );
+        assert_eq!(
+            parse_line("- - - [2024-05-08T00:39:00Z] \"PUT /3d/admin\" 201 -"),
+            None
+        );
+        assert_eq!(parse_line("garbage"), None);
+    }
+
+    #[test]
+    fn secrets_dropped() {
+        let mut url = Url::parse("http://localhost/3d/ion/a/b?access_token=s&v=1&share=t").unwrap();
+        without_secrets(&mut url);
+        assert_eq!(url.as_str(), "http://localhost/3d/ion/a/b?v=1");
+        let mut url = Url::parse("http://localhost/3d/models/a/b/?share=t").unwrap();
+        without_secrets(&mut url);
+        assert_eq!(url.as_str(), "http://localhost/3d/models/a/b/");
+    }
+
+    #[test]
+    fn timing() {
+        let entry = |time| Entry {
+            time,
+            uri: "/".to_owned(),
+        };
+        assert_eq!(
+            delay(&entry(Some(110)), Some(100), 1.0),
+            Duration::from_secs(10)
+        );
+        assert_eq!(
+            delay(&entry(Some(110)), Some(100), 2.0),
+            Duration::from_secs(5)
+        );
+        assert_eq!(delay(&entry(None), Some(100), 1.0), Duration::ZERO);
+        assert_eq!(delay(&entry(Some(90)), Some(100), 1.0), Duration::ZERO);
+    }
+}
@@ -0,0 +1,125 @@
+use rocket::http::Status;
+use rocket::serde::json::{self, Json};
+use rocket::serde::Serialize;
+use rocket::State;
+use std::io;
+use std::path::Path;
+
+use crate::admin::Admin;
+use crate::stat::{Metrics, Stat};
+use crate::usage::{this_month, Usage};
+
+/// Usage of the object in the period
+#[derive(Debug, Serialize, PartialEq)]
+pub struct ObjectUsage {
+    pub object: String,
+    pub metrics: Metrics,
+}
+
+/// Monthly usage report for billing
+#[derive(Debug, Serialize, PartialEq)]
+pub struct UsageReport {
+    pub month: String,
+    pub total: Metrics,
+    pub objects: Vec<ObjectUsage>, // sorted by object name
+}
+
+/// Check month format `YYYY-MM`
+fn is_month(month: &str) -> bool {
+    match month.split_once('-') {
+        Some((y, m)) => {
+            y.len() == 4
+                && y.bytes().all(|x| x.is_ascii_digit())
+                && matches!(m.parse::<u8>(), Ok(1..=12))
+                && m.len() == 2
+        }
+        None => false,
+    }
+}
+
+impl UsageReport {
+    /// Build report from per-object usage counters of the month
+    pub async fn build(usage: &Usage, month: &str) -> Self {
+        let mut total = Metrics::default();
+        let mut objects = Vec::new();
+        for (key, metrics) in usage.entries(month).await {
+            if key.client.is_some() {
+                continue;
+            }
+            total += metrics;
+            if let Some(object) = key.object {
+                objects.push(ObjectUsage { object, metrics });
+            }
+        }
+        objects.sort_by(|a, b| a.object.cmp(&b.object));
+        UsageReport {
+            month: month.to_owned(),
+            total,
+            objects,
+        }
+    }
+}
+
+/// Write the current month report to the directory
+pub async fn export(usage: &Usage, dir: &Path) -> io::Result<()> {
+    let report = UsageReport::build(usage, &this_month()).await;
+    let data =
+        json::to_string(&report).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    tokio::fs::create_dir_all(dir).await?;
+    let file = dir.join(format!("usage-{}.json", report.month));
+    tokio::fs::write(file, data).await
+}
+
+#[get("/admin/reports/usage?<month>")]
+pub async fn usage(
+    _admin: Admin,
+    month: Option<&str>,
+    stat: &State<Stat>,
+) -> Result<Json<UsageReport>, Status> {
+    let month = match month {
+        Some(x) if is_month(x) => x.to_owned(),
+        Some(_) => return Err(Status::BadRequest),
+        None => this_month(),
+    };
+    Ok(Json(UsageReport::build(stat.usage(), &month).await))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn month() {
+        assert!(is_month("2024-05"));
+        assert!(is_month("2024-12"));
+        assert!(!is_month("2024-13"));
+        assert!(!is_month("2024-5"));
+        assert!(!is_month("24-05"));
+        assert!(!is_month("2024-05-01"));
+    }
+
+    #[tokio::test]
+    async fn report() {
+        let usage = Usage::default();
+        let metrics = Metrics {
+            hits: 1,
+            cached: 0,
+            bytes: 100,
+            ..Default::default()
+        };
+        usage.insert(Some("key"), Some("lake"), metrics).await;
+        usage.insert(None, Some("city"), metrics).await;
+        usage.insert(None, Some("city"), metrics).await;
+        usage.insert(None, None, metrics).await;
+
+        let report = UsageReport::build(&usage, &this_month()).await;
+        assert_eq!(report.total.hits, 4);
+        assert_eq!(report.total.bytes, 400);
+        let objects: Vec<_> = report
+            .objects
+            .iter()
+            .map(|x| (x.object.as_str(), x.metrics.hits))
+            .collect();
+        assert_eq!(objects, [("city", 2), ("lake", 1)]);
+    }
+}
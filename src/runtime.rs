@@ -0,0 +1,53 @@
+use rocket::serde::{Deserialize, Serialize};
+use std::io;
+use std::thread;
+use tokio::runtime::{Builder, Runtime};
+
+/// Tokio runtime thread pools
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct RuntimeConfig {
+    pub workers: usize,      // async worker threads
+    pub max_blocking: usize, // threads for blocking calls, file IO included
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        let cpus = thread::available_parallelism().map_or(1, |x| x.get());
+        RuntimeConfig {
+            workers: cpus,
+            max_blocking: (cpus * 16).max(512), // tokio default is 512
+        }
+    }
+}
+
+impl RuntimeConfig {
+    /// Build multi-thread runtime with the pool sizes
+    pub fn build(&self) -> io::Result<Runtime> {
+        Builder::new_multi_thread()
+            .worker_threads(self.workers.max(1))
+            .max_blocking_threads(self.max_blocking.max(1))
+            .thread_name("rocket-worker-thread")
+            .enable_all()
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pool_sizes() {
+        let config = RuntimeConfig::default();
+        assert!(config.workers >= 1);
+        assert!(config.max_blocking >= 512);
+
+        let config = RuntimeConfig {
+            workers: 3,
+            max_blocking: 0,
+        };
+        let runtime = config.build().unwrap();
+        assert_eq!(runtime.metrics().num_workers(), 3);
+    }
+}
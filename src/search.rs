@@ -0,0 +1,118 @@
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::State;
+use std::sync::Arc;
+
+use crate::access::{AccessKey, AccessMode, ModelAccess, SessionId};
+use crate::registry::{ModelInfo, ModelRegistry};
+use crate::tenant::Tenant;
+use crate::{Config, Model};
+
+// default and max count of models in the result page
+const DEFAULT_LIMIT: usize = 50;
+const MAX_LIMIT: usize = 500;
+
+/// Result page, `limit` models after skipping `offset` accessible ones
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Page {
+    limit: usize,
+    offset: usize,
+}
+
+impl Page {
+    fn new(limit: Option<usize>, offset: Option<usize>) -> Self {
+        Page {
+            limit: limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT),
+            offset: offset.unwrap_or(0),
+        }
+    }
+}
+
+/// Page of the tenant storage models matching the query, filtered by user access rights
+async fn accessible(
+    query: Option<&str>,
+    page: Page,
+    session_id: SessionId,
+    tenant: &Tenant,
+    config: &Config<'_>,
+    registry: &ModelRegistry,
+    access: &ModelAccess,
+) -> Result<Vec<ModelInfo>, Status> {
+    let models = registry.models(&tenant.root).await.map_err(|err| {
+        error!("model registry error: {}", err);
+        Status::InternalServerError
+    })?;
+
+    let mut res = Vec::new();
+    let mut skip = page.offset;
+    for info in models.iter().map(|x| ModelInfo::new(x, config)) {
+        if res.len() == page.limit {
+            break;
+        }
+        if query.is_some_and(|q| !info.matches(q)) {
+            continue;
+        }
+        let model = Model::new(Some(&info.object), Some(&info.model));
+        let key = AccessKey::new(Arc::new(model), session_id.clone()).for_tenant(tenant);
+        if access.check(&key).await == AccessMode::Granted {
+            match skip {
+                0 => res.push(info),
+                _ => skip -= 1,
+            }
+        }
+    }
+    Ok(res)
+}
+
+/// Search models by names and titles, filtered by user access rights,
+/// paged by `limit` and `offset`
+#[get("/models/search?<q>&<limit>&<offset>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn search(
+    q: &str,
+    limit: Option<usize>,
+    offset: Option<usize>,
+    session_id: SessionId,
+    tenant: &Tenant,
+    config: &State<Config<'_>>,
+    registry: &State<ModelRegistry>,
+    access: &State<ModelAccess>,
+) -> Result<Json<Vec<ModelInfo>>, Status> {
+    let query = q.trim().to_lowercase();
+    if query.is_empty() {
+        return Err(Status::BadRequest);
+    }
+    let page = Page::new(limit, offset);
+    accessible(Some(&query), page, session_id, tenant, config, registry, access)
+        .await
+        .map(Json)
+}
+
+/// List models available to the user, paged by `limit` and `offset`
+#[get("/models?<limit>&<offset>")]
+pub async fn models(
+    limit: Option<usize>,
+    offset: Option<usize>,
+    session_id: SessionId,
+    tenant: &Tenant,
+    config: &State<Config<'_>>,
+    registry: &State<ModelRegistry>,
+    access: &State<ModelAccess>,
+) -> Result<Json<Vec<ModelInfo>>, Status> {
+    let page = Page::new(limit, offset);
+    accessible(None, page, session_id, tenant, config, registry, access)
+        .await
+        .map(Json)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn page() {
+        assert_eq!(Page::new(None, None), Page { limit: 50, offset: 0 });
+        assert_eq!(Page::new(Some(10), Some(20)), Page { limit: 10, offset: 20 });
+        assert_eq!(Page::new(Some(10_000), None).limit, MAX_LIMIT);
+    }
+}
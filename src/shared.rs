@@ -0,0 +1,129 @@
+use redis::aio::ConnectionManager;
+use redis::{AsyncCommands, Client, RedisResult};
+use rocket::futures::StreamExt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::OnceCell;
+
+/// Redis keys prefix of access decisions
+const PREFIX: &str = "rtiles:access:";
+
+/// Redis channel of revoked sessions
+const REVOKE_CHANNEL: &str = "rtiles:revoke";
+
+/// Access decisions shared by server replicas in Redis
+#[derive(Clone)]
+pub struct SharedAccess {
+    client: Client,
+    conn: Arc<OnceCell<ConnectionManager>>,
+    ttl: u64, // seconds
+}
+
+impl SharedAccess {
+    pub fn new(url: &str, ttl: u64) -> RedisResult<Self> {
+        Ok(SharedAccess {
+            client: Client::open(url)?,
+            conn: Arc::new(OnceCell::new()),
+            ttl,
+        })
+    }
+
+    /// Shared connection, reconnects automatically after errors
+    async fn conn(&self) -> RedisResult<ConnectionManager> {
+        self.conn
+            .get_or_try_init(|| self.client.get_connection_manager())
+            .await
+            .cloned()
+    }
+
+    /// Redis key of the session decision
+    pub fn key(session: &str, rest: &str) -> String {
+        format!("{}{}:{}", PREFIX, session, rest)
+    }
+
+    /// Get the decision, none if unknown or Redis is unavailable
+    pub async fn get(&self, key: &str) -> Option<bool> {
+        let res: RedisResult<Option<String>> = async { self.conn().await?.get(key).await }.await;
+        match res {
+            Ok(value) => value.map(|x| x == "granted"),
+            Err(err) => {
+                warn!("redis access cache get error: {}", err);
+                None
+            }
+        }
+    }
+
+    /// Store the decision for the cache ttl
+    pub async fn set(&self, key: &str, granted: bool, ttl: Option<Duration>) {
+        let value = if granted { "granted" } else { "denied" };
+        let ttl = ttl.map_or(self.ttl, |x| x.as_secs());
+        if ttl == 0 {
+            // not cacheable decision
+            return;
+        }
+        let res: RedisResult<()> = async {
+            self.conn()
+                .await?
+                .set_ex(key, value, ttl as usize)
+                .await
+        }
+        .await;
+        if let Err(err) = res {
+            warn!("redis access cache set error: {}", err);
+        }
+    }
+
+    /// Remove all decisions of the session and notify other replicas
+    pub async fn revoke(&self, session: &str) -> RedisResult<()> {
+        let mut conn = self.conn().await?;
+        // session is base64url encoded, no glob chars in the pattern
+        let pattern = format!("{}*", Self::key(session, ""));
+        let keys: Vec<String> = {
+            let mut iter = conn.scan_match::<_, String>(&pattern).await?;
+            let mut keys = Vec::new();
+            while let Some(key) = iter.next_item().await {
+                keys.push(key);
+            }
+            keys
+        };
+        if !keys.is_empty() {
+            conn.del::<_, ()>(keys).await?;
+        }
+        conn.publish::<_, _, ()>(REVOKE_CHANNEL, session).await
+    }
+
+    /// Call the handler for sessions revoked by any replica
+    pub fn subscribe(&self, handler: impl Fn(&str) + Send + Sync + 'static) {
+        let client = self.client.clone();
+        tokio::spawn(async move {
+            loop {
+                let res: RedisResult<()> = async {
+                    let mut pubsub = client.get_async_connection().await?.into_pubsub();
+                    pubsub.subscribe(REVOKE_CHANNEL).await?;
+                    let mut messages = pubsub.on_message();
+                    while let Some(msg) = messages.next().await {
+                        let session: String = msg.get_payload()?;
+                        handler(&session);
+                    }
+                    Ok(())
+                }
+                .await;
+                if let Err(err) = res {
+                    warn!("redis revoke subscription error: {}", err);
+                }
+                // reconnect after the connection is lost
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keys() {
+        assert_eq!(SharedAccess::key("c2Vz", "x"), "rtiles:access:c2Vz:x");
+    }
+}
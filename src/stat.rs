@@ -1,33 +1,115 @@
-use std::collections::HashMap;
+use rocket::fairing::AdHoc;
+use rocket::request::{FromRequest, Outcome, Request};
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::BuildHasher;
+use std::convert::Infallible;
 use std::ops::AddAssign;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::task;
-use tokio::sync::{mpsc, RwLock};
-use serde::Serialize;
+use tokio::sync::{mpsc, oneshot};
+use serde::{Deserialize, Serialize};
 
+use crate::events::{Event, Events};
+use crate::usage::Usage;
 use crate::Model;
 
+/// Stat collection params
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct StatConfig {
+    pub capacity: usize, // records queued for the stat task
+    pub blocking: bool,  // wait for a free slot when the queue is full instead of dropping
+}
+
+impl Default for StatConfig {
+    fn default() -> Self {
+        StatConfig {
+            capacity: 500,
+            blocking: false,
+        }
+    }
+}
+
 /// Statistic key
 #[derive(Default, Debug, Clone, Hash, PartialEq, Eq)]
 pub struct StatKey {
-    pub model: Arc<Model>
+    pub model: Arc<Model>,
+    pub class: Option<u8>,      // response status class, 2 for 2xx and so on
 }
 
 impl StatKey {
     pub fn new(object: Option<&str>, name: Option<&str>) -> Self {
         StatKey { 
-            model: Arc::new(Model::new(object, name))
+            model: Arc::new(Model::new(object, name)),
+            class: None,
         }
     }
+
+    /// Key of the metrics for responses with the status class
+    pub fn with_class(mut self, class: Option<u8>) -> Self {
+        self.class = class;
+        self
+    }
+}
+
+/// Parse status class like `4xx` or `4`
+pub fn parse_class(s: &str) -> Option<u8> {
+    let s = s.strip_suffix("xx").unwrap_or(s);
+    match s.parse::<u8>() {
+        Ok(class) if (1..=5).contains(&class) => Some(class),
+        _ => None,
+    }
 }
 
 
 /// Statistic metrics
-#[derive(Default, Debug, Copy, Clone, PartialEq, Serialize)]
+#[derive(Default, Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Metrics {
     pub hits: u64,                // request count
     pub cached: u64,              // cached request count
-    pub bytes: u64                // request bytes     
+    pub bytes: u64,               // request bytes     
+    #[serde(default)]
+    pub time_us: u64,             // cumulative service time, microseconds
+    #[serde(default)]
+    pub timed: u64,               // request count with measured service time
+    #[serde(default)]
+    pub hits_304: u64,            // not modified responses, no bytes counted
+    #[serde(default)]
+    pub hits_head: u64,           // HEAD requests, no bytes counted
+}
+
+impl Metrics {
+    /// Average service time in milliseconds
+    pub fn latency_ms(&self) -> f64 {
+        if self.timed > 0 {
+            self.time_us as f64 / self.timed as f64 / 1000.0
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Metrics with derived values, reported by `/stat`
+#[derive(Debug, Serialize)]
+pub struct Report {
+    #[serde(flatten)]
+    pub metrics: Metrics,
+    pub latency_ms: f64,    // average service time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub children: Option<BTreeMap<String, Report>>, // object models by name
+}
+
+impl From<Metrics> for Report {
+    fn from(metrics: Metrics) -> Self {
+        Report {
+            latency_ms: metrics.latency_ms(),
+            metrics,
+            children: None,
+        }
+    }
 }
 
 impl AddAssign for Metrics {
@@ -37,31 +119,85 @@ impl AddAssign for Metrics {
             hits: self.hits + other.hits,
             cached: self.cached + other.cached,
             bytes: self.bytes + other.bytes,
+            time_us: self.time_us + other.time_us,
+            timed: self.timed + other.timed,
+            hits_304: self.hits_304 + other.hits_304,
+            hits_head: self.hits_head + other.hits_head,
         };
     }
 }
 
+/// Request start time guard, place it first in the handler arguments
+#[derive(Debug, Clone, Copy)]
+pub struct Timer(Instant);
+
+impl Timer {
+    /// Start timer now
+    pub fn now() -> Self {
+        Timer(Instant::now())
+    }
+
+    /// Time passed since the request handling started
+    pub fn elapsed(&self) -> Duration {
+        self.0.elapsed()
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Timer {
+    type Error = Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(*req.local_cache(Timer::now))
+    }
+}
+
 /// Statistic record
 #[derive(Debug)]
 pub struct Record {
     key: StatKey,
+    client: Option<String>,
     metrics: Metrics
 }
 
-/// Async in-memory stitistic table
-struct StatTable(RwLock<HashMap<StatKey, Metrics>>);
+/// Message to the stat task
+enum Message {
+    Record(Record),
+    Flush(oneshot::Sender<()>), // barrier, answered when the records queued before are applied
+}
+
+// stat table shards, keys are spread by hash
+const SHARDS: usize = 16;
+
+/// In-memory stitistic table sharded by key, readers and the writer
+/// lock only the shard of the key for a short time
+struct StatTable {
+    shards: Vec<std::sync::RwLock<HashMap<StatKey, Metrics>>>,
+    hasher: RandomState,
+}
 
 impl StatTable {
     /// Create empty table
     fn new() -> Self {
-        StatTable(RwLock::new(HashMap::new()))
+        StatTable {
+            shards: (0..SHARDS).map(|_| Default::default()).collect(),
+            hasher: RandomState::new(),
+        }
+    }
+
+    fn shard(&self, key: &StatKey) -> &std::sync::RwLock<HashMap<StatKey, Metrics>> {
+        &self.shards[self.hasher.hash_one(key) as usize % SHARDS]
+    }
+
+    /// Add metrics to the key
+    fn add(&self, key: StatKey, metrics: Metrics) {
+        // lock the shard for update
+        let mut map = self.shard(&key).write().unwrap();
+        *map.entry(key).or_default() += metrics;
     }
 
     /// Insert new metrics, calculate aggregates
     async fn insert(&self, rec: Record) {
-        // lock map for update
-        let mut map = self.0.write().await;
-
         if rec.key.model.name.is_some() {
             if rec.key.model.object.is_none() {
                 // illegal model key
@@ -71,10 +207,9 @@ impl StatTable {
             let key = StatKey::new(
                 rec.key.model.object.as_deref(), 
                 None
-            );
+            ).with_class(rec.key.class);
             // update aggregates for all models of a given object
-            let metrics = map.entry(key).or_insert_with(Metrics::default);
-            *metrics += rec.metrics;
+            self.add(key, rec.metrics);
         }
         else {
             // if model was set to None, also set object to None
@@ -83,21 +218,30 @@ impl StatTable {
         }
 
         if rec.key.model.object.is_some() {
-            let key = StatKey::new(None, None);
+            let key = StatKey::new(None, None).with_class(rec.key.class);
             // update aggregates for all models of all objects
-            let metrics = map.entry(key).or_insert_with(Metrics::default);
-            *metrics += rec.metrics;
+            self.add(key, rec.metrics);
         }
 
         // finally update metrics for the given object and model 
-        let metrics = map.entry(rec.key).or_insert_with(Metrics::default);
-        *metrics += rec.metrics;
+        self.add(rec.key, rec.metrics);
+    }
+
+    /// Get all keys with metrics
+    async fn entries(&self) -> Vec<(StatKey, Metrics)> {
+        self.shards
+            .iter()
+            .flat_map(|x| {
+                let map = x.read().unwrap();
+                map.iter().map(|(k, v)| (k.clone(), *v)).collect::<Vec<_>>()
+            })
+            .collect()
     }
 
     /// Get metrics by the key
     async fn get(&self, key: &StatKey) -> Metrics {
-        // shared lock map for read
-        let map = self.0.read().await;
+        // shared lock the shard for read
+        let map = self.shard(key).read().unwrap();
         match map.get(key) {
             Some(metrics) => *metrics,
             None => Metrics::default()
@@ -110,39 +254,159 @@ impl StatTable {
 #[derive(Clone)]
 pub struct Stat {
     all: Arc<StatTable>,
-    tx: mpsc::Sender<Record>,
+    usage: Usage,
+    tx: mpsc::Sender<Message>,
+    blocking: bool,
+    dropped: Arc<AtomicU64>,
 }
 
 impl Stat {
-    pub fn new() -> Self {
+    pub fn new(config: &StatConfig, events: Events, usage: Usage) -> Self {
         let all = Arc::new(StatTable::new());
         let all_rx = Arc::clone(&all);
-        let (tx, mut rx) = mpsc::channel(500);
+        let usage_rx = usage.clone();
+        let (tx, mut rx) = mpsc::channel::<Message>(config.capacity.max(1));
         
         // spawn a detached async task
         // task ended when the channel has been closed 
         task::spawn(async move {
-            while let Some(rec) = rx.recv().await {
+            while let Some(msg) = rx.recv().await {
+                let rec = match msg {
+                    Message::Record(rec) => rec,
+                    Message::Flush(done) => {
+                        // the waiter may be gone, nothing to answer
+                        let _ = done.send(());
+                        continue;
+                    }
+                };
+                // status class breakdown repeats the served requests
+                if rec.key.class.is_none() {
+                    // publish metrics delta
+                    events.send(Event::Stat {
+                        object: rec.key.model.object.clone(),
+                        model: rec.key.model.name.clone(),
+                        metrics: rec.metrics,
+                    });
+                    // update time bucketed usage counters
+                    usage_rx
+                        .insert(rec.client.as_deref(), rec.key.model.object.as_deref(), rec.metrics)
+                        .await;
+                }
                 // insert record to stat table
                 all_rx.insert(rec).await;
             }
             debug!("stat recv task finished");
         });
 
-        Stat { all, tx }
+        Stat {
+            all,
+            usage,
+            tx,
+            blocking: config.blocking,
+            dropped: Arc::default(),
+        }
+    }
+
+    /// Insert metrics, also counted to the client usage if set,
+    /// the record is dropped if the queue is full and blocking is not enabled
+    pub async fn insert(&self, key: StatKey, client: Option<String>, metrics: Metrics) {
+        let rec = Message::Record(Record { key, client, metrics });
+        let res = if self.blocking {
+            self.tx.send(rec).await.map_err(|_| "closed")
+        } else {
+            self.tx.try_send(rec).map_err(|err| match err {
+                mpsc::error::TrySendError::Full(_) => "full",
+                mpsc::error::TrySendError::Closed(_) => "closed",
+            })
+        };
+        if let Err(reason) = res {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            debug!("stat record dropped, queue {reason}");
+        }
+    }
+
+    /// Wait until the records queued before the call are in the table,
+    /// the barrier follows own inserts in the queue, reads see them
+    pub async fn flush(&self) {
+        let (done, applied) = oneshot::channel();
+        // fails only if the stat task is gone, nothing to wait for
+        if self.tx.send(Message::Flush(done)).await.is_ok() {
+            let _ = applied.await;
+        }
+    }
+
+    /// Records dropped on the queue overflow
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Records waiting in the queue for the stat task
+    pub fn queue_depth(&self) -> usize {
+        self.tx.max_capacity() - self.tx.capacity()
     }
 
-    pub async fn insert(&self, key: StatKey, metrics: Metrics) 
-        -> Result<(), mpsc::error::SendError<Record>> {
-        self.tx.send(Record{ key, metrics }).await
+    /// Time bucketed usage counters
+    pub fn usage(&self) -> &Usage {
+        &self.usage
     }
 
     pub async fn get(&self, key: &StatKey) -> Metrics {
-        // move current task to end of the task queue 
-        // to complete async inserts before get back values
-        task::yield_now().await;
+        self.flush().await;
         self.all.get(key).await
     }
+
+    pub async fn entries(&self) -> Vec<(StatKey, Metrics)> {
+        self.flush().await;
+        self.all.entries().await
+    }
+
+    /// Metrics of the object models by name, with the status class of the object key
+    pub async fn children(&self, object: &StatKey) -> BTreeMap<String, Metrics> {
+        self.entries()
+            .await
+            .into_iter()
+            .filter(|(key, _)| {
+                key.class == object.class && key.model.object == object.model.object
+            })
+            .filter_map(|(key, metrics)| Some((key.model.name.clone()?, metrics)))
+            .collect()
+    }
+}
+
+// routes counted in the model stats
+const MODEL_ROUTES: [&str; 2] = ["tileset", "raster_tile"];
+
+/// Is the request routed to the model file serving handler?
+pub fn is_model_route(req: &Request<'_>) -> bool {
+    req.route()
+        .and_then(|r| r.name.as_deref())
+        .is_some_and(|name| MODEL_ROUTES.contains(&name))
+}
+
+/// Count model responses by status class, errors included
+pub fn fairing() -> AdHoc {
+    AdHoc::on_response("stat status classes", |req, res| {
+        Box::pin(async move {
+            let stat = match req.rocket().state::<Stat>() {
+                Some(stat) if is_model_route(req) => stat,
+                _ => return,
+            };
+            let model = match req.guard::<Model>().await {
+                Outcome::Success(model) => model,
+                _ => return,
+            };
+            let key = StatKey {
+                model: Arc::new(model),
+                class: Some((res.status().code / 100) as u8),
+            };
+            // body size is unknown for streamed files, count requests only
+            let metrics = Metrics {
+                hits: 1,
+                ..Default::default()
+            };
+            stat.insert(key, None, metrics).await;
+        })
+    })
 }
 
 
@@ -152,56 +416,56 @@ mod test {
 
     #[tokio::test]
     async fn stat_table() {
-        let metrics = Metrics { hits: 1, cached: 1,  bytes: 1000 };
+        let metrics = Metrics { hits: 1, cached: 1, bytes: 1000, ..Default::default() };
         let stat = StatTable::new();
         let mut key;
 
         // test first model metrics 
         key = StatKey::new(Some("lake"), Some("first"));
-        stat.insert(Record { key: key.clone(), metrics }).await;
-        stat.insert(Record { key: key.clone(), metrics }).await;
+        stat.insert(Record { key: key.clone(), client: None, metrics }).await;
+        stat.insert(Record { key: key.clone(), client: None, metrics }).await;
         let mut res = stat.get(&key).await;
-        assert_eq!(res, Metrics { hits: 2, cached: 2, bytes: 2000 });
+        assert_eq!(res, Metrics { hits: 2, cached: 2, bytes: 2000, ..Default::default() });
 
         // test second model metrics
         key = StatKey::new(Some("lake"), Some("second"));
-        stat.insert(Record { key: key.clone(), metrics }).await;
+        stat.insert(Record { key: key.clone(), client: None, metrics }).await;
         res = stat.get(&key).await;
-        assert_eq!(res, Metrics { hits: 1, cached: 1, bytes: 1000 });
+        assert_eq!(res, Metrics { hits: 1, cached: 1, bytes: 1000, ..Default::default() });
 
         // test metrics for whole object
         key = StatKey::new(Some("lake"), None);
         res = stat.get(&key).await;
-        assert_eq!(res, Metrics { hits: 3, cached: 3, bytes: 3000 });
+        assert_eq!(res, Metrics { hits: 3, cached: 3, bytes: 3000, ..Default::default() });
 
         // test another object metrics 
         key = StatKey::new(Some("land"), Some("first"));
-        stat.insert(Record { key: key.clone(), metrics }).await;
-        stat.insert(Record { key: key.clone(), metrics }).await;
+        stat.insert(Record { key: key.clone(), client: None, metrics }).await;
+        stat.insert(Record { key: key.clone(), client: None, metrics }).await;
         res = stat.get(&key).await;
-        assert_eq!(res, Metrics { hits: 2, cached: 2, bytes: 2000 });
+        assert_eq!(res, Metrics { hits: 2, cached: 2, bytes: 2000, ..Default::default() });
 
         // test metrics for another whole object
         key = StatKey::new(Some("land"), None);
         res = stat.get(&key).await;
-        assert_eq!(res, Metrics { hits: 2, cached: 2, bytes: 2000 });
+        assert_eq!(res, Metrics { hits: 2, cached: 2, bytes: 2000, ..Default::default() });
 
         // test metrics for server
         key = StatKey::default();
         res = stat.get(&key).await;
-        assert_eq!(res, Metrics { hits: 5, cached: 5, bytes: 5000 });
+        assert_eq!(res, Metrics { hits: 5, cached: 5, bytes: 5000, ..Default::default() });
 
         // test illegal object and model key metrics 
         key = StatKey::new(None, Some("first"));
-        stat.insert(Record { key: key.clone(), metrics }).await;
-        stat.insert(Record { key: key.clone(), metrics }).await;
+        stat.insert(Record { key: key.clone(), client: None, metrics }).await;
+        stat.insert(Record { key: key.clone(), client: None, metrics }).await;
         res = stat.get(&key).await;
-        assert_eq!(res, Metrics { hits: 0, cached: 0, bytes: 0 });
+        assert_eq!(res, Metrics { hits: 0, cached: 0, bytes: 0, ..Default::default() });
 
         // again test metrics for server 
         key = StatKey::default();
         res = stat.get(&key).await;
-        assert_eq!(res, Metrics { hits: 5, cached: 5, bytes: 5000 });
+        assert_eq!(res, Metrics { hits: 5, cached: 5, bytes: 5000, ..Default::default() });
     }
 
     #[tokio::test]
@@ -210,18 +474,148 @@ mod test {
             Some("city"),
             Some("block")
         );
-        let metrics = Metrics { hits: 1, cached: 1, bytes: 1000 };
-        let stat = Stat::new();
+        let metrics = Metrics { hits: 1, cached: 1, bytes: 1000, ..Default::default() };
+        let stat = Stat::new(&StatConfig::default(), Events::default(), Usage::default());
 
         for _ in 0..10 {
-            stat.insert(key.clone(), metrics).await.unwrap();
+            stat.insert(key.clone(), None, metrics).await;
         }
         let mut res = stat.get(&key).await;
-        assert_eq!(res, Metrics { hits: 10, cached: 10, bytes: 10000 });
+        assert_eq!(res, Metrics { hits: 10, cached: 10, bytes: 10000, ..Default::default() });
 
         // test metrics for server
         key = StatKey::default();
         res = stat.get(&key).await;
-        assert_eq!(res, Metrics { hits: 10, cached: 10, bytes: 10000 });
+        assert_eq!(res, Metrics { hits: 10, cached: 10, bytes: 10000, ..Default::default() });
+
+        // test models of the object, other classes are not mixed in
+        stat.insert(StatKey::new(Some("city"), Some("park")), None, metrics).await;
+        stat.insert(StatKey::new(Some("city"), Some("park")).with_class(Some(2)), None, metrics).await;
+        stat.insert(StatKey::new(Some("lake"), Some("first")), None, metrics).await;
+        let children = stat.children(&StatKey::new(Some("city"), None)).await;
+        assert_eq!(children.keys().collect::<Vec<_>>(), ["block", "park"]);
+        assert_eq!(children["park"].hits, 1);
+    }
+
+    #[tokio::test]
+    async fn overflow() {
+        let key = StatKey::new(Some("city"), Some("block"));
+        let metrics = Metrics { hits: 1, ..Default::default() };
+
+        // the stat task runs only when the test yields, the queue holds one record
+        let config = StatConfig { capacity: 1, blocking: false };
+        let stat = Stat::new(&config, Events::default(), Usage::default());
+        for _ in 0..3 {
+            stat.insert(key.clone(), None, metrics).await;
+        }
+        assert_eq!(stat.dropped(), 2);
+        assert_eq!(stat.queue_depth(), 1);
+        assert_eq!(stat.get(&key).await.hits, 1);
+        assert_eq!(stat.queue_depth(), 0);
+
+        // blocking mode waits for the stat task
+        let config = StatConfig { capacity: 1, blocking: true };
+        let stat = Stat::new(&config, Events::default(), Usage::default());
+        for _ in 0..3 {
+            stat.insert(key.clone(), None, metrics).await;
+        }
+        assert_eq!(stat.dropped(), 0);
+        assert_eq!(stat.get(&key).await.hits, 3);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn read_your_writes() {
+        let key = StatKey::new(Some("city"), Some("block"));
+        let metrics = Metrics { hits: 1, ..Default::default() };
+        let config = StatConfig { capacity: 10_000, blocking: false };
+        let stat = Stat::new(&config, Events::default(), Usage::default());
+
+        // every reader sees at least its own inserts without waiting on the timing
+        let mut tasks = task::JoinSet::new();
+        for _ in 0..8 {
+            let (stat, key) = (stat.clone(), key.clone());
+            tasks.spawn(async move {
+                for i in 1..=500 {
+                    stat.insert(key.clone(), None, metrics).await;
+                    assert!(stat.get(&key).await.hits >= i);
+                }
+            });
+        }
+        while let Some(res) = tasks.join_next().await {
+            res.unwrap();
+        }
+        assert_eq!(stat.get(&key).await.hits, 4000);
+    }
+
+    // cargo test --release contention -- --ignored --nocapture
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    #[ignore]
+    async fn contention() {
+        let table = Arc::new(StatTable::new());
+        let metrics = Metrics { hits: 1, ..Default::default() };
+        let start = Instant::now();
+        let mut tasks = task::JoinSet::new();
+        // one writer like the stat task, readers like stat and dashboard requests
+        let keys: Arc<Vec<StatKey>> = Arc::new(
+            (0..64).map(|i| StatKey::new(Some("city"), Some(&format!("m{i}")))).collect(),
+        );
+        let (writer, wkeys) = (Arc::clone(&table), Arc::clone(&keys));
+        tasks.spawn(async move {
+            for i in 0..200_000 {
+                let key = wkeys[i % 64].clone();
+                writer.insert(Record { key, client: None, metrics }).await;
+            }
+        });
+        for r in 0..7 {
+            let (table, keys) = (Arc::clone(&table), Arc::clone(&keys));
+            tasks.spawn(async move {
+                for i in 0..200_000 {
+                    table.get(&keys[(i + r) % 64]).await;
+                }
+            });
+        }
+        while let Some(res) = tasks.join_next().await {
+            res.unwrap();
+        }
+        println!("stat table contention: {:?}", start.elapsed());
+        assert_eq!(table.get(&StatKey::default()).await.hits, 200_000);
+    }
+
+    #[tokio::test]
+    async fn status_classes() {
+        let metrics = Metrics { hits: 1, ..Default::default() };
+        let stat = StatTable::new();
+        let key = StatKey::new(Some("lake"), Some("first"));
+        stat.insert(Record { key: key.clone(), client: None, metrics }).await;
+        stat.insert(Record { key: key.clone().with_class(Some(2)), client: None, metrics }).await;
+        stat.insert(Record { key: key.clone().with_class(Some(4)), client: None, metrics }).await;
+        stat.insert(Record { key: key.clone().with_class(Some(4)), client: None, metrics }).await;
+
+        // class counters do not mix with served requests
+        assert_eq!(stat.get(&key).await.hits, 1);
+        assert_eq!(stat.get(&key.clone().with_class(Some(4))).await.hits, 2);
+        // aggregates keep the class
+        let object = StatKey::new(Some("lake"), None);
+        assert_eq!(stat.get(&object.with_class(Some(2))).await.hits, 1);
+        assert_eq!(stat.get(&StatKey::default().with_class(Some(4))).await.hits, 2);
+        assert_eq!(stat.get(&StatKey::default().with_class(Some(5))).await.hits, 0);
+
+        assert_eq!(parse_class("4xx"), Some(4));
+        assert_eq!(parse_class("2"), Some(2));
+        assert_eq!(parse_class("6xx"), None);
+        assert_eq!(parse_class("err"), None);
+    }
+
+    #[test]
+    fn latency() {
+        let mut m = Metrics { hits: 1, time_us: 3000, timed: 1, ..Default::default() };
+        m += Metrics { hits: 1, ..Default::default() };
+        m += Metrics { hits: 1, time_us: 1000, timed: 1, ..Default::default() };
+        assert_eq!(m.latency_ms(), 2.0);
+        assert_eq!(Metrics::default().latency_ms(), 0.0);
+
+        let report = rocket::serde::json::to_value(Report::from(m)).unwrap();
+        assert_eq!(report["hits"], 3);
+        assert_eq!(report["latency_ms"], 2.0);
     }
 }
\ No newline at end of file
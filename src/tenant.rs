@@ -0,0 +1,367 @@
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::ext::IntoOwned;
+use rocket::http::uri::{Absolute, Origin};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::serde::{Deserialize, Serialize};
+use rocket::Data;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::Config;
+
+/// Virtual host params, not set fields fallback to the global config
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct HostConfig {
+    pub root: Option<PathBuf>,                    // storage root
+    pub access_server: Option<Absolute<'static>>, // auth server
+    pub base_path: Option<Origin<'static>>,       // mount point of the routes
+}
+
+/// Virtual hosts keyed by lowercase host name without port
+pub type HostsConfig = HashMap<String, HostConfig>;
+
+/// Legacy base paths like `/city` mapped to objects
+pub type ObjectPathsConfig = HashMap<String, String>;
+
+/// Route prefixes followed by the object segment
+const OBJECT_ROUTES: [&str; 4] = ["models", "raster", "list", "stat"];
+
+/// Get object from the host by pattern like `{object}.tiles.example.com`
+pub fn object_from_host<'h>(pattern: &str, host: &'h str) -> Option<&'h str> {
+    let (prefix, suffix) = pattern.split_once("{object}")?;
+    let object = host.strip_prefix(prefix)?.strip_suffix(suffix)?;
+    if object.is_empty() || object.contains('.') {
+        return None;
+    }
+    Some(object)
+}
+
+/// Insert the object segment to the route path, None if not needed
+fn object_path(path: &str, base: &str, object: &str) -> Option<String> {
+    let rest = path.strip_prefix(base)?.strip_prefix('/')?;
+    let (route, rest) = rest.split_once('/')?;
+    if !OBJECT_ROUTES.contains(&route) || (route == "models" && rest == "search") {
+        return None;
+    }
+    // path already has the object, e.g. from generated documents
+    if rest.split('/').next() == Some(object) {
+        return None;
+    }
+    Some(format!("{}/{}/{}/{}", base, route, object, rest))
+}
+
+/// Map the legacy object path to the model route, longest prefix first
+fn legacy_path(path: &str, paths: &ObjectPathsConfig, mount: &str) -> Option<String> {
+    paths
+        .iter()
+        .filter_map(|(prefix, object)| {
+            let rest = path.strip_prefix(base(prefix))?.strip_prefix('/')?;
+            Some((base(prefix).len(), object, rest))
+        })
+        .filter(|(_, _, rest)| !rest.is_empty())
+        .max_by_key(|(len, _, _)| *len)
+        .map(|(_, object, rest)| format!("{}/models/{}/{}", mount, object, rest))
+}
+
+/// Is the path equal to the base or nested under it?
+fn is_under(path: &str, base: &str) -> bool {
+    path.strip_prefix(base)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Reject empty, duplicate and route shadowing legacy prefixes
+pub fn check_object_paths(config: &Config<'_>) -> Result<(), String> {
+    let mut prefixes: Vec<&str> = Vec::new();
+    for prefix in config.object_paths.keys() {
+        let path = base(prefix);
+        if path.is_empty() {
+            return Err(format!("object_paths: empty prefix `{}`", prefix));
+        }
+        if prefixes.contains(&path) {
+            return Err(format!("object_paths: duplicate prefix `{}`", prefix));
+        }
+        for mount in base_paths(config) {
+            let mount = base(mount.path().as_str());
+            if is_under(mount, path) || (!mount.is_empty() && is_under(path, mount)) {
+                return Err(format!(
+                    "object_paths: prefix `{}` shadows base path `{}`",
+                    prefix, mount
+                ));
+            }
+        }
+        prefixes.push(path);
+    }
+    Ok(())
+}
+
+/// Replace the request path keeping the query
+fn set_path(req: &mut Request<'_>, path: String) {
+    let uri = match req.uri().query() {
+        Some(query) => format!("{}?{}", path, query),
+        None => path,
+    };
+    match Origin::parse_owned(uri) {
+        Ok(uri) => req.set_uri(uri),
+        Err(err) => debug!("object path rewrite error: {}", err),
+    }
+}
+
+/// Rewrite `/models/<model>/..` to `/models/<object>/<model>/..`
+/// when the object is derived from the subdomain
+pub struct SubdomainObject;
+
+#[rocket::async_trait]
+impl Fairing for SubdomainObject {
+    fn info(&self) -> Info {
+        Info {
+            name: "Subdomain object",
+            kind: Kind::Request,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _: &mut Data<'_>) {
+        let config = req.rocket().state::<Config<'_>>().unwrap();
+        let pattern = match config.object_host {
+            Some(ref pattern) => pattern,
+            None => return,
+        };
+        let host = match req.host() {
+            Some(host) => host.domain().as_str().to_lowercase(),
+            None => return,
+        };
+        let object = match object_from_host(pattern, &host) {
+            Some(object) => object,
+            None => return,
+        };
+        let tenant = Tenant::resolve(config, Some(&host));
+        if let Some(path) = object_path(req.uri().path().as_str(), &tenant.base_path, object) {
+            set_path(req, path);
+        }
+    }
+}
+
+/// Rewrite legacy `/<path>/<model>/..` urls to `/models/<object>/<model>/..`
+pub struct LegacyObjectPaths;
+
+#[rocket::async_trait]
+impl Fairing for LegacyObjectPaths {
+    fn info(&self) -> Info {
+        Info {
+            name: "Legacy object paths",
+            kind: Kind::Request,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _: &mut Data<'_>) {
+        let config = req.rocket().state::<Config<'_>>().unwrap();
+        if config.object_paths.is_empty() {
+            return;
+        }
+        let host = req.host().map(|x| x.domain().as_str());
+        let tenant = Tenant::resolve(config, host);
+        let path = legacy_path(
+            req.uri().path().as_str(),
+            &config.object_paths,
+            &tenant.base_path,
+        );
+        if let Some(path) = path {
+            set_path(req, path);
+        }
+    }
+}
+
+/// Normalized base path without trailing slash
+fn base(path: &str) -> &str {
+    path.trim_end_matches('/')
+}
+
+/// All base paths to mount public routes at
+pub fn base_paths(config: &Config<'_>) -> Vec<Origin<'static>> {
+    let mut paths = vec![config.base_path.clone().into_owned()];
+    for host in config.hosts.values() {
+        if let Some(ref path) = host.base_path {
+            if !paths
+                .iter()
+                .any(|x| base(x.path().as_str()) == base(path.path().as_str()))
+            {
+                paths.push(path.clone());
+            }
+        }
+    }
+    paths
+}
+
+/// Storage roots of the server and virtual hosts
+pub fn roots(config: &Config<'_>) -> Vec<PathBuf> {
+    let mut roots = vec![config.storage.root.clone()];
+    for root in config.hosts.values().filter_map(|x| x.root.as_ref()) {
+        if !roots.contains(root) {
+            roots.push(root.clone());
+        }
+    }
+    roots
+}
+
+/// Request tenant resolved by the `Host` header
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tenant {
+    pub host: Option<String>, // virtual host name, none for the global config
+    pub root: PathBuf,
+    pub server: Option<Arc<str>>, // auth server override
+    pub base_path: String,        // without trailing slash
+}
+
+impl Tenant {
+    /// Resolve tenant for the host, fallback to the global config
+    pub fn resolve(config: &Config<'_>, host: Option<&str>) -> Self {
+        let name = host
+            .map(str::to_lowercase)
+            .filter(|x| config.hosts.contains_key(x));
+        let host = name.as_ref().and_then(|x| config.hosts.get(x));
+        let root = host
+            .and_then(|x| x.root.clone())
+            .unwrap_or_else(|| config.storage.root.clone());
+        let server = host
+            .and_then(|x| x.access_server.as_ref())
+            .map(|x| Arc::from(x.to_string()));
+        let base_path = host
+            .and_then(|x| x.base_path.as_ref())
+            .unwrap_or(&config.base_path);
+        Tenant {
+            host: name,
+            root,
+            server,
+            base_path: base(base_path.path().as_str()).to_owned(),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for &'r Tenant {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let tenant = req.local_cache(|| {
+            let config = req.rocket().state::<Config<'_>>().unwrap();
+            let host = req.host().map(|x| x.domain().as_str());
+            Tenant::resolve(config, host)
+        });
+        // routes are mounted at base paths of all tenants, forward foreign ones
+        match req.route() {
+            Some(route) if base(route.uri.base()) != tenant.base_path => Outcome::Forward(()),
+            _ => Outcome::Success(tenant),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn resolve() {
+        let mut config = Config::default();
+        config.hosts.insert(
+            "tver.example.com".to_owned(),
+            HostConfig {
+                root: Some(PathBuf::from("tver")),
+                access_server: Some(uri!("http://auth.tver")),
+                base_path: Some(Origin::path_only("/tiles/")),
+            },
+        );
+        config.hosts.insert(
+            "lake.example.com".to_owned(),
+            HostConfig {
+                base_path: Some(Origin::path_only("/3d")),
+                ..Default::default()
+            },
+        );
+
+        let tenant = Tenant::resolve(&config, Some("TVER.example.com"));
+        assert_eq!(tenant.host.as_deref(), Some("tver.example.com"));
+        assert_eq!(tenant.root, PathBuf::from("tver"));
+        assert_eq!(tenant.server.as_deref(), Some("http://auth.tver"));
+        assert_eq!(tenant.base_path, "/tiles");
+
+        let tenant = Tenant::resolve(&config, Some("other.example.com"));
+        assert_eq!(tenant.host, None);
+        assert_eq!(tenant.root, PathBuf::from("data"));
+        assert_eq!(tenant.server, None);
+        assert_eq!(tenant.base_path, "/3d");
+
+        let paths = base_paths(&config);
+        assert_eq!(paths.len(), 2);
+    }
+
+    #[test]
+    fn subdomain() {
+        let pattern = "{object}.tiles.example.com";
+        assert_eq!(
+            object_from_host(pattern, "tver.tiles.example.com"),
+            Some("tver")
+        );
+        assert_eq!(object_from_host(pattern, "tiles.example.com"), None);
+        assert_eq!(object_from_host(pattern, "a.b.tiles.example.com"), None);
+        assert_eq!(
+            object_from_host("tiles-{object}.example.com", "tiles-lake.example.com"),
+            Some("lake")
+        );
+
+        assert_eq!(
+            object_path("/3d/models/panorama/tileset.json", "/3d", "tver").as_deref(),
+            Some("/3d/models/tver/panorama/tileset.json")
+        );
+        assert_eq!(
+            object_path("/3d/raster/map/1/0/0.png", "/3d", "tver").as_deref(),
+            Some("/3d/raster/tver/map/1/0/0.png")
+        );
+        assert_eq!(
+            object_path("/3d/models/tver/panorama/tileset.json", "/3d", "tver"),
+            None
+        );
+        assert_eq!(object_path("/3d/models/search", "/3d", "tver"), None);
+        assert_eq!(object_path("/3d/ping", "/3d", "tver"), None);
+        assert_eq!(object_path("/other/models/a/b", "/3d", "tver"), None);
+    }
+
+    #[test]
+    fn legacy_paths() {
+        let paths: ObjectPathsConfig = [
+            ("/city/".to_owned(), "city".to_owned()),
+            ("/city/old".to_owned(), "archive".to_owned()),
+        ]
+        .into();
+        assert_eq!(
+            legacy_path("/city/center/tileset.json", &paths, "/3d").as_deref(),
+            Some("/3d/models/city/center/tileset.json")
+        );
+        assert_eq!(
+            legacy_path("/city/old/center/0.b3dm", &paths, "").as_deref(),
+            Some("/models/archive/center/0.b3dm")
+        );
+        assert_eq!(legacy_path("/city", &paths, "/3d"), None);
+        assert_eq!(legacy_path("/city/", &paths, "/3d"), None);
+        assert_eq!(legacy_path("/cityscape/a/b", &paths, "/3d"), None);
+    }
+
+    #[test]
+    fn check_legacy_paths() {
+        let check = |prefixes: &[&str]| {
+            let mut config = Config {
+                base_path: Origin::parse("/3d").unwrap(),
+                ..Default::default()
+            };
+            for (i, prefix) in prefixes.iter().enumerate() {
+                config.object_paths.insert(prefix.to_string(), i.to_string());
+            }
+            check_object_paths(&config)
+        };
+        assert!(check(&["/city", "/city/old", "/3dcity"]).is_ok());
+        assert!(check(&["/"]).is_err());
+        assert!(check(&["/city", "/city/"]).is_err());
+        assert!(check(&["/3d"]).is_err());
+        assert!(check(&["/3d/city"]).is_err());
+    }
+}
@@ -0,0 +1,85 @@
+use moka::future::Cache;
+use rocket::serde::Serialize;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Tileset files statistics
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct TilesetStats {
+    pub files: u64,                        // total file count
+    pub bytes: u64,                        // total file size
+    pub extensions: BTreeMap<String, u64>, // file count per extension
+    pub depth: usize,                      // deepest directory level
+}
+
+impl TilesetStats {
+    /// Walk the directory tree and compute statistics
+    pub async fn compute(root: &Path) -> io::Result<Self> {
+        let mut stats = TilesetStats::default();
+        let mut dirs = vec![(root.to_path_buf(), 0)];
+        while let Some((dir, level)) = dirs.pop() {
+            let mut entries = tokio::fs::read_dir(&dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let meta = entry.metadata().await?;
+                if meta.is_dir() {
+                    dirs.push((entry.path(), level + 1));
+                    continue;
+                }
+                stats.files += 1;
+                stats.bytes += meta.len();
+                stats.depth = stats.depth.max(level);
+                let ext = entry
+                    .path()
+                    .extension()
+                    .map(|x| x.to_string_lossy().to_lowercase())
+                    .unwrap_or_default();
+                *stats.extensions.entry(ext).or_default() += 1;
+            }
+        }
+        Ok(stats)
+    }
+}
+
+/// Cache of computed tileset statistics
+#[derive(Clone)]
+pub struct TilesetStatsCache {
+    cache: Cache<PathBuf, Arc<TilesetStats>>,
+}
+
+impl TilesetStatsCache {
+    pub fn new(ttl: u64) -> Self {
+        let cache = Cache::builder()
+            .max_capacity(10_000)
+            .time_to_live(Duration::from_secs(ttl))
+            .build();
+        TilesetStatsCache { cache }
+    }
+
+    /// Get cached or compute statistics for the directory
+    pub async fn get(&self, root: &Path) -> io::Result<Arc<TilesetStats>> {
+        self.cache
+            .try_get_with(root.to_path_buf(), async {
+                TilesetStats::compute(root).await.map(Arc::new)
+            })
+            .await
+            .map_err(|err| io::Error::new(err.kind(), err.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn compute() {
+        let cache = TilesetStatsCache::new(60);
+        let stats = cache.get(Path::new("src")).await.unwrap();
+        assert!(stats.files > 0);
+        assert_eq!(stats.files, stats.extensions["rs"]);
+        assert_eq!(stats.depth, 0);
+        assert!(cache.get(Path::new("not_exists")).await.is_err());
+    }
+}
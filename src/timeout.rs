@@ -0,0 +1,100 @@
+use rocket::http::Status;
+use rocket::route::{Handler, Outcome};
+use rocket::serde::{Deserialize, Serialize};
+use rocket::{Data, Request, Route};
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::metrics::ServerMetrics;
+
+/// Request handler timeouts, seconds, 0 disables
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct TimeoutConfig {
+    pub tile: u64,  // public routes, includes the auth server wait
+    pub admin: u64, // admin routes, uploads included
+}
+
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        TimeoutConfig {
+            tile: 30,
+            admin: 600, // 10 minutes
+        }
+    }
+}
+
+/// Route handler answering 504 if the inner one is not done in time
+#[derive(Clone)]
+struct Timed {
+    inner: Box<dyn Handler>,
+    timeout: Duration,
+}
+
+#[rocket::async_trait]
+impl Handler for Timed {
+    async fn handle<'r>(&self, req: &'r Request<'_>, data: Data<'r>) -> Outcome<'r> {
+        match tokio::time::timeout(self.timeout, self.inner.handle(req, data)).await {
+            Ok(outcome) => outcome,
+            Err(_) => {
+                let err = Error::Timeout(format!("no response in {:?}", self.timeout));
+                warn!("{} {}: handler timed out", req.method(), req.uri());
+                if let Some(metrics) = req.rocket().state::<ServerMetrics>() {
+                    metrics.error(&err);
+                }
+                Outcome::Failure(Status::GatewayTimeout)
+            }
+        }
+    }
+}
+
+/// Limit handling time of the routes, seconds, 0 keeps them as is
+pub fn with_timeout(routes: Vec<Route>, secs: u64) -> Vec<Route> {
+    if secs == 0 {
+        return routes;
+    }
+    routes
+        .into_iter()
+        .map(|mut route| {
+            route.handler = Box::new(Timed {
+                inner: route.handler,
+                timeout: Duration::from_secs(secs),
+            });
+            route
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rocket::local::asynchronous::Client;
+
+    // rocket declares an unused uri macro reexport per route
+    #[allow(unused_imports)]
+    mod handlers {
+        use super::*;
+
+        #[get("/slow")]
+        pub async fn slow() -> &'static str {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            "done"
+        }
+
+        #[get("/fast")]
+        pub fn fast() -> &'static str {
+            "done"
+        }
+    }
+
+    #[rocket::async_test]
+    async fn timed_out() {
+        let rocket = rocket::build().mount("/", with_timeout(routes![handlers::slow, handlers::fast], 1));
+        let client = Client::tracked(rocket).await.unwrap();
+        assert_eq!(
+            client.get("/slow").dispatch().await.status(),
+            Status::GatewayTimeout
+        );
+        assert_eq!(client.get("/fast").dispatch().await.status(), Status::Ok);
+    }
+}
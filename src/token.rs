@@ -0,0 +1,219 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use rocket::http::{Cookie, CookieJar, SameSite, Status};
+use rocket::serde::json::Json;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::State;
+use sha2::Sha256;
+use time::OffsetDateTime;
+
+use crate::access::{AccessKey, AccessMode, ModelAccess, SessionId};
+use crate::admin::Admin;
+use crate::model::{validate_name, Model};
+use crate::tenant::Tenant;
+use crate::Config;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Signed model token configuration
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct TokenConfig {
+    pub secret: Option<String>, // HMAC-SHA256 key shared with CDN, tokens disabled if not set
+    pub ttl: u64,               // max token lifetime, seconds
+    pub cookie_name: String,
+    pub share_ttl: u64, // default lifetime of share links, seconds
+}
+
+impl Default for TokenConfig {
+    fn default() -> Self {
+        TokenConfig {
+            secret: None,
+            ttl: 60 * 60, // 1 hour
+            cookie_name: "rtiles_token".to_owned(),
+            share_ttl: 48 * 60 * 60, // 48 hours
+        }
+    }
+}
+
+/// Current unix time, seconds
+pub fn now() -> i64 {
+    OffsetDateTime::now_utc().unix_timestamp()
+}
+
+fn signature(secret: &str, payload: &str) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("any key size");
+    mac.update(payload.as_bytes());
+    mac
+}
+
+/// Sign the model access until expiry, token is `<object>/<model>.<expires>.<signature>`
+pub fn sign(secret: &str, model: &str, expires: i64) -> String {
+    let payload = format!("{}.{}", model, expires);
+    let sig = signature(secret, &payload).finalize().into_bytes();
+    format!("{}.{}", payload, URL_SAFE_NO_PAD.encode(sig))
+}
+
+/// Check the token signature, expiry and model, whole object tokens cover all its models
+pub fn verify(secret: &str, token: &str, model: &Model, now: i64) -> bool {
+    let mut parts = token.rsplitn(3, '.');
+    let (sig, expires, scope) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(sig), Some(expires), Some(scope)) => (sig, expires, scope),
+        _ => return false,
+    };
+    let sig = match URL_SAFE_NO_PAD.decode(sig) {
+        Ok(x) => x,
+        Err(_) => return false,
+    };
+    if !matches!(expires.parse::<i64>(), Ok(x) if x > now) {
+        return false;
+    }
+    let object = model.object.as_deref().unwrap_or_default();
+    let in_scope = match scope.split_once('/') {
+        Some((o, name)) => o == object && Some(name) == model.name.as_deref(),
+        None => scope == object,
+    };
+    in_scope
+        && signature(secret, &format!("{}.{}", scope, expires))
+            .verify_slice(&sig)
+            .is_ok()
+}
+
+/// Check model format `<object>/<model>`, both parts are valid storage names
+fn is_model(model: &str) -> bool {
+    match model.split_once('/') {
+        Some((object, name)) => validate_name(object).is_ok() && validate_name(name).is_ok(),
+        None => false,
+    }
+}
+
+/// Unix time of expiry after `ttl` seconds, `None` on overflow
+fn expiry(ttl: u64) -> Option<i64> {
+    i64::try_from(ttl).ok().and_then(|ttl| now().checked_add(ttl))
+}
+
+/// Issued model token
+#[derive(Debug, Serialize)]
+pub struct Token {
+    pub token: String,
+    pub expires: i64, // unix time
+}
+
+/// Issue signed token and cookie for the model to the user with access
+#[post("/auth/token?<model>&<ttl>")]
+pub async fn issue(
+    model: &str,
+    ttl: Option<u64>,
+    session_id: SessionId,
+    tenant: &Tenant,
+    cookies: &CookieJar<'_>,
+    access: &State<ModelAccess>,
+    config: &State<Config<'_>>,
+) -> Result<Json<Token>, Status> {
+    let secret = config.token.secret.as_ref().ok_or(Status::NotFound)?;
+    if !is_model(model) {
+        return Err(Status::BadRequest);
+    }
+    let (object, name) = model.split_once('/').unwrap();
+
+    let key =
+        AccessKey::new(Model::new(Some(object), Some(name)).into(), session_id).for_tenant(tenant);
+    if access.check(&key).await != AccessMode::Granted {
+        return Err(Status::Forbidden);
+    }
+
+    let ttl = ttl.unwrap_or(config.token.ttl).min(config.token.ttl);
+    let expires = expiry(ttl).ok_or(Status::BadRequest)?;
+    let token = sign(secret, model, expires);
+
+    // cookie is sent only with the model requests
+    let cookie = Cookie::build(config.token.cookie_name.clone(), token.clone())
+        .path(format!("{}/models/{}", tenant.base_path, model))
+        .max_age(time::Duration::seconds(ttl as i64)) // fits, checked by expiry
+        .same_site(SameSite::Lax)
+        .http_only(true)
+        .finish();
+    cookies.add(cookie);
+
+    Ok(Json(Token { token, expires }))
+}
+
+/// Minted share link
+#[derive(Debug, Serialize)]
+pub struct ShareLink {
+    pub path: String, // model url path with the `share` query param
+    pub token: String,
+    pub expires: i64, // unix time
+}
+
+/// Mint time-limited link to the model for external users
+#[post("/admin/share?<model>&<ttl>")]
+pub async fn share(
+    _admin: Admin,
+    model: &str,
+    ttl: Option<u64>,
+    tenant: &Tenant,
+    config: &State<Config<'_>>,
+) -> Result<Json<ShareLink>, Status> {
+    let secret = config.token.secret.as_ref().ok_or(Status::NotFound)?;
+    if !is_model(model) {
+        return Err(Status::BadRequest);
+    }
+
+    let expires = expiry(ttl.unwrap_or(config.token.share_ttl)).ok_or(Status::BadRequest)?;
+    let token = sign(secret, model, expires);
+    let path = format!("{}/models/{}/?share={}", tenant.base_path, model, token);
+    Ok(Json(ShareLink {
+        path,
+        token,
+        expires,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn signed() {
+        let model = Model::new(Some("tver"), Some("panorama"));
+        let token = sign("secret", "tver/panorama", 1000);
+        assert!(verify("secret", &token, &model, 999));
+        assert!(!verify("secret", &token, &model, 1000));
+        assert!(!verify("other", &token, &model, 999));
+        assert!(!verify(
+            "secret",
+            &token,
+            &Model::new(Some("tver"), Some("x")),
+            999
+        ));
+        let forged = token.replacen("1000", "2000", 1);
+        assert!(!verify("secret", &forged, &model, 999));
+        assert!(!verify("secret", "garbage", &model, 0));
+
+        assert!(is_model("tver/panorama"));
+        assert!(is_model("tver/pano.rama"));
+        assert!(!is_model("tver/.panorama"));
+        assert!(!is_model("tver/pano?rama"));
+        assert!(!is_model("tver/pano/rama"));
+        assert!(!is_model("tver"));
+        assert!(!is_model("/panorama"));
+
+        let dotted = sign("secret", "tver/pano.rama", 1000);
+        let dotted_model = Model::new(Some("tver"), Some("pano.rama"));
+        assert!(verify("secret", &dotted, &dotted_model, 999));
+
+        assert!(expiry(60).is_some());
+        assert!(expiry(u64::MAX).is_none());
+        assert!(expiry(i64::MAX as u64).is_none());
+
+        let object = sign("secret", "tver", 1000);
+        assert!(verify("secret", &object, &model, 999));
+        assert!(!verify(
+            "secret",
+            &object,
+            &Model::new(Some("msk"), Some("x")),
+            999
+        ));
+    }
+}
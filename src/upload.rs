@@ -0,0 +1,207 @@
+use rocket::data::{ByteUnit, Data};
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::State;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::admin::{model_dir, Admin};
+use crate::cache::FileCache;
+use crate::events::{Event, Events};
+use crate::publish::version_dir;
+use crate::registry::ModelRegistry;
+use crate::tenant::Tenant;
+use crate::Config;
+
+/// Per-object storage quotas for the upload API, Mbytes
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct StorageQuotaConfig {
+    pub default: Option<u64>, // quota of objects not listed, unlimited if not set
+    pub objects: HashMap<String, u64>, // quota by object name
+}
+
+impl StorageQuotaConfig {
+    /// Object quota in bytes
+    pub fn get(&self, object: &str) -> Option<u64> {
+        self.objects
+            .get(object)
+            .or(self.default.as_ref())
+            .map(|x| x * 1024 * 1024)
+    }
+}
+
+/// Bytes allowed to upload to the object, replaced file size is reused
+fn available(quota: Option<u64>, used: u64, replaced: u64) -> u64 {
+    match quota {
+        Some(quota) => (quota + replaced).saturating_sub(used),
+        None => u64::MAX,
+    }
+}
+
+/// Upload model file to the live model or the staged version,
+/// rejected with 507 if the object quota is exceeded
+#[put("/admin/models/<object>/<model>/<path..>?<version>", data = "<data>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn upload(
+    _admin: Admin,
+    object: &str,
+    model: &str,
+    path: PathBuf,
+    version: Option<&str>,
+    data: Data<'_>,
+    tenant: &Tenant,
+    config: &State<Config<'_>>,
+    registry: &State<ModelRegistry>,
+    cache: &State<FileCache>,
+    events: &State<Events>,
+) -> Result<Status, Status> {
+    let root = &tenant.root;
+    let dir = match version {
+        Some(version) => version_dir(root, object, model, version)?,
+        None => model_dir(root, object, model)?,
+    };
+    let file = dir.join(&path);
+    let existing = match tokio::fs::metadata(&file).await {
+        Ok(meta) if meta.is_dir() => return Err(Status::Conflict),
+        Ok(meta) => Some(meta.len()),
+        Err(_) => None,
+    };
+    let replaced = existing.unwrap_or(0);
+    let used = registry.object_bytes(root, object).await.map_err(|err| {
+        error!("storage inventory error: {}", err);
+        Status::InternalServerError
+    })?;
+    let limit = available(config.storage_quota.get(object), used, replaced);
+
+    // write to temporary file, then replace atomically
+    let dir = file.parent().ok_or(Status::BadRequest)?;
+    tokio::fs::create_dir_all(dir).await.map_err(|err| {
+        error!("upload dir error: {}", err);
+        Status::InternalServerError
+    })?;
+    let name = file.file_name().ok_or(Status::BadRequest)?;
+    let tmp = dir.join(format!(".{}.upload", name.to_string_lossy()));
+    let res = data.open(ByteUnit::from(limit)).into_file(&tmp).await;
+    let written = match res {
+        Ok(x) if x.is_complete() => x.n.written,
+        Ok(_) => {
+            let _ = tokio::fs::remove_file(&tmp).await;
+            debug!("storage quota exceeded for object {}", object);
+            return Err(Status::InsufficientStorage);
+        }
+        Err(err) => {
+            let _ = tokio::fs::remove_file(&tmp).await;
+            error!("upload error: {}", err);
+            return Err(Status::InternalServerError);
+        }
+    };
+    tokio::fs::rename(&tmp, &file).await.map_err(|err| {
+        error!("upload rename error: {}", err);
+        Status::InternalServerError
+    })?;
+
+    // staged files are not served until activation, but count against the quota
+    let bytes = written as i64 - replaced as i64;
+    match version {
+        Some(_) => registry.add_staged(root, object, bytes),
+        None => {
+            registry.add(root, object, model, bytes);
+            registry.add_path(root, &file);
+            cache.invalidate(&file).await;
+        }
+    }
+    events.send(Event::ModelUploaded {
+        object: object.to_owned(),
+        model: model.to_owned(),
+        path: path.to_string_lossy().into_owned(),
+        bytes: written,
+    });
+    Ok(match existing {
+        Some(_) => Status::NoContent,
+        None => Status::Created,
+    })
+}
+
+/// Delete model with all files
+#[delete("/admin/models/<object>/<model>")]
+pub async fn delete(
+    _admin: Admin,
+    object: &str,
+    model: &str,
+    tenant: &Tenant,
+    registry: &State<ModelRegistry>,
+    cache: &State<FileCache>,
+    events: &State<Events>,
+) -> Result<Status, Status> {
+    let dir = model_dir(&tenant.root, object, model)?;
+    tokio::fs::remove_dir_all(&dir).await.map_err(|err| {
+        debug!("model delete error: {}", err);
+        Status::NotFound
+    })?;
+
+    registry.remove(&tenant.root, object, model);
+    cache.purge(&dir).await;
+    events.send(Event::ModelDeleted {
+        object: object.to_owned(),
+        model: model.to_owned(),
+    });
+    Ok(Status::NoContent)
+}
+
+/// Object storage usage
+#[derive(Debug, Serialize, PartialEq)]
+pub struct ObjectStorage {
+    pub object: String,
+    pub bytes: u64,
+    pub quota: Option<u64>, // bytes
+}
+
+/// Storage usage and quotas of the tenant objects
+#[get("/admin/storage")]
+pub async fn storage(
+    _admin: Admin,
+    tenant: &Tenant,
+    config: &State<Config<'_>>,
+    registry: &State<ModelRegistry>,
+) -> Result<Json<Vec<ObjectStorage>>, Status> {
+    let models = registry.models(&tenant.root).await.map_err(|err| {
+        error!("storage inventory error: {}", err);
+        Status::InternalServerError
+    })?;
+
+    let mut objects: Vec<ObjectStorage> = Vec::new();
+    for entry in models.iter() {
+        match objects.last_mut() {
+            Some(x) if x.object == entry.object => x.bytes += entry.bytes,
+            _ => objects.push(ObjectStorage {
+                object: entry.object.clone(),
+                bytes: entry.bytes,
+                quota: config.storage_quota.get(&entry.object),
+            }),
+        }
+    }
+    Ok(Json(objects))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn quota() {
+        let config = StorageQuotaConfig {
+            default: Some(1),
+            objects: [("tver".to_owned(), 2)].into(),
+        };
+        assert_eq!(config.get("tver"), Some(2 * 1024 * 1024));
+        assert_eq!(config.get("msk"), Some(1024 * 1024));
+        assert_eq!(StorageQuotaConfig::default().get("msk"), None);
+
+        assert_eq!(available(Some(100), 60, 0), 40);
+        assert_eq!(available(Some(100), 60, 10), 50);
+        assert_eq!(available(Some(100), 120, 0), 0);
+        assert_eq!(available(None, 120, 0), u64::MAX);
+    }
+}
@@ -0,0 +1,240 @@
+use rocket::serde::json;
+use rocket::serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use time::OffsetDateTime;
+use tokio::sync::RwLock;
+use tokio::{fs, task};
+
+use crate::report;
+use crate::stat::Metrics;
+
+/// Usage counters params
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct UsageConfig {
+    pub path: Option<PathBuf>, // file to persist usage counters, in-memory only if not set
+    pub flush_interval: u64,   // seconds between writes to the file
+    pub report_dir: Option<PathBuf>, // export monthly usage reports on every flush
+}
+
+impl Default for UsageConfig {
+    fn default() -> Self {
+        UsageConfig {
+            path: None,
+            flush_interval: 60, // 1 minute
+            report_dir: None,
+        }
+    }
+}
+
+/// Usage bucket key, period is a day `2024-05-17` or a month `2024-05`
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsageKey {
+    pub period: String,
+    pub client: Option<String>,
+    pub object: Option<String>,
+}
+
+/// Persisted usage counter
+#[derive(Debug, Serialize, Deserialize)]
+struct UsageEntry {
+    #[serde(flatten)]
+    key: UsageKey,
+    #[serde(flatten)]
+    metrics: Metrics,
+}
+
+/// Current day period, UTC
+pub fn today() -> String {
+    day(OffsetDateTime::now_utc())
+}
+
+/// Current month period, UTC
+pub fn this_month() -> String {
+    month(OffsetDateTime::now_utc())
+}
+
+fn day(t: OffsetDateTime) -> String {
+    format!("{}-{:02}", month(t), t.day())
+}
+
+fn month(t: OffsetDateTime) -> String {
+    format!("{:04}-{:02}", t.year(), t.month() as u8)
+}
+
+/// Is the period a day, not a month?
+fn is_day(period: &str) -> bool {
+    period.len() == 10
+}
+
+/// Time bucketed usage counters by client and object
+#[derive(Clone, Default)]
+pub struct Usage {
+    table: Arc<RwLock<HashMap<UsageKey, Metrics>>>,
+    path: Option<PathBuf>,
+}
+
+impl Usage {
+    /// Create counters, load saved state and start periodic flush if configured
+    pub fn new(config: &UsageConfig) -> Self {
+        let table = match config.path {
+            Some(ref path) => load(path).unwrap_or_else(|err| {
+                if err.kind() != io::ErrorKind::NotFound {
+                    error!("error load usage from {:?}: {}", path, err);
+                }
+                HashMap::new()
+            }),
+            None => HashMap::new(),
+        };
+        let usage = Usage {
+            table: Arc::new(RwLock::new(table)),
+            path: config.path.clone(),
+        };
+
+        if usage.path.is_some() || config.report_dir.is_some() {
+            let flushed = usage.clone();
+            let report_dir = config.report_dir.clone();
+            let period = Duration::from_secs(config.flush_interval.max(1));
+            task::spawn(async move {
+                let mut interval = tokio::time::interval(period);
+                loop {
+                    interval.tick().await;
+                    flushed
+                        .flush()
+                        .await
+                        .unwrap_or_else(|err| error!("error save usage: {}", err));
+                    if let Some(ref dir) = report_dir {
+                        report::export(&flushed, dir)
+                            .await
+                            .unwrap_or_else(|err| error!("error export usage report: {}", err));
+                    }
+                }
+            });
+        }
+        usage
+    }
+
+    /// Add metrics to the day and month buckets of the client and the object
+    pub async fn insert(&self, client: Option<&str>, object: Option<&str>, metrics: Metrics) {
+        let now = OffsetDateTime::now_utc();
+        let mut map = self.table.write().await;
+        for period in [day(now), month(now)] {
+            if let Some(client) = client {
+                let key = UsageKey {
+                    period: period.clone(),
+                    client: Some(client.to_owned()),
+                    object: None,
+                };
+                *map.entry(key).or_default() += metrics;
+            }
+            let key = UsageKey {
+                period,
+                client: None,
+                object: object.map(str::to_owned),
+            };
+            *map.entry(key).or_default() += metrics;
+        }
+    }
+
+    /// Get metrics of the bucket
+    pub async fn get(&self, key: &UsageKey) -> Metrics {
+        let map = self.table.read().await;
+        map.get(key).copied().unwrap_or_default()
+    }
+
+    /// Get all buckets of the period
+    pub async fn entries(&self, period: &str) -> Vec<(UsageKey, Metrics)> {
+        let map = self.table.read().await;
+        map.iter()
+            .filter(|(k, _)| k.period == period)
+            .map(|(k, v)| (k.clone(), *v))
+            .collect()
+    }
+
+    /// Drop past day buckets and write counters to the file
+    pub async fn flush(&self) -> io::Result<()> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        let today = today();
+        let data = {
+            let mut map = self.table.write().await;
+            map.retain(|k, _| !is_day(&k.period) || k.period == today);
+            let entries: Vec<_> = map
+                .iter()
+                .map(|(key, metrics)| UsageEntry {
+                    key: key.clone(),
+                    metrics: *metrics,
+                })
+                .collect();
+            json::to_string(&entries).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+        };
+        // write to temp file and replace, not to leave truncated file on crash
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, data).await?;
+        fs::rename(&tmp, path).await
+    }
+}
+
+/// Load saved counters
+fn load(path: &Path) -> io::Result<HashMap<UsageKey, Metrics>> {
+    let data = std::fs::read_to_string(path)?;
+    let entries: Vec<UsageEntry> =
+        json::from_str(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(entries.into_iter().map(|x| (x.key, x.metrics)).collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn periods() {
+        // 2024-05-07 23:59 UTC
+        let t = OffsetDateTime::from_unix_timestamp(1715126340).unwrap();
+        assert_eq!(day(t), "2024-05-07");
+        assert_eq!(month(t), "2024-05");
+        assert!(is_day(&day(t)));
+        assert!(!is_day(&month(t)));
+    }
+
+    #[tokio::test]
+    async fn persist() {
+        let path = std::env::temp_dir().join(format!("rtiles-usage-{}.json", std::process::id()));
+        let config = UsageConfig {
+            path: Some(path.clone()),
+            flush_interval: 3600,
+            report_dir: None,
+        };
+        let metrics = Metrics {
+            hits: 1,
+            cached: 0,
+            bytes: 100,
+            ..Default::default()
+        };
+        let usage = Usage::new(&config);
+        usage.insert(Some("key"), Some("city"), metrics).await;
+        usage.insert(Some("key"), Some("lake"), metrics).await;
+        usage.flush().await.unwrap();
+
+        let usage = Usage::new(&config);
+        let client = UsageKey {
+            period: this_month(),
+            client: Some("key".to_owned()),
+            object: None,
+        };
+        let object = UsageKey {
+            period: today(),
+            client: None,
+            object: Some("city".to_owned()),
+        };
+        assert_eq!(usage.get(&client).await.hits, 2);
+        assert_eq!(usage.get(&object).await.bytes, 100);
+        assert_eq!(usage.entries(&this_month()).await.len(), 3);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,89 @@
+use rocket::http::Status;
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use rocket::State;
+use std::path::PathBuf;
+
+use crate::access::AccessKey;
+use crate::cache::{validators, Validators};
+use crate::error::Error;
+use crate::meta::{Meta, MetaCache};
+use crate::tenant::Tenant;
+use crate::Config;
+
+/// Validators of the file without the body, 304 if the client copy is current
+pub struct Validated {
+    meta: Meta,
+    current: bool,
+}
+
+impl<'r> Responder<'r, 'static> for Validated {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        let mut builder = Response::build();
+        if self.current {
+            builder.status(Status::NotModified);
+        }
+        for header in validators(&self.meta) {
+            builder.header(header);
+        }
+        builder.ok()
+    }
+}
+
+/// Is the entity tag of the query param the current one, quotes and weak prefix are optional?
+fn is_current(etag: &str, meta: &Meta) -> bool {
+    let tag = |x: &str| x.trim().trim_start_matches("W/").trim_matches('"').to_owned();
+    tag(etag) == tag(&meta.etag())
+}
+
+/// Revalidation of cached copies for CDN origins, answered from
+/// the metadata only, tile bodies and the file cache are not touched
+#[get("/validate/<_>/<_>/<path..>?<etag>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn validate(
+    key: AccessKey,
+    tenant: &Tenant,
+    path: PathBuf,
+    etag: Option<&str>,
+    validators: Validators<'_>,
+    config: &State<Config<'_>>,
+    metacache: &State<MetaCache>,
+) -> Result<Validated, Error> {
+    config.storage.check_path(&path).map_err(Error::BadRequest)?;
+    let model = &key.model;
+    let dir = tenant.root.join(model.object.as_ref().unwrap());
+    let file = dir.join(model.name.as_ref().unwrap()).join(&path);
+
+    let mut meta = metacache.metadata(&file).await?;
+    if meta.is_dir() {
+        let mut found = None;
+        for name in config.index(model) {
+            if let Ok(m) = metacache.metadata(&file.join(name)).await {
+                found = Some(m);
+                break;
+            }
+        }
+        meta = found.ok_or_else(|| {
+            Error::NotFound(format!("no index file in {}", file.to_string_lossy()))
+        })?;
+    }
+    let current = match etag {
+        Some(etag) => is_current(etag, &meta),
+        None => validators.not_modified(&meta),
+    };
+    Ok(Validated { meta, current })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn current_etag() {
+        let meta = Meta::new(255, None, false);
+        assert!(is_current("\"ff-0\"", &meta));
+        assert!(is_current("W/\"ff-0\"", &meta));
+        assert!(is_current("ff-0", &meta));
+        assert!(!is_current("\"fe-0\"", &meta));
+    }
+}
@@ -0,0 +1,49 @@
+use rocket::http::Accept;
+use std::collections::HashMap;
+
+/// Get model variant directories for capabilities disabled by the client
+/// in the `Accept` header params (e.g. `model/gltf-binary;dracoDecoding=0`)
+pub fn variant_dirs<'a>(
+    accept: Option<&Accept>,
+    variants: &'a HashMap<String, String>,
+) -> Vec<&'a str> {
+    let accept = match accept {
+        Some(accept) if !variants.is_empty() => accept,
+        _ => return Vec::new(),
+    };
+    let mut dirs = Vec::new();
+    for media in accept.media_types() {
+        for (name, value) in media.params() {
+            if value == "0" || value.eq_ignore_ascii_case("false") {
+                if let Some((_, dir)) = variants.iter().find(|(param, _)| name == param.as_str()) {
+                    if !dirs.contains(&dir.as_str()) {
+                        dirs.push(dir.as_str());
+                    }
+                }
+            }
+        }
+    }
+    dirs
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn dirs() {
+        let variants = [
+            ("dracoDecoding".to_owned(), "city_nodraco".to_owned()),
+            ("ktx2".to_owned(), "city_noktx2".to_owned()),
+        ]
+        .into();
+
+        let accept = Accept::from_str("model/gltf-binary;dracoDecoding=0, */*").unwrap();
+        assert_eq!(variant_dirs(Some(&accept), &variants), vec!["city_nodraco"]);
+
+        let accept = Accept::from_str("model/gltf-binary;dracoDecoding=1").unwrap();
+        assert!(variant_dirs(Some(&accept), &variants).is_empty());
+        assert!(variant_dirs(None, &variants).is_empty());
+    }
+}
@@ -0,0 +1,150 @@
+use reqwest::{Client, Url};
+use rocket::serde::json::{self, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use crate::cli::WarmArgs;
+
+// max concurrent requests to the server
+const CONCURRENCY: usize = 8;
+
+/// Collect content urls of the tile and its children down to max depth
+pub fn collect(tile: &Value, base: &Url, depth: u32, max: u32, out: &mut Vec<(Url, u32)>) {
+    let content = tile.get("content");
+    // `url` is used by pre 1.0 tilesets
+    let uri = content
+        .and_then(|x| x.get("uri").or_else(|| x.get("url")))
+        .and_then(Value::as_str);
+    if let Some(url) = uri.and_then(|x| base.join(x).ok()) {
+        out.push((url, depth));
+    }
+    if depth >= max {
+        return;
+    }
+    if let Some(children) = tile.get("children").and_then(Value::as_array) {
+        for child in children {
+            collect(child, base, depth + 1, max, out);
+        }
+    }
+}
+
+/// Request the url, returns response body
+async fn fetch(client: &Client, url: Url, token: Option<&str>) -> Result<Vec<u8>, String> {
+    let mut req = client.get(url.clone());
+    if let Some(token) = token {
+        req = req.query(&[("access_token", token)]);
+    }
+    let res = req.send().await.map_err(|e| e.to_string())?;
+    if !res.status().is_success() {
+        return Err(format!("{}: {}", url, res.status()));
+    }
+    res.bytes()
+        .await
+        .map(|x| x.to_vec())
+        .map_err(|e| format!("{}: {}", url, e))
+}
+
+/// Walk the model tileset through the server, returns true if no errors
+pub async fn run(args: &WarmArgs, server: &str) -> bool {
+    let client = match Client::builder().timeout(Duration::from_secs(30)).build() {
+        Ok(client) => client,
+        Err(err) => {
+            eprintln!("Problem create http client: {err}");
+            return false;
+        }
+    };
+    let root = match Url::parse(&format!(
+        "{}/models/{}/",
+        server.trim_end_matches('/'),
+        args.model
+    )) {
+        Ok(url) => url,
+        Err(err) => {
+            eprintln!("Illegal server url: {err}");
+            return false;
+        }
+    };
+    let token = args.token.as_deref().map(Arc::from);
+    let limit = Arc::new(Semaphore::new(CONCURRENCY));
+
+    // root path serves the directory index tileset
+    let mut queue = vec![(root, 0)];
+    let (mut tiles, mut bytes, mut errors) = (0u64, 0u64, 0u64);
+    while !queue.is_empty() {
+        let mut tasks = JoinSet::new();
+        for (url, depth) in queue.drain(..) {
+            let client = client.clone();
+            let token: Option<Arc<str>> = token.clone();
+            let limit = Arc::clone(&limit);
+            tasks.spawn(async move {
+                let _permit = limit.acquire().await;
+                let res = fetch(&client, url.clone(), token.as_deref()).await;
+                (url, depth, res)
+            });
+        }
+        while let Some(res) = tasks.join_next().await {
+            let (url, depth, body) = match res {
+                Ok((url, depth, Ok(body))) => (url, depth, body),
+                Ok((_, _, Err(err))) => {
+                    eprintln!("error: {err}");
+                    errors += 1;
+                    continue;
+                }
+                Err(err) => {
+                    eprintln!("error: {err}");
+                    errors += 1;
+                    continue;
+                }
+            };
+            tiles += 1;
+            bytes += body.len() as u64;
+            // external tilesets are walked further
+            let is_json = depth == 0 || url.path().ends_with(".json");
+            if let Some(tileset) = is_json
+                .then(|| json::from_slice::<Value>(&body).ok())
+                .flatten()
+            {
+                if let Some(root) = tileset.get("root") {
+                    let mut found = Vec::new();
+                    collect(root, &url, depth, args.depth, &mut found);
+                    queue.extend(found);
+                }
+            }
+        }
+    }
+    println!("warmed {} files, {} bytes, {} errors", tiles, bytes, errors);
+    errors == 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn collect_tiles() {
+        let tileset = json::json!({ "root": {
+            "content": { "uri": "root.b3dm" },
+            "children": [
+                { "content": { "uri": "1/a.b3dm" }, "children": [
+                    { "content": { "uri": "2/a.b3dm" } }
+                ]},
+                { "content": { "url": "ext/tileset.json" } },
+                { "children": [] }
+            ]
+        }});
+        let base = Url::parse("http://localhost/3d/models/city/block/tileset.json").unwrap();
+        let mut out = Vec::new();
+        collect(&tileset["root"], &base, 0, 1, &mut out);
+        let urls: Vec<_> = out.iter().map(|(u, d)| (u.path(), *d)).collect();
+        assert_eq!(
+            urls,
+            [
+                ("/3d/models/city/block/root.b3dm", 0),
+                ("/3d/models/city/block/1/a.b3dm", 1),
+                ("/3d/models/city/block/ext/tileset.json", 1)
+            ]
+        );
+    }
+}
@@ -0,0 +1,132 @@
+use reqwest::Client;
+use rocket::serde::json;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::tokio::sync::broadcast::error::RecvError;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::task;
+
+use crate::events::{Event, Events};
+
+/// Webhook params
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct WebhookConfig {
+    pub url: String,
+    pub events: Vec<String>, // event types to send, all notable events if empty
+    pub retries: u32,        // delivery attempts after the first failure
+    pub min_interval: u64,   // seconds, suppress repeated events of the same type
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        WebhookConfig {
+            url: String::new(),
+            events: Vec::new(),
+            retries: 3,
+            min_interval: 60, // 1 minute
+        }
+    }
+}
+
+impl WebhookConfig {
+    /// Should the event be sent to the hook?
+    fn matches(&self, event: &Event) -> bool {
+        if self.events.is_empty() {
+            event.is_notable()
+        } else {
+            self.events.iter().any(|x| x == event.kind())
+        }
+    }
+}
+
+/// Start delivery tasks for configured webhooks
+pub fn start(hooks: &[WebhookConfig], events: &Events) {
+    if hooks.is_empty() {
+        return;
+    }
+    let client = match Client::builder().timeout(Duration::from_secs(5)).build() {
+        Ok(client) => client,
+        Err(err) => {
+            error!("webhooks disabled, problem create http client: {}", err);
+            return;
+        }
+    };
+    for hook in hooks.iter().cloned() {
+        let client = client.clone();
+        let mut rx = events.subscribe();
+        task::spawn(async move {
+            let mut last: HashMap<&'static str, Instant> = HashMap::new();
+            let min_interval = Duration::from_secs(hook.min_interval);
+            loop {
+                let event = match rx.recv().await {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(n)) => {
+                        debug!("webhook {} skipped {} events", hook.url, n);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                if !hook.matches(&event) {
+                    continue;
+                }
+                // suppress alert storms of the same event type
+                let now = Instant::now();
+                match last.get(event.kind()) {
+                    Some(t) if now.duration_since(*t) < min_interval => continue,
+                    _ => last.insert(event.kind(), now),
+                };
+                deliver(&client, &hook, &event).await;
+            }
+        });
+    }
+}
+
+/// POST event as JSON, retry with exponential backoff
+async fn deliver(client: &Client, hook: &WebhookConfig, event: &Event) {
+    let body = match json::to_string(event) {
+        Ok(body) => body,
+        Err(err) => return error!("webhook event serialization error: {}", err),
+    };
+    let mut delay = Duration::from_secs(1);
+    for attempt in 0..=hook.retries {
+        let req = client
+            .post(&hook.url)
+            .header("Content-Type", "application/json")
+            .body(body.clone());
+        match req.send().await {
+            Ok(res) if res.status().is_success() => return,
+            Ok(res) => error!("webhook {} responded {}", hook.url, res.status()),
+            Err(err) => error!("webhook {} failed: {}", hook.url, err),
+        }
+        if attempt < hook.retries {
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+        }
+    }
+    error!("webhook {} dropped {} event", hook.url, event.kind());
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn matches() {
+        let full = Event::CacheFull {
+            bytes: 95,
+            capacity: 100,
+        };
+        let insert = Event::CacheInsert {
+            path: "a".into(),
+            bytes: 1,
+        };
+        let mut hook = WebhookConfig::default();
+        assert!(hook.matches(&full));
+        assert!(!hook.matches(&insert));
+
+        hook.events = vec!["cache_insert".into()];
+        assert!(!hook.matches(&full));
+        assert!(hook.matches(&insert));
+    }
+}
@@ -0,0 +1,194 @@
+use rocket::http::ContentType;
+use rocket::State;
+use std::fmt::Write;
+use std::ops::RangeInclusive;
+
+use crate::access::AccessKey;
+use crate::config::BaseUrl;
+use crate::raster::{LayerInfo, MbTiles};
+use crate::tenant::Tenant;
+use crate::{Config, Error};
+
+// GoogleMapsCompatible tile matrix set params (EPSG:3857)
+const ORIGIN: f64 = 20037508.3427892;
+const SCALE_DENOMINATOR: f64 = 559082264.0287178;
+
+/// Escape special xml chars
+fn escape(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => res.push_str("&amp;"),
+            '<' => res.push_str("&lt;"),
+            '>' => res.push_str("&gt;"),
+            '"' => res.push_str("&quot;"),
+            '\'' => res.push_str("&apos;"),
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+/// Mime type of the tile format
+fn format_mime(format: &str) -> &'static str {
+    match format {
+        "jpg" | "jpeg" => "image/jpeg",
+        "webp" => "image/webp",
+        "pbf" | "mvt" => "application/x-protobuf",
+        _ => "image/png",
+    }
+}
+
+/// Zoom levels of the layer served by the raster route, none if the metadata is inconsistent
+fn zoom_levels(info: &LayerInfo, max_zoom: u8) -> Option<RangeInclusive<u8>> {
+    // tile addresses are limited to 31 levels, see `TileCoord::parse`
+    let maxzoom = info.maxzoom.min(max_zoom).min(31);
+    (info.minzoom <= maxzoom).then_some(info.minzoom..=maxzoom)
+}
+
+/// Build WMTS capabilities document for the raster layer zoom levels.
+/// Tile rows in the resource url are in XYZ scheme, as required by WMTS
+pub fn capabilities(
+    base_url: &str,
+    object: &str,
+    layer: &str,
+    info: &LayerInfo,
+    zooms: RangeInclusive<u8>,
+) -> String {
+    let id = escape(&format!("{}/{}", object, layer));
+    let title = escape(info.name.as_deref().unwrap_or(layer));
+    let [w, s, e, n] = info.bounds;
+    let template = escape(&format!(
+        "{}/raster/{}/{}/{{TileMatrix}}/{{TileCol}}/{{TileRow}}.{}",
+        base_url, object, layer, info.format
+    ));
+
+    let mut xml = String::new();
+    xml.push_str(concat!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+        "\n",
+        r#"<Capabilities xmlns="http://www.opengis.net/wmts/1.0" "#,
+        r#"xmlns:ows="http://www.opengis.net/ows/1.1" "#,
+        r#"xmlns:xlink="http://www.w3.org/1999/xlink" version="1.0.0">"#,
+        "\n"
+    ));
+    let _ = writeln!(
+        xml,
+        "<ows:ServiceIdentification><ows:Title>{}</ows:Title>\
+         <ows:ServiceType>OGC WMTS</ows:ServiceType>\
+         <ows:ServiceTypeVersion>1.0.0</ows:ServiceTypeVersion>\
+         </ows:ServiceIdentification>",
+        title
+    );
+    xml.push_str("<Contents>\n<Layer>\n");
+    let _ = writeln!(xml, "<ows:Title>{}</ows:Title>", title);
+    if let Some(ref d) = info.description {
+        let _ = writeln!(xml, "<ows:Abstract>{}</ows:Abstract>", escape(d));
+    }
+    let _ = writeln!(
+        xml,
+        "<ows:WGS84BoundingBox><ows:LowerCorner>{} {}</ows:LowerCorner>\
+         <ows:UpperCorner>{} {}</ows:UpperCorner></ows:WGS84BoundingBox>",
+        w, s, e, n
+    );
+    let _ = writeln!(xml, "<ows:Identifier>{}</ows:Identifier>", id);
+    xml.push_str("<Style isDefault=\"true\"><ows:Identifier>default</ows:Identifier></Style>\n");
+    let _ = writeln!(xml, "<Format>{}</Format>", format_mime(&info.format));
+    xml.push_str(
+        "<TileMatrixSetLink><TileMatrixSet>GoogleMapsCompatible</TileMatrixSet></TileMatrixSetLink>\n",
+    );
+    let _ = writeln!(
+        xml,
+        "<ResourceURL format=\"{}\" resourceType=\"tile\" template=\"{}\"/>",
+        format_mime(&info.format),
+        template
+    );
+    xml.push_str("</Layer>\n<TileMatrixSet>\n");
+    xml.push_str("<ows:Identifier>GoogleMapsCompatible</ows:Identifier>\n");
+    xml.push_str("<ows:SupportedCRS>urn:ogc:def:crs:EPSG::3857</ows:SupportedCRS>\n");
+    for z in zooms {
+        let size = 1u64 << z;
+        let _ = writeln!(
+            xml,
+            "<TileMatrix><ows:Identifier>{}</ows:Identifier>\
+             <ScaleDenominator>{}</ScaleDenominator>\
+             <TopLeftCorner>{} {}</TopLeftCorner>\
+             <TileWidth>256</TileWidth><TileHeight>256</TileHeight>\
+             <MatrixWidth>{}</MatrixWidth><MatrixHeight>{}</MatrixHeight></TileMatrix>",
+            z,
+            SCALE_DENOMINATOR / size as f64,
+            -ORIGIN,
+            ORIGIN,
+            size,
+            size
+        );
+    }
+    xml.push_str("</TileMatrixSet>\n</Contents>\n</Capabilities>\n");
+    xml
+}
+
+#[get("/raster/<_>/<_>/WMTSCapabilities.xml")]
+pub async fn get_capabilities(
+    key: AccessKey,
+    base_url: BaseUrl,
+    tenant: &Tenant,
+    config: &State<Config<'_>>,
+    mbtiles: &State<MbTiles>,
+) -> Result<(ContentType, String), Error> {
+    let object = key.model.object.as_ref().unwrap();
+    let layer = key.model.name.as_ref().unwrap();
+
+    let mut path = tenant.root.clone();
+    path.push(object);
+    path.push(layer);
+
+    let info = LayerInfo::load(&path, mbtiles).await?;
+    let zooms = zoom_levels(&info, config.raster.max_zoom).ok_or_else(|| {
+        Error::Internal(format!(
+            "layer {}/{} zoom levels {}-{} out of range",
+            object, layer, info.minzoom, info.maxzoom
+        ))
+    })?;
+    Ok((
+        ContentType::XML,
+        capabilities(&base_url.0, object, layer, &info, zooms),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn capabilities_xml() {
+        let info = LayerInfo {
+            name: Some("Ortho & Co".to_owned()),
+            minzoom: 0,
+            maxzoom: 2,
+            ..Default::default()
+        };
+        let xml = capabilities("http://localhost/3d", "city", "ortho", &info, 0..=2);
+
+        assert!(xml.contains("<ows:Title>Ortho &amp; Co</ows:Title>"));
+        assert!(xml.contains("<ows:Identifier>city/ortho</ows:Identifier>"));
+        assert!(xml.contains(
+            "template=\"http://localhost/3d/raster/city/ortho/{TileMatrix}/{TileCol}/{TileRow}.png\""
+        ));
+        assert_eq!(xml.matches("<TileMatrix>").count(), 3);
+        assert!(xml.contains("<MatrixWidth>4</MatrixWidth>"));
+    }
+
+    #[test]
+    fn zoom_range() {
+        let info = |minzoom, maxzoom| LayerInfo {
+            minzoom,
+            maxzoom,
+            ..Default::default()
+        };
+        assert_eq!(zoom_levels(&info(0, 18), 24), Some(0..=18));
+        assert_eq!(zoom_levels(&info(2, 200), 24), Some(2..=24));
+        assert_eq!(zoom_levels(&info(2, 200), 255), Some(2..=31));
+        assert_eq!(zoom_levels(&info(20, 22), 18), None);
+        assert_eq!(zoom_levels(&info(5, 3), 24), None);
+    }
+}
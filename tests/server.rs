@@ -0,0 +1,445 @@
+use rocket::figment::Figment;
+use rocket::http::uri::{Absolute, Host};
+use rocket::http::{Cookie, Header, Status};
+use rocket::local::asynchronous::Client;
+use rocket::serde::json::Value;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use rtiles::mock_auth::{self, MockPolicy};
+use rtiles::Config;
+
+const TILESET: &str = r#"{"asset":{"version":"1.0"},"geometricError":100,
+"root":{"boundingVolume":{"region":[0,0.5,0.1,0.6,0,100]},"geometricError":10,
+"content":{"uri":"0/0.b3dm"}}}"#;
+
+/// Temporary storage tree removed on drop
+struct Storage(PathBuf);
+
+impl Storage {
+    fn new() -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let root = std::env::temp_dir().join(format!(
+            "rtiles-test-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let model = root.join("tver/panorama");
+        std::fs::create_dir_all(model.join("0")).unwrap();
+        std::fs::write(model.join("tileset.json"), TILESET).unwrap();
+        std::fs::write(model.join("0/0.b3dm"), vec![7u8; 4096]).unwrap();
+        Storage(root)
+    }
+}
+
+impl Drop for Storage {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Local client for the public server with mock auth granting `tver/*`
+async fn client(storage: &Storage) -> Client {
+    client_with(storage, |_| ()).await
+}
+
+/// Local client with the config changed by the test
+async fn client_with(storage: &Storage, setup: impl FnOnce(&mut Config)) -> Client {
+    let policy = MockPolicy::parse("tver/*").unwrap();
+    let addr = mock_auth::spawn(policy, ([127, 0, 0, 1], 0).into())
+        .await
+        .unwrap();
+
+    let mut config = Config::default();
+    config.storage.root = storage.0.clone();
+    config.access.server = Absolute::parse_owned(format!("http://{}", addr)).unwrap();
+    config.admin.token = Some("adm".to_owned());
+    setup(&mut config);
+
+    let figment = Figment::from(rocket::Config::debug_default());
+    let (public, _) = rtiles::build(config, figment).unwrap();
+    Client::tracked(public).await.unwrap()
+}
+
+/// Repeat the check until it holds, background tasks update caches and stat
+async fn eventually<F, Fut>(mut check: F) -> bool
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = bool>,
+{
+    for _ in 0..50 {
+        if check().await {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    false
+}
+
+#[rocket::async_test]
+async fn tileset() {
+    let storage = Storage::new();
+    let client = client(&storage).await;
+
+    let res = client
+        .get("/3d/models/tver/panorama/tileset.json")
+        .cookie(Cookie::new("PHPSESSID", "x"))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::Ok);
+    assert_eq!(
+        res.headers().get_one("Cache-Status"),
+        Some("rtiles; fwd=uri-miss; stored; detail=disk")
+    );
+    assert_eq!(res.into_string().await.unwrap(), TILESET);
+}
+
+#[rocket::async_test]
+async fn cache_hit() {
+    let storage = Storage::new();
+    let client = client(&storage).await;
+
+    let hit = eventually(|| async {
+        let res = client
+            .get("/3d/models/tver/panorama/0/0.b3dm")
+            .cookie(Cookie::new("PHPSESSID", "x"))
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Ok);
+        res.headers().get_one("Cache-Status") == Some("rtiles; hit")
+    })
+    .await;
+    assert!(hit);
+}
+
+#[rocket::async_test]
+async fn forbidden_and_missing() {
+    let storage = Storage::new();
+    let client = client(&storage).await;
+
+    let res = client
+        .get("/3d/models/lake/panorama/tileset.json")
+        .cookie(Cookie::new("PHPSESSID", "x"))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::Forbidden);
+
+    let res = client
+        .get("/3d/models/tver/panorama/1/0.b3dm")
+        .cookie(Cookie::new("PHPSESSID", "x"))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn stat_aggregation() {
+    let storage = Storage::new();
+    let client = client(&storage).await;
+
+    for _ in 0..3 {
+        let res = client
+            .get("/3d/models/tver/panorama/tileset.json")
+            .cookie(Cookie::new("PHPSESSID", "x"))
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Ok);
+    }
+
+    let counted = eventually(|| async {
+        let res = client
+            .get("/3d/stat/tver/panorama")
+            .header(Header::new("Authorization", "Bearer adm"))
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), Status::Ok);
+        let report: Value = res.into_json().await.unwrap();
+        report["hits"] == 3
+    })
+    .await;
+    assert!(counted);
+
+    // object aggregate with the per-model breakdown
+    let res = client
+        .get("/3d/stat/tver?children=1")
+        .header(Header::new("Authorization", "Bearer adm"))
+        .dispatch()
+        .await;
+    let report: Value = res.into_json().await.unwrap();
+    assert_eq!(report["hits"], 3);
+    assert_eq!(report["children"]["panorama"]["hits"], 3);
+}
+
+#[rocket::async_test]
+async fn read_only() {
+    let storage = Storage::new();
+    let client = client_with(&storage, |config| config.read_only = true).await;
+
+    let res = client
+        .put("/3d/admin/models/tver/panorama/0/1.b3dm")
+        .header(Header::new("Authorization", "Bearer adm"))
+        .body([1u8; 16])
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::NotFound);
+    assert!(!storage.0.join("tver/panorama/0/1.b3dm").exists());
+
+    let res = client
+        .get("/3d/admin/storage")
+        .header(Header::new("Authorization", "Bearer adm"))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::Ok);
+}
+
+#[rocket::async_test]
+async fn pin() {
+    let storage = Storage::new();
+    let client = client_with(&storage, |config| config.storage.pin_budget = 1).await;
+
+    let res = client
+        .post("/3d/admin/cache/pin")
+        .header(Header::new("Authorization", "Bearer adm"))
+        .body(r#"{"prefix":"tver/panorama"}"#)
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::Ok);
+    let pinned: Value = res.into_json().await.unwrap();
+    assert_eq!(pinned["pinned"], 2);
+    assert_eq!(pinned["bytes"], TILESET.len() + 4096);
+
+    // served from memory on the first request
+    let res = client
+        .get("/3d/models/tver/panorama/0/0.b3dm")
+        .cookie(Cookie::new("PHPSESSID", "x"))
+        .dispatch()
+        .await;
+    assert_eq!(res.headers().get_one("Cache-Status"), Some("rtiles; hit"));
+
+    let res = client
+        .post("/3d/admin/cache/pin")
+        .header(Header::new("Authorization", "Bearer adm"))
+        .body(r#"{"paths":["../etc/passwd"]}"#)
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::BadRequest);
+}
+
+#[rocket::async_test]
+async fn pin_virtual_host() {
+    let storage = Storage::new();
+    let other = Storage::new();
+    std::fs::remove_file(other.0.join("tver/panorama/0/0.b3dm")).unwrap();
+    let root = other.0.clone();
+    let client = client_with(&storage, |config| {
+        config.storage.pin_budget = 1;
+        let host = format!(r#"{{"root":{:?}}}"#, root);
+        let host = rocket::serde::json::from_str(&host).unwrap();
+        config.hosts.insert("other.local".to_owned(), host);
+    })
+    .await;
+
+    // pinned from the storage root of the virtual host
+    let mut req = client
+        .post("/3d/admin/cache/pin")
+        .header(Header::new("Authorization", "Bearer adm"))
+        .body(r#"{"prefix":"tver/panorama"}"#);
+    req.inner_mut().set_host(Host::parse("other.local").unwrap());
+    let res = req.dispatch().await;
+    assert_eq!(res.status(), Status::Ok);
+    let pinned: Value = res.into_json().await.unwrap();
+    assert_eq!(pinned["pinned"], 1);
+    assert_eq!(pinned["bytes"], TILESET.len());
+}
+
+#[rocket::async_test]
+async fn cache_bypass() {
+    let storage = Storage::new();
+    let client = client_with(&storage, |config| config.storage.no_cache_requests = true).await;
+    let uri = "/3d/models/tver/panorama/0/0.b3dm";
+
+    let hit = eventually(|| async {
+        let res = client.get(uri).cookie(Cookie::new("PHPSESSID", "x")).dispatch().await;
+        res.headers().get_one("Cache-Status") == Some("rtiles; hit")
+    })
+    .await;
+    assert!(hit);
+
+    // changed file is served with the no-cache request
+    std::fs::write(storage.0.join("tver/panorama/0/0.b3dm"), vec![8u8; 16]).unwrap();
+    let res = client
+        .get(uri)
+        .cookie(Cookie::new("PHPSESSID", "x"))
+        .header(Header::new("Cache-Control", "no-cache"))
+        .dispatch()
+        .await;
+    assert_eq!(
+        res.headers().get_one("Cache-Status"),
+        Some("rtiles; fwd=request; stored; detail=disk")
+    );
+    assert_eq!(res.into_bytes().await.unwrap(), vec![8u8; 16]);
+
+    // bypass header is ignored without admin token
+    for (auth, status) in [("Bearer x", "rtiles; hit"), ("Bearer adm", "rtiles; fwd=request")] {
+        let refreshed = eventually(|| async {
+            let res = client
+                .get(uri)
+                .cookie(Cookie::new("PHPSESSID", "x"))
+                .header(Header::new("X-Rtiles-Bypass-Cache", "1"))
+                .header(Header::new("Authorization", auth))
+                .dispatch()
+                .await;
+            res.headers()
+                .get_one("Cache-Status")
+                .is_some_and(|x| x.starts_with(status))
+        })
+        .await;
+        assert!(refreshed);
+    }
+}
+
+#[rocket::async_test]
+async fn conditional_stat() {
+    let storage = Storage::new();
+    let client = client(&storage).await;
+    let uri = "/3d/models/tver/panorama/tileset.json";
+
+    let res = client.get(uri).cookie(Cookie::new("PHPSESSID", "x")).dispatch().await;
+    let etag = res.headers().get_one("ETag").unwrap().to_owned();
+    let res = client
+        .get(uri)
+        .cookie(Cookie::new("PHPSESSID", "x"))
+        .header(Header::new("If-None-Match", etag))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::NotModified);
+    let res = client.head(uri).cookie(Cookie::new("PHPSESSID", "x")).dispatch().await;
+    assert_eq!(res.status(), Status::Ok);
+
+    // bytes of the full response only
+    let counted = eventually(|| async {
+        let res = client
+            .get("/3d/stat/tver/panorama")
+            .header(Header::new("Authorization", "Bearer adm"))
+            .dispatch()
+            .await;
+        let report: Value = res.into_json().await.unwrap();
+        report["hits"] == 3
+            && report["hits_304"] == 1
+            && report["hits_head"] == 1
+            && report["bytes"] == TILESET.len()
+    })
+    .await;
+    assert!(counted);
+}
+
+#[rocket::async_test]
+async fn freeze() {
+    let storage = Storage::new();
+    let client = client(&storage).await;
+    let uri = "/3d/models/tver/panorama/0/0.b3dm";
+    let get = || client.get(uri).cookie(Cookie::new("PHPSESSID", "x")).dispatch();
+    let admin = |action| {
+        client
+            .post(format!("/3d/admin/models/tver/panorama/{}", action))
+            .header(Header::new("Authorization", "Bearer adm"))
+            .dispatch()
+    };
+
+    let hit = eventually(|| async {
+        get().await.headers().get_one("Cache-Status") == Some("rtiles; hit")
+    })
+    .await;
+    assert!(hit);
+    let res = admin("freeze").await;
+    assert_eq!(res.status(), Status::Ok);
+    let frozen: Value = res.into_json().await.unwrap();
+    assert_eq!(frozen["entries"], 1);
+    assert_eq!(frozen["bytes"], 4096);
+    assert_eq!(admin("freeze").await.status(), Status::Conflict);
+
+    // dataset update is not visible while frozen
+    std::fs::write(storage.0.join("tver/panorama/0/0.b3dm"), vec![8u8; 16]).unwrap();
+    assert_eq!(get().await.into_bytes().await.unwrap(), vec![7u8; 4096]);
+    let res = client
+        .get("/3d/models/tver/panorama/tileset.json")
+        .cookie(Cookie::new("PHPSESSID", "x"))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::ServiceUnavailable);
+
+    let res = admin("unfreeze").await;
+    assert_eq!(res.status(), Status::Ok);
+    let unfrozen: Value = res.into_json().await.unwrap();
+    assert_eq!(unfrozen["queued"], 1);
+    assert_eq!(get().await.into_bytes().await.unwrap(), vec![8u8; 16]);
+    assert_eq!(admin("unfreeze").await.status(), Status::Conflict);
+}
+
+#[rocket::async_test]
+async fn validate() {
+    let storage = Storage::new();
+    let client = client(&storage).await;
+    let res = client
+        .get("/3d/models/tver/panorama/tileset.json")
+        .cookie(Cookie::new("PHPSESSID", "x"))
+        .dispatch()
+        .await;
+    let etag = res.headers().get_one("ETag").unwrap().to_owned();
+
+    let validate = |uri: String| client.get(uri).cookie(Cookie::new("PHPSESSID", "x")).dispatch();
+    let tag = etag.trim_start_matches("W/").trim_matches('"');
+    let uri = format!("/3d/validate/tver/panorama/tileset.json?etag={}", tag);
+    let res = validate(uri).await;
+    assert_eq!(res.status(), Status::NotModified);
+    let res = validate("/3d/validate/tver/panorama/tileset.json?etag=other".to_owned()).await;
+    assert_eq!(res.status(), Status::Ok);
+    assert_eq!(res.headers().get_one("ETag"), Some(etag.as_str()));
+    assert!(res.into_bytes().await.unwrap_or_default().is_empty());
+
+    // conditional request headers of the CDN, directory index
+    let res = client
+        .get("/3d/validate/tver/panorama/")
+        .cookie(Cookie::new("PHPSESSID", "x"))
+        .header(Header::new("If-None-Match", etag))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::NotModified);
+    let res = validate("/3d/validate/tver/panorama/missing.json".to_owned()).await;
+    assert_eq!(res.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn legacy_object_path() {
+    let storage = Storage::new();
+    let client = client_with(&storage, |config| {
+        config.object_paths.insert("/city".to_owned(), "tver".to_owned());
+    })
+    .await;
+
+    let res = client
+        .get("/city/panorama/tileset.json")
+        .cookie(Cookie::new("PHPSESSID", "x"))
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::Ok);
+    assert_eq!(res.into_string().await.unwrap(), TILESET);
+    let res = client.get("/other/panorama/tileset.json").dispatch().await;
+    assert_eq!(res.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn security_headers() {
+    let storage = Storage::new();
+    let uri = "/3d/models/tver/panorama/tileset.json";
+    let client = client(&storage).await;
+    let res = client.get(uri).cookie(Cookie::new("PHPSESSID", "x")).dispatch().await;
+    assert!(res.headers().get_one("X-Frame-Options").is_some());
+
+    // embedded viewers need responses without frame restrictions
+    let client = client_with(&storage, |config| config.security_headers = false).await;
+    let res = client.get(uri).cookie(Cookie::new("PHPSESSID", "x")).dispatch().await;
+    assert_eq!(res.status(), Status::Ok);
+    assert_eq!(res.headers().get_one("X-Frame-Options"), None);
+}